@@ -13,6 +13,9 @@ if [ -z "$LOCAL_BIN" ]; then
     exit 1
 fi
 
+shift
+BIN_ARGS="$*"
+
 BIN_NAME=$(basename "$LOCAL_BIN")
 REMOTE_DIR="/data/local/tmp"
 REMOTE_PATH="$REMOTE_DIR/$BIN_NAME"
@@ -32,6 +35,12 @@ REMOTE_PATH="$REMOTE_DIR/$BIN_NAME"
 echo "Pushing $LOCAL_BIN to $REMOTE_DIR..."
 adb push "$LOCAL_BIN" "$REMOTE_PATH"
 
+# The library for --launch-mode app-process, built alongside the binary
+LOCAL_LIB="$(dirname "$LOCAL_BIN")/libandroid_egui_ext.so"
+if [ -f "$LOCAL_LIB" ]; then
+    adb push "$LOCAL_LIB" "$REMOTE_DIR/"
+fi
+
 # Run
 ENV_VARS=""
 if [ -n "$RUST_BACKTRACE" ]; then
@@ -43,8 +52,8 @@ fi
 
 if [ "$USE_SU" = "1" ]; then
     echo "Running on device as root (su)..."
-    adb shell "su -c 'chmod +x $REMOTE_PATH && $ENV_VARS $REMOTE_PATH'"
+    adb shell "su -c 'chmod +x $REMOTE_PATH && $ENV_VARS $REMOTE_PATH $BIN_ARGS'"
 else
     echo "Running on device as shell user..."
-    adb shell "chmod +x $REMOTE_PATH && $ENV_VARS $REMOTE_PATH"
+    adb shell "chmod +x $REMOTE_PATH && $ENV_VARS $REMOTE_PATH $BIN_ARGS"
 fi
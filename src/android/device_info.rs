@@ -0,0 +1,239 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+
+use crate::sysprop;
+
+static DEVICE_INFO: OnceLock<DeviceInfo> = OnceLock::new();
+
+/// `ro.build.version.release`, which is not always a plain number: "8.1.0",
+/// "14", "15 QPR1", or a codename on some preview builds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AndroidVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// Whatever follows the numbers, e.g. "QPR1". The whole string if it
+    /// doesn't start with a number.
+    pub suffix: String,
+}
+
+impl AndroidVersion {
+    pub fn parse(release: &str) -> Self {
+        let release = release.trim();
+        let end = release
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(release.len());
+        let (numbers, suffix) = release.split_at(end);
+        let mut numbers = numbers
+            .split('.')
+            .map(|part| part.parse::<u32>().unwrap_or(0));
+        Self {
+            major: numbers.next().unwrap_or(0),
+            minor: numbers.next().unwrap_or(0),
+            patch: numbers.next().unwrap_or(0),
+            suffix: suffix.trim().to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for AndroidVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.major == 0 {
+            return f.write_str(&self.suffix);
+        }
+        write!(f, "{}", self.major)?;
+        if self.minor != 0 || self.patch != 0 {
+            write!(f, ".{}", self.minor)?;
+        }
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        if !self.suffix.is_empty() {
+            write!(f, " {}", self.suffix)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelinuxMode {
+    Enforcing,
+    Permissive,
+    Disabled,
+    /// `/sys/fs/selinux/enforce` exists but can't be read.
+    Unknown,
+}
+
+impl SelinuxMode {
+    fn read() -> Self {
+        match std::fs::read_to_string("/sys/fs/selinux/enforce") {
+            Ok(enforce) if enforce.trim() == "1" => Self::Enforcing,
+            Ok(_) => Self::Permissive,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::Disabled,
+            Err(_) => Self::Unknown,
+        }
+    }
+}
+
+/// What kind of system we run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+    Device,
+    /// The SDK emulator (goldfish/ranchu) or another QEMU-based one.
+    Emulator,
+    /// Waydroid's LXC container on a Linux host.
+    Waydroid,
+}
+
+impl Environment {
+    fn detect() -> Self {
+        let hardware = sysprop::get("ro.hardware").unwrap_or_default();
+        let brand = sysprop::get("ro.product.brand").unwrap_or_default();
+        if hardware.contains("waydroid") || brand.eq_ignore_ascii_case("waydroid") {
+            Self::Waydroid
+        } else if matches!(hardware.as_str(), "goldfish" | "ranchu")
+            || sysprop::get("ro.kernel.qemu").as_deref() == Some("1")
+            || sysprop::get("ro.boot.qemu").as_deref() == Some("1")
+        {
+            Self::Emulator
+        } else {
+            Self::Device
+        }
+    }
+}
+
+/// Build and platform facts, read once from system properties. Use this
+/// instead of reading properties to decide what the platform supports.
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    /// `Build.VERSION.SDK_INT`.
+    pub sdk_int: u32,
+    /// `Build.VERSION.PREVIEW_SDK_INT`: non-zero on preview builds, whose
+    /// APIs go beyond `sdk_int`.
+    pub preview_sdk: u32,
+    pub release: AndroidVersion,
+    /// "REL" on release builds, the next letter's codename on previews.
+    pub codename: String,
+    pub manufacturer: String,
+    pub model: String,
+    /// Supported ABIs, preferred first.
+    pub abis: Vec<String>,
+    /// e.g. "2024-05-05".
+    pub security_patch: String,
+    pub fingerprint: String,
+    pub selinux: SelinuxMode,
+    pub environment: Environment,
+}
+
+impl DeviceInfo {
+    /// The info for this device, read on first use.
+    pub fn current() -> Result<&'static Self> {
+        if let Some(info) = DEVICE_INFO.get() {
+            return Ok(info);
+        }
+        let info = Self::read()?;
+        Ok(DEVICE_INFO.get_or_init(|| info))
+    }
+
+    fn read() -> Result<Self> {
+        let property = |name: &str| sysprop::get(name).unwrap_or_default();
+        let sdk_int = sysprop::get("ro.build.version.sdk")
+            .context("ro.build.version.sdk is not set")?
+            .parse::<u32>()
+            .context("parsing ro.build.version.sdk")?;
+
+        Ok(Self {
+            sdk_int,
+            preview_sdk: property("ro.build.version.preview_sdk")
+                .parse()
+                .unwrap_or(0),
+            release: AndroidVersion::parse(&property("ro.build.version.release")),
+            codename: property("ro.build.version.codename"),
+            manufacturer: property("ro.product.manufacturer"),
+            model: property("ro.product.model"),
+            abis: property("ro.product.cpu.abilist")
+                .split(',')
+                .filter(|abi| !abi.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            security_patch: property("ro.build.version.security_patch"),
+            fingerprint: property("ro.build.fingerprint"),
+            selinux: SelinuxMode::read(),
+            environment: Environment::detect(),
+        })
+    }
+
+    /// Whether APIs of level `api` are available. A preview build reports
+    /// the previous level as `sdk_int` but already has the next one's APIs.
+    pub fn is_at_least(&self, api: u32) -> bool {
+        self.sdk_int >= api || (self.preview_sdk != 0 && self.sdk_int + 1 >= api)
+    }
+
+    pub fn is_preview(&self) -> bool {
+        self.preview_sdk != 0 || (!self.codename.is_empty() && self.codename != "REL")
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Android {} (API {}", self.release, self.sdk_int)?;
+        if self.is_preview() {
+            write!(f, ", preview {} {}", self.codename, self.preview_sdk)?;
+        }
+        write!(
+            f,
+            ") on {} {} [{}], patch {}, SELinux {:?}",
+            self.manufacturer,
+            self.model,
+            self.abis.join(","),
+            self.security_patch,
+            self.selinux
+        )?;
+        if self.environment != Environment::Device {
+            write!(f, ", {:?}", self.environment)?;
+        }
+        write!(f, ", build {}", self.fingerprint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dotted_releases() {
+        let version = AndroidVersion::parse("8.1.0");
+        assert_eq!((version.major, version.minor, version.patch), (8, 1, 0));
+        assert!(version.suffix.is_empty());
+        assert!(version < AndroidVersion::parse("14"));
+    }
+
+    #[test]
+    fn keeps_the_suffix() {
+        let version = AndroidVersion::parse("15 QPR1");
+        assert_eq!((version.major, version.minor, version.patch), (15, 0, 0));
+        assert_eq!(version.suffix, "QPR1");
+    }
+
+    #[test]
+    fn codename_only_release() {
+        let version = AndroidVersion::parse(" Baklava ");
+        assert_eq!(version.major, 0);
+        assert_eq!(version.suffix, "Baklava");
+    }
+
+    #[test]
+    fn displays_without_trailing_zeros() {
+        for (release, shown) in [
+            ("8.1.0", "8.1"),
+            ("8.0.1", "8.0.1"),
+            ("14", "14"),
+            ("15 QPR1", "15 QPR1"),
+            ("Baklava", "Baklava"),
+        ] {
+            assert_eq!(AndroidVersion::parse(release).to_string(), shown);
+        }
+    }
+}
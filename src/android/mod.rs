@@ -1,4 +1,8 @@
+pub mod device_info;
 pub mod runtime;
 mod util;
+mod vm_options;
 
+pub use device_info::DeviceInfo;
 pub use util::*;
+pub use vm_options::JavaVmOptions;
@@ -1,15 +1,21 @@
 use anyhow::{Context, Result};
 use jni::{
     JNIEnv, JavaVM,
-    sys::{JNI_TRUE, JNI_VERSION_1_6, JavaVMInitArgs, jint, jsize},
+    sys::{jint, jsize},
 };
-use std::ffi::{CStr, CString, c_char, c_void};
-use tracing::info;
+use std::ffi::{CStr, c_char, c_void};
+use tracing::{debug, info, warn};
 
 use xdl_rs::Library;
 
+use super::vm_options::JavaVmOptions;
+
 const ANDROID_RUNTIME_DSO: &str = "libandroid_runtime.so";
 
+/// `AndroidRuntime::mJavaVM`, the VM of the process as far as the framework
+/// is concerned.
+const JAVA_VM_SYMBOL: &str = "_ZN7android14AndroidRuntime7mJavaVME";
+
 #[repr(C)]
 #[allow(non_snake_case)]
 pub struct JniInvocationImpl {
@@ -54,8 +60,8 @@ impl std::fmt::Debug for JniInvocationImpl {
 }
 
 type JniInvocationCreate = unsafe extern "C" fn() -> *mut JniInvocationImpl;
-type JniInvocationInit =
-    unsafe extern "C" fn(*mut JniInvocationImpl, *const c_char) -> *mut JniInvocationImpl;
+/// `bool JniInvocationInit(JniInvocationImpl*, const char* library)`.
+type JniInvocationInit = unsafe extern "C" fn(*mut JniInvocationImpl, *const c_char) -> bool;
 type StartReg = unsafe extern "C" fn(JNIEnv) -> jint;
 
 type JNICreateJavaVM = unsafe extern "C" fn(
@@ -64,8 +70,83 @@ type JNICreateJavaVM = unsafe extern "C" fn(
     *mut c_void,
 ) -> jint;
 
+/// `jint JNI_GetCreatedJavaVMs(JavaVM**, jsize, jsize*)`.
+type JNIGetCreatedJavaVMs =
+    unsafe extern "C" fn(*mut *mut jni::sys::JavaVM, jsize, *mut jsize) -> jint;
+
+/// `JniInvocation::JniInvocation()`: constructs the object at `this`.
+type JniInvocationCtor = unsafe extern "C" fn(*mut c_void);
+/// `bool JniInvocation::Init(const char* library)`.
+type JniInvocationClassInit = unsafe extern "C" fn(*mut c_void, *const c_char) -> bool;
+
+/// Runtime library handed to `JniInvocation` by every strategy: null picks
+/// the system default (`persist.sys.dalvik.vm.lib.2`, normally
+/// `libart.so`).
+const DEFAULT_RUNTIME: *const c_char = std::ptr::null();
+
+const NATIVE_HELPER_DSO: &str = "libnativehelper.so";
+const ART_DSO: &str = "libart.so";
+
+/// C++ names of the invocation class: `JniInvocation` up to Android 9,
+/// `JniInvocationImpl` on 10 and 11 (hidden behind the C API).
+const INVOCATION_CLASS_SYMBOLS: [(&str, &str); 2] = [
+    ("_ZN13JniInvocationC1Ev", "_ZN13JniInvocation4InitEPKc"),
+    (
+        "_ZN17JniInvocationImplC1Ev",
+        "_ZN17JniInvocationImpl4InitEPKc",
+    ),
+];
+
+/// Generous upper bound on `sizeof(JniInvocation)`, which is a library
+/// handle, the library name and three function pointers.
+const INVOCATION_CLASS_SIZE: usize = 16;
+
+/// How [`AndroidRuntime::init_invocation`] got hold of the VM entry points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvocationStrategy {
+    /// `JniInvocationCreate`/`JniInvocationInit` exported by
+    /// `libandroid_runtime.so` or `libnativehelper.so`.
+    Exported,
+    /// The C++ `JniInvocation` class exported by `libnativehelper.so`.
+    NativeHelperClass,
+    /// Any of the above found through `.symtab`/minidebuginfo when they are
+    /// not exported.
+    Symtab,
+    /// No `JniInvocation` at all; `JNI_CreateJavaVM` comes straight from
+    /// `libart.so`.
+    LibArt,
+}
+
+impl InvocationStrategy {
+    const ALL: [Self; 4] = [
+        Self::Exported,
+        Self::NativeHelperClass,
+        Self::Symtab,
+        Self::LibArt,
+    ];
+}
+
 pub struct AndroidRuntime {
     handle: Library,
+    // Kept open once used; closing them could unload them.
+    native_helper: Option<Library>,
+    art: Option<Library>,
+    /// `JNI_CreateJavaVM` when it doesn't come from `libandroid_runtime.so`.
+    create_java_vm: Option<JNICreateJavaVM>,
+    /// Whether a `JniInvocation` was constructed, even if its `Init` failed.
+    /// A second one aborts the process, so only [`InvocationStrategy::LibArt`]
+    /// is left after that.
+    invocation_created: bool,
+}
+
+/// Look a symbol up in `.symtab`, which also covers the minidebuginfo of
+/// stripped system libraries.
+unsafe fn get_symtab<T>(library: &Library, symbol: &str) -> Option<T> {
+    unsafe {
+        library
+            .dsym(symbol)
+            .map(|ptr| std::mem::transmute_copy(&ptr))
+    }
 }
 
 impl AndroidRuntime {
@@ -74,62 +155,150 @@ impl AndroidRuntime {
             handle: Library::open(ANDROID_RUNTIME_DSO, xdl_rs::XDL_TRY_FORCE_LOAD)
                 .map_err(|err| anyhow::anyhow!(err))
                 .context("Failed to open libandroid_runtime.so")?,
+            native_helper: None,
+            art: None,
+            create_java_vm: None,
+            invocation_created: false,
         })
     }
 
-    pub fn init_invocation(&self) -> Result<*const JniInvocationImpl> {
-        unsafe {
-            let create = self
-                .handle
-                .get::<JniInvocationCreate>("JniInvocationCreate")
-                .ok_or(anyhow::anyhow!("JniInvocationCreate symbol not found"))?;
+    /// Initialize `JniInvocation`, which makes `JNI_CreateJavaVM` load ART.
+    ///
+    /// Tries each [`InvocationStrategy`] in turn, since OEM builds and older
+    /// releases don't all export the C API.
+    pub fn init_invocation(&mut self) -> Result<InvocationStrategy> {
+        let mut errors = Vec::new();
+        for strategy in InvocationStrategy::ALL {
+            if self.invocation_created && strategy != InvocationStrategy::LibArt {
+                debug!("Skipping {:?}, a JniInvocation already exists", strategy);
+                continue;
+            }
+            let result = match strategy {
+                InvocationStrategy::Exported => self.init_exported(),
+                InvocationStrategy::NativeHelperClass => self.init_native_helper_class(),
+                InvocationStrategy::Symtab => self.init_symtab(),
+                InvocationStrategy::LibArt => self.use_lib_art(),
+            };
+            match result {
+                Ok(()) => {
+                    info!("JNI invocation ready ({:?})", strategy);
+                    return Ok(strategy);
+                }
+                Err(e) => {
+                    debug!("JNI invocation strategy {:?} failed: {:#}", strategy, e);
+                    errors.push(format!("{strategy:?}: {e:#}"));
+                }
+            }
+        }
+        anyhow::bail!("Cannot initialize JNI invocation ({})", errors.join("; "))
+    }
 
-            let init = self
-                .handle
-                .get::<JniInvocationInit>("JniInvocationInit")
-                .ok_or(anyhow::anyhow!("JniInvocationInit symbol not found"))?;
+    fn native_helper(&mut self) -> Result<&Library> {
+        if self.native_helper.is_none() {
+            let library = Library::open(NATIVE_HELPER_DSO, xdl_rs::XDL_TRY_FORCE_LOAD)
+                .map_err(|err| anyhow::anyhow!(err))
+                .context("Failed to open libnativehelper.so")?;
+            self.native_helper = Some(library);
+        }
+        Ok(self.native_helper.as_ref().unwrap())
+    }
 
-            // TODO: Fallback: manually find JniInvocation constructor and init method if symbols not found
+    fn init_exported(&mut self) -> Result<()> {
+        let lookup = |library: &Library| unsafe {
+            Some((
+                library.get::<JniInvocationCreate>("JniInvocationCreate")?,
+                library.get::<JniInvocationInit>("JniInvocationInit")?,
+            ))
+        };
+        let functions = match lookup(&self.handle) {
+            Some(functions) => functions,
+            None => lookup(self.native_helper()?).ok_or(anyhow::anyhow!(
+                "JniInvocationCreate/Init symbols not found"
+            ))?,
+        };
+        unsafe { init_c_api(functions, &mut self.invocation_created) }
+    }
 
-            let invocation = create();
+    fn init_native_helper_class(&mut self) -> Result<()> {
+        let (ctor_name, init_name) = INVOCATION_CLASS_SYMBOLS[0];
+        let library = self.native_helper()?;
+        let functions = unsafe {
+            library
+                .get::<JniInvocationCtor>(ctor_name)
+                .zip(library.get::<JniInvocationClassInit>(init_name))
+                .ok_or(anyhow::anyhow!("JniInvocation class not exported"))?
+        };
+        unsafe { init_class(functions, &mut self.invocation_created) }
+    }
 
-            if invocation.is_null() {
-                anyhow::bail!("JniInvocationCreate returned null");
+    fn init_symtab(&mut self) -> Result<()> {
+        // libandroid_runtime.so's own table is still worth a look.
+        if let Err(e) = self.native_helper() {
+            debug!("{e:#}");
+        }
+        for library in std::iter::once(&self.handle).chain(&self.native_helper) {
+            unsafe {
+                if let (Some(create), Some(init)) = (
+                    get_symtab::<JniInvocationCreate>(library, "JniInvocationCreate"),
+                    get_symtab::<JniInvocationInit>(library, "JniInvocationInit"),
+                ) {
+                    return init_c_api((create, init), &mut self.invocation_created);
+                }
+                for (ctor_name, init_name) in INVOCATION_CLASS_SYMBOLS {
+                    if let (Some(ctor), Some(init)) = (
+                        get_symtab::<JniInvocationCtor>(library, ctor_name),
+                        get_symtab::<JniInvocationClassInit>(library, init_name),
+                    ) {
+                        return init_class((ctor, init), &mut self.invocation_created);
+                    }
+                }
             }
-
-            let lib_name = CString::new(ANDROID_RUNTIME_DSO).unwrap();
-            init(invocation, lib_name.as_ptr());
-
-            Ok(invocation)
         }
+        anyhow::bail!("no JniInvocation symbols in .symtab")
     }
 
-    pub fn create_java_vm(&self) -> Result<JavaVM> {
-        let jni_create_java_vm = unsafe {
-            self.handle
+    fn use_lib_art(&mut self) -> Result<()> {
+        let library = Library::open(ART_DSO, xdl_rs::XDL_TRY_FORCE_LOAD)
+            .map_err(|err| anyhow::anyhow!(err))
+            .context("Failed to open libart.so")?;
+        let create_java_vm = unsafe {
+            library
                 .get::<JNICreateJavaVM>("JNI_CreateJavaVM")
-                .ok_or(anyhow::anyhow!("JNI_CreateJavaVM symbol not found"))?
+                .ok_or(anyhow::anyhow!("JNI_CreateJavaVM not found in libart.so"))?
+        };
+        warn!("Bypassing JniInvocation; libnativehelper's JNI_* wrappers stay unusable");
+        self.create_java_vm = Some(create_java_vm);
+        self.art = Some(library);
+        Ok(())
+    }
+
+    pub fn create_java_vm(&self, options: &JavaVmOptions) -> Result<JavaVM> {
+        let jni_create_java_vm = match self.create_java_vm {
+            Some(create_java_vm) => create_java_vm,
+            None => unsafe {
+                self.handle
+                    .get::<JNICreateJavaVM>("JNI_CreateJavaVM")
+                    .ok_or(anyhow::anyhow!("JNI_CreateJavaVM symbol not found"))?
+            },
         };
 
         info!("JNI_CreateJavaVM found at {:?}", jni_create_java_vm);
-
-        let mut args = JavaVMInitArgs {
-            version: JNI_VERSION_1_6,
-            nOptions: 0,
-            options: std::ptr::null_mut(),
-            ignoreUnrecognized: JNI_TRUE,
+        // Looked up first, so a missing symbol fails before there is a VM.
+        let avm_ptr = unsafe {
+            self.handle
+                .get::<*mut *mut c_void>(JAVA_VM_SYMBOL)
+                .ok_or(anyhow::anyhow!("{JAVA_VM_SYMBOL} symbol not found"))?
         };
 
+        if !options.as_slice().is_empty() {
+            info!("VM options: {}", options.as_slice().join(" "));
+        }
+        let mut args = options.to_init_args()?;
+
         let mut vm_ptr: *mut jni::sys::JavaVM = std::ptr::null_mut();
         let mut env_ptr: *mut jni::sys::JNIEnv = std::ptr::null_mut();
 
-        let status = unsafe {
-            jni_create_java_vm(
-                &mut vm_ptr,
-                &mut env_ptr,
-                &mut args as *mut _ as *mut c_void,
-            )
-        };
+        let status = unsafe { jni_create_java_vm(&mut vm_ptr, &mut env_ptr, args.as_mut_ptr()) };
 
         if status != 0 {
             anyhow::bail!("JNI_CreateJavaVM failed with status: {}", status);
@@ -139,22 +308,54 @@ impl AndroidRuntime {
 
         // Patch AndroidRuntime::mJavaVM
         unsafe {
-            let avm_ptr = self
-                .handle
-                .get::<*mut *mut c_void>("_ZN7android14AndroidRuntime7mJavaVME")
-                .ok_or(anyhow::anyhow!(
-                    "_ZN7android14AndroidRuntime7mJavaVME symbol not found"
-                ))?;
-
             *avm_ptr = vm_ptr as *mut c_void;
-            info!("Patched AndroidRuntime::mJavaVM");
         }
+        info!("Patched AndroidRuntime::mJavaVM");
 
         let vm = unsafe { JavaVM::from_raw(vm_ptr)? };
 
         Ok(vm)
     }
 
+    /// The VM of the process we were loaded into (library mode).
+    ///
+    /// Its framework natives are registered and `AndroidRuntime::mJavaVM` is
+    /// set already, so neither [`Self::start_registration`] nor the patch in
+    /// [`Self::create_java_vm`] apply.
+    pub fn existing_java_vm(&mut self) -> Result<JavaVM> {
+        if self.art.is_none() {
+            self.art = Library::open(ART_DSO, xdl_rs::XDL_TRY_FORCE_LOAD).ok();
+        }
+        // libart answers directly; libnativehelper forwards to whichever
+        // runtime JniInvocation loaded.
+        let get_created_java_vms = unsafe {
+            match self
+                .art
+                .as_ref()
+                .and_then(|art| art.get::<JNIGetCreatedJavaVMs>("JNI_GetCreatedJavaVMs"))
+            {
+                Some(function) => function,
+                None => self
+                    .native_helper()?
+                    .get::<JNIGetCreatedJavaVMs>("JNI_GetCreatedJavaVMs")
+                    .ok_or(anyhow::anyhow!("JNI_GetCreatedJavaVMs symbol not found"))?,
+            }
+        };
+
+        let mut vm_ptr: *mut jni::sys::JavaVM = std::ptr::null_mut();
+        let mut count: jsize = 0;
+        let status = unsafe { get_created_java_vms(&mut vm_ptr, 1, &mut count) };
+        if status != 0 {
+            anyhow::bail!("JNI_GetCreatedJavaVMs failed with status: {}", status);
+        }
+        if count == 0 || vm_ptr.is_null() {
+            anyhow::bail!("No Java VM running in this process");
+        }
+
+        info!("Found running VM at {:?}", vm_ptr);
+        Ok(unsafe { JavaVM::from_raw(vm_ptr)? })
+    }
+
     pub fn start_registration(&self, env: &mut JNIEnv) -> Result<()> {
         let start_reg = unsafe {
             self.handle
@@ -176,3 +377,37 @@ impl AndroidRuntime {
         Ok(())
     }
 }
+
+/// Create and initialize a `JniInvocationImpl` through the C API
+/// (Android 10+) with the default runtime library. It lives for the rest of
+/// the process. `created` is set once it exists.
+unsafe fn init_c_api(
+    (create, init): (JniInvocationCreate, JniInvocationInit),
+    created: &mut bool,
+) -> Result<()> {
+    let invocation = unsafe { create() };
+    *created = true;
+    if invocation.is_null() {
+        anyhow::bail!("JniInvocationCreate returned null");
+    }
+    if !unsafe { init(invocation, DEFAULT_RUNTIME) } {
+        anyhow::bail!("JniInvocationInit failed");
+    }
+    Ok(())
+}
+
+/// Construct the C++ invocation object in leaked memory and `Init` it with
+/// the default runtime library. `created` is set once it exists.
+unsafe fn init_class(
+    (ctor, init): (JniInvocationCtor, JniInvocationClassInit),
+    created: &mut bool,
+) -> Result<()> {
+    let storage = Box::leak(Box::new([0usize; INVOCATION_CLASS_SIZE]));
+    let this = storage.as_mut_ptr() as *mut c_void;
+    unsafe { ctor(this) };
+    *created = true;
+    if !unsafe { init(this, DEFAULT_RUNTIME) } {
+        anyhow::bail!("JniInvocation::Init failed");
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::ffi::{CString, c_void};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use jni::sys::{JNI_FALSE, JNI_TRUE, JNI_VERSION_1_6, JavaVMInitArgs, JavaVMOption, jint};
+
+/// Arguments for `JNI_CreateJavaVM`, e.g.
+///
+/// ```ignore
+/// let options = JavaVmOptions::new()
+///     .option("-Xcheck:jni")
+///     .option("-Djava.class.path=/data/local/tmp/classes.dex");
+/// ```
+///
+/// Defaults to no options and ignoring options ART doesn't know. The VM is
+/// always asked for JNI 1.6.
+#[derive(Debug, Clone)]
+pub struct JavaVmOptions {
+    options: Vec<String>,
+    ignore_unrecognized: bool,
+}
+
+impl Default for JavaVmOptions {
+    fn default() -> Self {
+        Self {
+            options: Vec::new(),
+            ignore_unrecognized: true,
+        }
+    }
+}
+
+impl JavaVmOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// One VM option such as `-Xcheck:jni`, `-Xmx256m` or `-Dkey=value`.
+    pub fn option(mut self, option: impl Into<String>) -> Self {
+        self.options.push(option.into());
+        self
+    }
+
+    pub fn options<I>(mut self, options: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.options.extend(options.into_iter().map(Into::into));
+        self
+    }
+
+    /// Options read from a file, one per line. Blank lines and lines
+    /// starting with `#` are skipped.
+    pub fn options_file(self, path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading VM options from {}", path.display()))?;
+        Ok(self.options(parse_options_file(&text)))
+    }
+
+    /// Turn on CheckJNI, which aborts with a report on JNI misuse.
+    pub fn check_jni(self) -> Self {
+        self.option("-Xcheck:jni")
+    }
+
+    /// Whether ART should skip options it doesn't recognize instead of
+    /// failing to start.
+    pub fn ignore_unrecognized(mut self, ignore: bool) -> Self {
+        self.ignore_unrecognized = ignore;
+        self
+    }
+
+    pub fn as_slice(&self) -> &[String] {
+        &self.options
+    }
+
+    pub(super) fn to_init_args(&self) -> Result<InitArgs> {
+        let strings = self
+            .options
+            .iter()
+            .map(|option| {
+                CString::new(option.as_str())
+                    .with_context(|| format!("VM option {option:?} contains a NUL byte"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut options = strings
+            .iter()
+            .map(|option| JavaVMOption {
+                optionString: option.as_ptr() as *mut _,
+                extraInfo: std::ptr::null_mut(),
+            })
+            .collect::<Vec<_>>();
+        let args = JavaVMInitArgs {
+            version: JNI_VERSION_1_6,
+            nOptions: options.len() as jint,
+            options: options.as_mut_ptr(),
+            ignoreUnrecognized: if self.ignore_unrecognized {
+                JNI_TRUE
+            } else {
+                JNI_FALSE
+            },
+        };
+        Ok(InitArgs {
+            _strings: strings,
+            _options: options,
+            args,
+        })
+    }
+}
+
+/// The options in a `--vm-options-file`: one per line, trimmed, skipping
+/// blank lines and lines starting with `#`.
+fn parse_options_file(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// A `JavaVMInitArgs` together with the memory it points to.
+pub(super) struct InitArgs {
+    _strings: Vec<CString>,
+    _options: Vec<JavaVMOption>,
+    args: JavaVMInitArgs,
+}
+
+impl InitArgs {
+    pub fn as_mut_ptr(&mut self) -> *mut c_void {
+        &mut self.args as *mut _ as *mut c_void
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_file_skips_comments_and_blank_lines() {
+        let text = "# heap\n-Xmx256m\n\n   \n  -Dkey=value  \n#-Xcheck:jni\n";
+        assert_eq!(parse_options_file(text), ["-Xmx256m", "-Dkey=value"]);
+    }
+
+    #[test]
+    fn options_file_keeps_hash_inside_an_option() {
+        assert_eq!(parse_options_file("-Dcolor=#fff\r\n"), ["-Dcolor=#fff"]);
+    }
+
+    #[test]
+    fn options_file_may_be_empty() {
+        assert!(parse_options_file("").is_empty());
+        assert!(parse_options_file("# nothing\n\n").is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+//! `app_process` launch mode: rather than creating a VM by patching
+//! `libandroid_runtime.so`, run `/system/bin/app_process` with the embedded
+//! dex on the class path, so the platform starts the runtime the supported
+//! way.
+//!
+//! The main class is a small generated [`ENTRY_CLASS`]: it `System.load`s
+//! `libandroid_egui_ext.so` from [`LIBRARY_ENV`] and passes the command line
+//! to its native `run` (see `library.rs`), which runs the dex `main` and the
+//! overlay on the `app_process` main thread. The dex files are removed once
+//! `app_process` exits, however it exits.
+
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use tracing::info;
+
+use crate::bridge::DEX;
+use crate::config::Config;
+use crate::dex::writer::{ClassBuilder, Code};
+use crate::shutdown::Phase;
+
+const APP_PROCESS: &str = "/system/bin/app_process";
+
+/// Where the dex files are written; readable by `app_process` as shell or
+/// root.
+const DEX_DIR: &str = "/data/local/tmp";
+
+const LIBRARY_NAME: &str = "libandroid_egui_ext.so";
+
+/// Process name shown by `ps` instead of `app_process`.
+const NICE_NAME: &str = "android-egui-ext";
+
+/// `app_process` main class; its native `run` is
+/// `Java_com_example_mylibrary_AppProcessEntry_run` in `library.rs`.
+pub const ENTRY_CLASS: &str = "com.example.mylibrary.AppProcessEntry";
+
+/// Absolute path of the library, for the entry class to load.
+pub const LIBRARY_ENV: &str = "ANDROID_EGUI_EXT_LIBRARY";
+
+/// Run the overlay in an `app_process` child and wait for it to exit. A
+/// failing child's exit code keeps its [`Phase`].
+pub fn run(config: &Config) -> Result<()> {
+    let library = library_path(config).context(Phase::Runtime)?;
+    let dex = DexFiles::write().context(Phase::Runtime)?;
+
+    info!("Starting through {APP_PROCESS}");
+    // The child parses our arguments itself, passed as they are.
+    let status = Command::new(APP_PROCESS)
+        .arg("/system/bin")
+        .arg(format!("--nice-name={NICE_NAME}"))
+        .arg(ENTRY_CLASS)
+        .args(std::env::args_os().skip(1))
+        .env("CLASSPATH", dex.class_path())
+        .env(LIBRARY_ENV, &library)
+        .status()
+        .with_context(|| format!("Failed to start {APP_PROCESS}"))
+        .context(Phase::Runtime)?;
+
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(code) => {
+            let error = anyhow::anyhow!("{APP_PROCESS} exited with status {code}");
+            Err(match Phase::from_exit_code(code) {
+                Some(phase) => error.context(phase),
+                None => error,
+            })
+        }
+        None => Err(anyhow::anyhow!(
+            "{APP_PROCESS} was killed by signal {}",
+            status.signal().unwrap_or_default()
+        )),
+    }
+}
+
+/// `--library`, or the library next to the running binary.
+fn library_path(config: &Config) -> Result<PathBuf> {
+    let path = match &config.library {
+        Some(path) => path.clone(),
+        None => std::env::current_exe()?.with_file_name(LIBRARY_NAME),
+    };
+    // `System.load` only takes absolute paths.
+    path.canonicalize().with_context(|| {
+        format!(
+            "{} not found; push it next to the binary or pass --library",
+            path.display()
+        )
+    })
+}
+
+/// `AppProcessEntry.main(args)`:
+/// `System.load(System.getenv(LIBRARY_ENV)); run(args);`
+fn entry_dex() -> Result<Vec<u8>> {
+    let main = Code::new(2)
+        .const_string(0, LIBRARY_ENV)
+        .invoke_static(
+            "java.lang.System",
+            "getenv",
+            "(Ljava/lang/String;)Ljava/lang/String;",
+            &[0],
+        )?
+        .move_result_object(0)
+        .invoke_static("java.lang.System", "load", "(Ljava/lang/String;)V", &[0])?
+        .invoke_static(ENTRY_CLASS, "run", "([Ljava/lang/String;)V", &[1])?
+        .return_void();
+    ClassBuilder::new(ENTRY_CLASS)
+        .static_method("main", "([Ljava/lang/String;)V", main)?
+        .static_native_method("run", "([Ljava/lang/String;)V")?
+        .to_dex()
+}
+
+/// The embedded dex and the entry class, removed again when dropped.
+struct DexFiles(Vec<PathBuf>);
+
+impl DexFiles {
+    fn write() -> Result<Self> {
+        let mut files = Self(Vec::new());
+        let id = std::process::id();
+        files.add(&format!("{NICE_NAME}-{id}.dex"), DEX)?;
+        files.add(&format!("{NICE_NAME}-{id}-entry.dex"), &entry_dex()?)?;
+        Ok(files)
+    }
+
+    fn add(&mut self, name: &str, contents: &[u8]) -> Result<()> {
+        let path = Path::new(DEX_DIR).join(name);
+        // Tracked first, so a half-written file is removed too.
+        self.0.push(path.clone());
+        std::fs::write(&path, contents).with_context(|| format!("writing {}", path.display()))?;
+        // Newer releases refuse to load writable dex files.
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o444))?;
+        Ok(())
+    }
+
+    fn class_path(&self) -> String {
+        self.0
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+}
+
+impl Drop for DexFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
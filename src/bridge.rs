@@ -1,22 +1,113 @@
 use anyhow::Result;
 use jni::{
     JNIEnv,
-    objects::{JIntArray, JObject, JValue},
+    objects::{GlobalRef, JClass, JIntArray, JLongArray, JObject, JObjectArray, JString, JValue},
 };
 use ndk::native_window::NativeWindow;
 
-use crate::dex::util::inject_dex;
+use crate::android::DeviceInfo;
+use crate::dex::util::{inject_dex, path_class_loader, system_class_loader};
 use crate::jni::jni_result_ext::JniResultExt;
 
+/// Entry class of the embedded dex.
+pub const MAIN_CLASS: &str = "com.example.mylibrary.Main";
+
+/// The Java half of the bridge.
+pub const DEX: &[u8] = include_bytes!("../classes.dex");
+
+/// Holds `com.android.server.display.DisplayControl` (Android 14+).
+const SERVICES_JAR: &str = "/system/framework/services.jar";
+
+/// `PixelFormat.RGBA_8888`.
+const PIXEL_FORMAT_RGBA_8888: i32 = 1;
+
+/// Z-order of overlay layers: above app windows and system bars.
+const OVERLAY_LAYER: i32 = i32::MAX - 1;
+
+/// `LayoutParams.FLAG_NOT_FOCUSABLE | FLAG_NOT_TOUCH_MODAL`: touches outside
+/// the touchable region go to the windows below.
+const INPUT_WINDOW_FLAGS: i32 = 0x8 | 0x20;
+
+/// `LayoutParams.TYPE_APPLICATION_OVERLAY`.
+const TYPE_APPLICATION_OVERLAY: i32 = 2038;
+
+/// `InputConfig.NO_INPUT_CHANNEL | NOT_FOCUSABLE | TRUSTED_OVERLAY`
+/// (Android 13+). Touches inside the region are swallowed; ours come from
+/// `/dev/input`.
+const INPUT_CONFIG: i32 = 0x1 | 0x4 | 0x100;
+
+/// `LayoutParams.INPUT_FEATURE_NO_INPUT_CHANNEL`, before Android 13.
+const INPUT_FEATURE_NO_INPUT_CHANNEL: i32 = 0x2;
+
+/// A positioned SurfaceFlinger layer created by
+/// [`JavaBridge::create_overlay_surface`], used for secondary viewports.
+pub struct OverlaySurface {
+    pub window: NativeWindow,
+    surface: GlobalRef,
+    control: GlobalRef,
+    /// The layer's connection to SurfaceFlinger, killed with it.
+    session: GlobalRef,
+}
+
+/// The full-screen surface from [`JavaBridge::create_native_window`].
+pub struct MainSurface {
+    pub window: NativeWindow,
+    surface: GlobalRef,
+    /// Its layer, if the dex's surface map gave it away.
+    control: Option<GlobalRef>,
+    /// The size it was created with, for [`JavaBridge::recreate_native_window`].
+    size: (i32, i32),
+}
+
+/// One mode of the built-in display (`Display.Mode`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayMode {
+    pub id: i32,
+    /// Physical resolution in pixels.
+    pub width: i32,
+    pub height: i32,
+    pub refresh_rate: f32,
+    /// `Display.HdrCapabilities.HDR_TYPE_*` values.
+    pub hdr_types: Vec<i32>,
+}
+
+impl DisplayMode {
+    pub fn hdr_type_name(hdr_type: i32) -> &'static str {
+        match hdr_type {
+            1 => "Dolby Vision",
+            2 => "HDR10",
+            3 => "HLG",
+            4 => "HDR10+",
+            _ => "unknown HDR",
+        }
+    }
+}
+
+/// Everything the built-in display can do and what it does right now.
+#[derive(Debug, Clone, Default)]
+pub struct DisplayModes {
+    pub modes: Vec<DisplayMode>,
+    /// Id of the active mode.
+    pub active: i32,
+}
+
 pub struct JavaBridge<'a> {
     main_class: jni::objects::JClass<'a>,
 }
 
 impl<'a> JavaBridge<'a> {
     pub fn new(env: &mut JNIEnv<'a>) -> Result<Self> {
-        let dex_bytes = include_bytes!("../classes.dex");
-        let cl = inject_dex(env, dex_bytes)?;
-        let main_class = cl.find_class(env, "com.example.mylibrary.Main")?;
+        let cl = inject_dex(env, DEX)?;
+        let main_class = cl.find_class(env, MAIN_CLASS)?;
+        Ok(Self { main_class })
+    }
+
+    /// Use the dex already on the class path (`app_process` mode) instead of
+    /// injecting a second copy, so static state set up by its `main` is
+    /// shared.
+    pub fn from_class_path(env: &mut JNIEnv<'a>) -> Result<Self> {
+        let cl = system_class_loader(env)?;
+        let main_class = cl.find_class(env, MAIN_CLASS)?;
         Ok(Self { main_class })
     }
 
@@ -47,12 +138,41 @@ impl<'a> JavaBridge<'a> {
         Ok((buf[0], buf[1], buf[2]))
     }
 
+    /// Logical display density (`DisplayMetrics.density`), e.g. `3.0` on an
+    /// xxhdpi phone. Read from `Resources.getSystem()` since the dex does not
+    /// expose it directly.
+    pub fn get_display_density(&self, env: &mut JNIEnv<'a>) -> Result<f32> {
+        let resources = env
+            .call_static_method(
+                "android/content/res/Resources",
+                "getSystem",
+                "()Landroid/content/res/Resources;",
+                &[],
+            )
+            .check_exception(env)?
+            .l()?;
+        let metrics = env
+            .call_method(
+                &resources,
+                "getDisplayMetrics",
+                "()Landroid/util/DisplayMetrics;",
+                &[],
+            )
+            .check_exception(env)?
+            .l()?;
+        let density = env
+            .get_field(&metrics, "density", "F")
+            .check_exception(env)?
+            .f()?;
+        Ok(density)
+    }
+
     pub fn create_native_window(
         &self,
         env: &mut JNIEnv<'a>,
         width: i32,
         height: i32,
-    ) -> Result<NativeWindow> {
+    ) -> Result<MainSurface> {
         let surface = env
             .call_static_method(
                 &self.main_class,
@@ -73,7 +193,1201 @@ impl<'a> JavaBridge<'a> {
                 "Failed to create NativeWindow from surface"
             ))?
         };
-        Ok(window)
+        let control = match self.find_surface_control(env, &surface) {
+            Ok(control) => Some(env.new_global_ref(control)?),
+            Err(e) => {
+                tracing::warn!("{e:#}");
+                None
+            }
+        };
+        Ok(MainSurface {
+            window,
+            surface: env.new_global_ref(surface)?,
+            control,
+            size: (width, height),
+        })
+    }
+
+    /// Whether the Java `Surface` behind `surface` still has a buffer queue
+    /// (`Surface.isValid`).
+    pub fn is_native_window_valid(
+        &self,
+        env: &mut JNIEnv<'a>,
+        surface: &MainSurface,
+    ) -> Result<bool> {
+        let valid = env
+            .call_method(surface.surface.as_obj(), "isValid", "()Z", &[])
+            .check_exception(env)?
+            .z()?;
+        Ok(valid)
+    }
+
+    /// Replace a surface from [`Self::create_native_window`] by a new one of
+    /// the same size, removing the old one once the new one exists. The old
+    /// one's EGL surface must be gone by now.
+    pub fn recreate_native_window(
+        &self,
+        env: &mut JNIEnv<'a>,
+        surface: &mut MainSurface,
+    ) -> Result<()> {
+        let (width, height) = surface.size;
+        let old = std::mem::replace(surface, self.create_native_window(env, width, height)?);
+        if let Err(e) = self.destroy_native_window(env, old) {
+            tracing::debug!("Removing the old native window failed: {e:#}");
+        }
+        Ok(())
+    }
+
+    /// The `SurfaceControl` behind a surface from `createNativeWindow`, looked
+    /// up in the dex's `Main.surfaceControlSurfaceMap`.
+    fn find_surface_control(
+        &self,
+        env: &mut JNIEnv<'a>,
+        surface: &JObject<'a>,
+    ) -> Result<JObject<'a>> {
+        let map = env
+            .get_static_field(
+                &self.main_class,
+                "surfaceControlSurfaceMap",
+                "Ljava/util/Map;",
+            )
+            .check_exception(env)?
+            .l()?;
+        if map.is_null() {
+            anyhow::bail!("Main.surfaceControlSurfaceMap is null");
+        }
+        let entries = env
+            .call_method(&map, "entrySet", "()Ljava/util/Set;", &[])
+            .check_exception(env)?
+            .l()?;
+        let entries: JObjectArray = env
+            .call_method(&entries, "toArray", "()[Ljava/lang/Object;", &[])
+            .check_exception(env)?
+            .l()?
+            .into();
+
+        // Which side is the key varies between dex builds.
+        for i in 0..env.get_array_length(&entries)? {
+            let entry = env
+                .get_object_array_element(&entries, i)
+                .check_exception(env)?;
+            let key = env
+                .call_method(&entry, "getKey", "()Ljava/lang/Object;", &[])
+                .check_exception(env)?
+                .l()?;
+            let value = env
+                .call_method(&entry, "getValue", "()Ljava/lang/Object;", &[])
+                .check_exception(env)?
+                .l()?;
+            for (this, other) in [(&key, &value), (&value, &key)] {
+                if env.is_same_object(this, surface)?
+                    && env.is_instance_of(other, "android/view/SurfaceControl")?
+                {
+                    return Ok(env.new_local_ref(other)?);
+                }
+            }
+        }
+        anyhow::bail!("No SurfaceControl for the native window in Main.surfaceControlSurfaceMap")
+    }
+
+    /// Make the main surface's layer take touches only inside `rects`
+    /// (physical pixels); the rest reach the windows underneath. Touches
+    /// inside are swallowed since the layer has no input channel.
+    ///
+    /// Drives the hidden `InputWindowHandle` / `setInputWindowInfo` API
+    /// (Android 11+) and marks the layer a trusted overlay (Android 12+) so
+    /// touches passing by aren't blocked as obscured.
+    pub fn set_touchable_region(
+        &self,
+        env: &mut JNIEnv<'a>,
+        surface: &MainSurface,
+        rects: &[egui::Rect],
+    ) -> Result<()> {
+        let Some(control) = &surface.control else {
+            anyhow::bail!("The native window's SurfaceControl is unknown");
+        };
+
+        let region = env
+            .new_object("android/graphics/Region", "()V", &[])
+            .check_exception(env)?;
+        for rect in rects {
+            let rect = env
+                .new_object(
+                    "android/graphics/Rect",
+                    "(IIII)V",
+                    &[
+                        JValue::Int(rect.min.x.floor() as i32),
+                        JValue::Int(rect.min.y.floor() as i32),
+                        JValue::Int(rect.max.x.ceil() as i32),
+                        JValue::Int(rect.max.y.ceil() as i32),
+                    ],
+                )
+                .check_exception(env)?;
+            env.call_method(
+                &region,
+                "union",
+                "(Landroid/graphics/Rect;)Z",
+                &[JValue::Object(&rect)],
+            )
+            .check_exception(env)?;
+        }
+
+        let handle = env
+            .new_object(
+                "android/view/InputWindowHandle",
+                "(Landroid/view/InputApplicationHandle;I)V",
+                &[JValue::Object(&JObject::null()), JValue::Int(0)],
+            )
+            .check_exception(env)
+            .map_err(|e| e.context("InputWindowHandle (needs Android 11)"))?;
+        let name = env.new_string("android-egui-ext").check_exception(env)?;
+        env.set_field(&handle, "name", "Ljava/lang/String;", JValue::Object(&name))
+            .check_exception(env)?;
+        let token = env
+            .new_object("android/os/Binder", "()V", &[])
+            .check_exception(env)?;
+        env.set_field(
+            &handle,
+            "token",
+            "Landroid/os/IBinder;",
+            JValue::Object(&token),
+        )
+        .check_exception(env)?;
+        env.set_field(
+            &handle,
+            "layoutParamsFlags",
+            "I",
+            JValue::Int(INPUT_WINDOW_FLAGS),
+        )
+        .check_exception(env)?;
+        env.set_field(
+            &handle,
+            "layoutParamsType",
+            "I",
+            JValue::Int(TYPE_APPLICATION_OVERLAY),
+        )
+        .check_exception(env)?;
+        // Visibility and the input channel moved into `inputConfig` on
+        // Android 13.
+        if env
+            .set_field(&handle, "inputConfig", "I", JValue::Int(INPUT_CONFIG))
+            .check_exception(env)
+            .is_err()
+        {
+            env.set_field(&handle, "visible", "Z", JValue::Bool(1))
+                .check_exception(env)?;
+            env.set_field(
+                &handle,
+                "inputFeatures",
+                "I",
+                JValue::Int(INPUT_FEATURE_NO_INPUT_CHANNEL),
+            )
+            .check_exception(env)?;
+        }
+        let touchable = env
+            .get_field(&handle, "touchableRegion", "Landroid/graphics/Region;")
+            .check_exception(env)?
+            .l()?;
+        env.call_method(
+            &touchable,
+            "set",
+            "(Landroid/graphics/Region;)Z",
+            &[JValue::Object(&region)],
+        )
+        .check_exception(env)?;
+
+        let transaction = env
+            .new_object("android/view/SurfaceControl$Transaction", "()V", &[])
+            .check_exception(env)?;
+        env.call_method(
+            &transaction,
+            "setInputWindowInfo",
+            "(Landroid/view/SurfaceControl;Landroid/view/InputWindowHandle;)Landroid/view/SurfaceControl$Transaction;",
+            &[JValue::Object(control.as_obj()), JValue::Object(&handle)],
+        )
+        .check_exception(env)?;
+        if let Err(e) = env
+            .call_method(
+                &transaction,
+                "setTrustedOverlay",
+                "(Landroid/view/SurfaceControl;Z)Landroid/view/SurfaceControl$Transaction;",
+                &[JValue::Object(control.as_obj()), JValue::Bool(1)],
+            )
+            .check_exception(env)
+        {
+            tracing::debug!("setTrustedOverlay unavailable (needs Android 12): {e:#}");
+        }
+        env.call_method(&transaction, "apply", "()V", &[])
+            .check_exception(env)?;
+        Ok(())
+    }
+
+    /// Remove the surface made by [`Self::create_native_window`]. Its EGL
+    /// surface must be gone by now.
+    pub fn destroy_native_window(&self, env: &mut JNIEnv<'a>, surface: MainSurface) -> Result<()> {
+        env.call_static_method(
+            &self.main_class,
+            "destroyNativeWindow",
+            "(Landroid/view/Surface;)V",
+            &[JValue::Object(surface.surface.as_obj())],
+        )
+        .check_exception(env)?;
+        Ok(())
+    }
+
+    /// The built-in display (`DisplayManager.getDisplay(DEFAULT_DISPLAY)`).
+    fn get_default_display(&self, env: &mut JNIEnv<'a>) -> Result<JObject<'a>> {
+        let context = self.get_system_context(env)?;
+        let service_name = env.new_string("display").check_exception(env)?;
+        let display_manager = env
+            .call_method(
+                &context,
+                "getSystemService",
+                "(Ljava/lang/String;)Ljava/lang/Object;",
+                &[JValue::Object(&service_name)],
+            )
+            .check_exception(env)?
+            .l()?;
+        if display_manager.is_null() {
+            anyhow::bail!("DisplayManager unavailable");
+        }
+        let display = env
+            .call_method(
+                &display_manager,
+                "getDisplay",
+                "(I)Landroid/view/Display;",
+                &[JValue::Int(0)],
+            )
+            .check_exception(env)?
+            .l()?;
+        if display.is_null() {
+            anyhow::bail!("default display not found");
+        }
+        Ok(display)
+    }
+
+    fn get_int_array(&self, env: &mut JNIEnv<'a>, array: JObject<'a>) -> Result<Vec<i32>> {
+        if array.is_null() {
+            return Ok(Vec::new());
+        }
+        let array = JIntArray::from(array);
+        let mut values = vec![0i32; env.get_array_length(&array)? as usize];
+        env.get_int_array_region(&array, 0, &mut values)
+            .check_exception(env)?;
+        Ok(values)
+    }
+
+    /// Supported modes of the built-in display, with the active one.
+    ///
+    /// HDR types are per mode on Android 14+; before that every mode gets
+    /// the display's `HdrCapabilities`.
+    pub fn get_display_modes(&self, env: &mut JNIEnv<'a>) -> Result<DisplayModes> {
+        let display = self.get_default_display(env)?;
+
+        let display_hdr_types = match env
+            .call_method(
+                &display,
+                "getHdrCapabilities",
+                "()Landroid/view/Display$HdrCapabilities;",
+                &[],
+            )
+            .check_exception(env)
+            .and_then(|caps| Ok(caps.l()?))
+        {
+            Ok(caps) if !caps.is_null() => {
+                let types = env
+                    .call_method(&caps, "getSupportedHdrTypes", "()[I", &[])
+                    .check_exception(env)?
+                    .l()?;
+                self.get_int_array(env, types)?
+            }
+            _ => Vec::new(),
+        };
+
+        let modes: JObjectArray = env
+            .call_method(
+                &display,
+                "getSupportedModes",
+                "()[Landroid/view/Display$Mode;",
+                &[],
+            )
+            .check_exception(env)?
+            .l()?
+            .into();
+        let count = env.get_array_length(&modes)?;
+        let mut result = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let mode = env
+                .get_object_array_element(&modes, i)
+                .check_exception(env)?;
+            let int = |env: &mut JNIEnv<'a>, method: &str| -> Result<i32> {
+                Ok(env
+                    .call_method(&mode, method, "()I", &[])
+                    .check_exception(env)?
+                    .i()?)
+            };
+            let id = int(env, "getModeId")?;
+            let width = int(env, "getPhysicalWidth")?;
+            let height = int(env, "getPhysicalHeight")?;
+            let refresh_rate = env
+                .call_method(&mode, "getRefreshRate", "()F", &[])
+                .check_exception(env)?
+                .f()?;
+            let hdr_types = match env
+                .call_method(&mode, "getSupportedHdrTypes", "()[I", &[])
+                .check_exception(env)
+            {
+                Ok(types) => self.get_int_array(env, types.l()?)?,
+                Err(_) => display_hdr_types.clone(),
+            };
+            env.delete_local_ref(mode)?;
+            result.push(DisplayMode {
+                id,
+                width,
+                height,
+                refresh_rate,
+                hdr_types,
+            });
+        }
+
+        let active_mode = env
+            .call_method(&display, "getMode", "()Landroid/view/Display$Mode;", &[])
+            .check_exception(env)?
+            .l()?;
+        let active = env
+            .call_method(&active_mode, "getModeId", "()I", &[])
+            .check_exception(env)?
+            .i()?;
+
+        Ok(DisplayModes {
+            modes: result,
+            active,
+        })
+    }
+
+    /// Current refresh rate of the built-in display in Hz.
+    pub fn get_refresh_rate(&self, env: &mut JNIEnv<'a>) -> Result<f32> {
+        let display = self.get_default_display(env)?;
+        let rate = env
+            .call_method(&display, "getRefreshRate", "()F", &[])
+            .check_exception(env)?
+            .f()?;
+        Ok(rate)
+    }
+
+    /// Ask the system to run the display at `fps` while our surface is
+    /// shown (`Surface.setFrameRate`, Android 11+). `0.0` clears the request.
+    pub fn set_frame_rate(
+        &self,
+        env: &mut JNIEnv<'a>,
+        surface: &MainSurface,
+        fps: f32,
+    ) -> Result<()> {
+        const FRAME_RATE_COMPATIBILITY_DEFAULT: i32 = 0;
+
+        env.call_method(
+            surface.surface.as_obj(),
+            "setFrameRate",
+            "(FI)V",
+            &[
+                JValue::Float(fps),
+                JValue::Int(FRAME_RATE_COMPATIBILITY_DEFAULT),
+            ],
+        )
+        .check_exception(env)
+        .map_err(|e| e.context("Surface.setFrameRate (needs Android 11)"))?;
+        Ok(())
+    }
+
+    pub fn get_system_context(&self, env: &mut JNIEnv<'a>) -> Result<JObject<'a>> {
+        let context = env
+            .call_static_method(
+                &self.main_class,
+                "getSystemContext",
+                "()Landroid/content/Context;",
+                &[],
+            )
+            .check_exception(env)?
+            .l()?;
+        Ok(context)
+    }
+
+    /// Whether this process holds `permission`, as the activity manager
+    /// sees it (root and system always do).
+    pub fn check_permission(&self, env: &mut JNIEnv<'a>, permission: &str) -> Result<bool> {
+        const PERMISSION_GRANTED: i32 = 0;
+
+        let context = self.get_system_context(env)?;
+        let permission = env.new_string(permission).check_exception(env)?;
+        let result = env
+            .call_method(
+                &context,
+                "checkPermission",
+                "(Ljava/lang/String;II)I",
+                &[
+                    JValue::Object(&permission),
+                    JValue::Int(unsafe { libc::getpid() }),
+                    JValue::Int(unsafe { libc::getuid() } as i32),
+                ],
+            )
+            .check_exception(env)?
+            .i()?;
+        env.delete_local_ref(permission)?;
+        env.delete_local_ref(context)?;
+        Ok(result == PERMISSION_GRANTED)
+    }
+
+    /// Whether the system is in night (dark) mode.
+    ///
+    /// Asks `UiModeManager` first since it reflects changes made while we run;
+    /// for `MODE_NIGHT_AUTO`/`CUSTOM` falls back to `Configuration.uiMode`.
+    pub fn is_night_mode(&self, env: &mut JNIEnv<'a>) -> Result<bool> {
+        const MODE_NIGHT_NO: i32 = 1;
+        const MODE_NIGHT_YES: i32 = 2;
+        const UI_MODE_NIGHT_MASK: i32 = 0x30;
+        const UI_MODE_NIGHT_YES: i32 = 0x20;
+
+        let context = self.get_system_context(env)?;
+        let service_name = env.new_string("uimode").check_exception(env)?;
+        let ui_mode_manager = env
+            .call_method(
+                &context,
+                "getSystemService",
+                "(Ljava/lang/String;)Ljava/lang/Object;",
+                &[JValue::Object(&service_name)],
+            )
+            .check_exception(env)?
+            .l()?;
+        env.delete_local_ref(service_name)?;
+
+        let night_mode = if ui_mode_manager.is_null() {
+            None
+        } else {
+            let mode = env
+                .call_method(&ui_mode_manager, "getNightMode", "()I", &[])
+                .check_exception(env)?
+                .i()?;
+            env.delete_local_ref(ui_mode_manager)?;
+            match mode {
+                MODE_NIGHT_YES => Some(true),
+                MODE_NIGHT_NO => Some(false),
+                _ => None,
+            }
+        };
+
+        let night_mode = match night_mode {
+            Some(night_mode) => night_mode,
+            None => {
+                let resources = env
+                    .call_method(
+                        &context,
+                        "getResources",
+                        "()Landroid/content/res/Resources;",
+                        &[],
+                    )
+                    .check_exception(env)?
+                    .l()?;
+                let configuration = env
+                    .call_method(
+                        &resources,
+                        "getConfiguration",
+                        "()Landroid/content/res/Configuration;",
+                        &[],
+                    )
+                    .check_exception(env)?
+                    .l()?;
+                let ui_mode = env
+                    .get_field(&configuration, "uiMode", "I")
+                    .check_exception(env)?
+                    .i()?;
+                env.delete_local_ref(configuration)?;
+                env.delete_local_ref(resources)?;
+                ui_mode & UI_MODE_NIGHT_MASK == UI_MODE_NIGHT_YES
+            }
+        };
+        // Polled periodically; don't leak refs.
+        env.delete_local_ref(context)?;
+        Ok(night_mode)
+    }
+
+    fn get_package_manager(&self, env: &mut JNIEnv<'a>) -> Result<JObject<'a>> {
+        let context = self.get_system_context(env)?;
+        let package_manager = env
+            .call_method(
+                &context,
+                "getPackageManager",
+                "()Landroid/content/pm/PackageManager;",
+                &[],
+            )
+            .check_exception(env)?
+            .l()?;
+        Ok(package_manager)
+    }
+
+    /// `PackageManager.getApplicationIcon(packageName)` as a `Drawable`.
+    pub fn get_application_icon(
+        &self,
+        env: &mut JNIEnv<'a>,
+        package_name: &str,
+    ) -> Result<JObject<'a>> {
+        let package_manager = self.get_package_manager(env)?;
+        let package_name = env.new_string(package_name).check_exception(env)?;
+        let drawable = env
+            .call_method(
+                &package_manager,
+                "getApplicationIcon",
+                "(Ljava/lang/String;)Landroid/graphics/drawable/Drawable;",
+                &[JValue::Object(&package_name)],
+            )
+            .check_exception(env)?
+            .l()?;
+        Ok(drawable)
+    }
+
+    /// A drawable from another package's resources, looked up by name
+    /// (e.g. `"ic_launcher"` of type `"mipmap"`).
+    pub fn get_package_drawable(
+        &self,
+        env: &mut JNIEnv<'a>,
+        package_name: &str,
+        resource_type: &str,
+        resource_name: &str,
+    ) -> Result<JObject<'a>> {
+        let package_manager = self.get_package_manager(env)?;
+        let package = env.new_string(package_name).check_exception(env)?;
+        let resources = env
+            .call_method(
+                &package_manager,
+                "getResourcesForApplication",
+                "(Ljava/lang/String;)Landroid/content/res/Resources;",
+                &[JValue::Object(&package)],
+            )
+            .check_exception(env)?
+            .l()?;
+        let name = env.new_string(resource_name).check_exception(env)?;
+        let def_type = env.new_string(resource_type).check_exception(env)?;
+        let id = env
+            .call_method(
+                &resources,
+                "getIdentifier",
+                "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)I",
+                &[
+                    JValue::Object(&name),
+                    JValue::Object(&def_type),
+                    JValue::Object(&package),
+                ],
+            )
+            .check_exception(env)?
+            .i()?;
+        if id == 0 {
+            anyhow::bail!("Resource {resource_type}/{resource_name} not found in {package_name}");
+        }
+        let drawable = env
+            .call_method(
+                &resources,
+                "getDrawable",
+                "(ILandroid/content/res/Resources$Theme;)Landroid/graphics/drawable/Drawable;",
+                &[JValue::Int(id), JValue::Object(&JObject::null())],
+            )
+            .check_exception(env)?
+            .l()?;
+        Ok(drawable)
+    }
+
+    /// Render a `Drawable` into an ARGB_8888 `Bitmap` using the dex helper.
+    pub fn get_bitmap_from_drawable(
+        &self,
+        env: &mut JNIEnv<'a>,
+        drawable: &JObject<'a>,
+    ) -> Result<JObject<'a>> {
+        let bitmap = env
+            .call_static_method(
+                &self.main_class,
+                "getBitmapFromDrawable",
+                "(Landroid/graphics/drawable/Drawable;)Landroid/graphics/Bitmap;",
+                &[JValue::Object(drawable)],
+            )
+            .check_exception(env)?
+            .l()?;
+        Ok(bitmap)
+    }
+
+    /// Read a `Bitmap`'s pixels as non-premultiplied ARGB, row by row.
+    pub fn get_bitmap_pixels(
+        &self,
+        env: &mut JNIEnv<'a>,
+        bitmap: &JObject<'a>,
+    ) -> Result<([usize; 2], Vec<i32>)> {
+        let width = env
+            .call_method(bitmap, "getWidth", "()I", &[])
+            .check_exception(env)?
+            .i()?;
+        let height = env
+            .call_method(bitmap, "getHeight", "()I", &[])
+            .check_exception(env)?
+            .i()?;
+        // Java arrays hold at most i32::MAX elements.
+        let len = usize::try_from(width)?
+            .checked_mul(usize::try_from(height)?)
+            .filter(|&len| i32::try_from(len).is_ok())
+            .ok_or_else(|| anyhow::anyhow!("Bitmap of {width}x{height} is too large"))?;
+        let pixel_array = env.new_int_array(len as i32).check_exception(env)?;
+        env.call_method(
+            bitmap,
+            "getPixels",
+            "([IIIIIII)V",
+            &[
+                JValue::Object(&pixel_array),
+                JValue::Int(0),
+                JValue::Int(width),
+                JValue::Int(0),
+                JValue::Int(0),
+                JValue::Int(width),
+                JValue::Int(height),
+            ],
+        )
+        .check_exception(env)?;
+        let mut pixels = vec![0i32; len];
+        env.get_int_array_region(&pixel_array, 0, &mut pixels)
+            .check_exception(env)?;
+        Ok(([width as usize, height as usize], pixels))
+    }
+
+    /// Current primary clip as text, or `None` if the clipboard is empty or
+    /// holds something that isn't text.
+    pub fn get_clipboard_text(&self, env: &mut JNIEnv<'a>) -> Result<Option<String>> {
+        let text = env
+            .call_static_method(
+                &self.main_class,
+                "getClipboardText",
+                "()Ljava/lang/String;",
+                &[],
+            )
+            .check_exception(env)?
+            .l()?;
+        if text.is_null() {
+            return Ok(None);
+        }
+        let text = JString::from(text);
+        let value: String = env.get_string(&text)?.into();
+        // Polled every few frames on a long-lived thread; don't leak refs.
+        env.delete_local_ref(text)?;
+        Ok(Some(value))
+    }
+
+    /// Replace the primary clip. Returns `false` if the clipboard service
+    /// rejected it.
+    pub fn set_clipboard_text(&self, env: &mut JNIEnv<'a>, text: &str) -> Result<bool> {
+        let text = env.new_string(text).check_exception(env)?;
+        let ok = env
+            .call_static_method(
+                &self.main_class,
+                "setClipboardText",
+                "(Ljava/lang/String;)Z",
+                &[JValue::Object(&text)],
+            )
+            .check_exception(env)?
+            .z()?;
+        env.delete_local_ref(text)?;
+        Ok(ok)
+    }
+
+    /// Open `url` with whatever app handles `ACTION_VIEW` for it, started
+    /// from the system context as a new task.
+    pub fn open_url(&self, env: &mut JNIEnv<'a>, url: &str) -> Result<()> {
+        let url = env.new_string(url).check_exception(env)?;
+        let uri = env
+            .call_static_method(
+                "android/net/Uri",
+                "parse",
+                "(Ljava/lang/String;)Landroid/net/Uri;",
+                &[JValue::Object(&url)],
+            )
+            .check_exception(env)?
+            .l()?;
+        let action = env
+            .new_string("android.intent.action.VIEW")
+            .check_exception(env)?;
+        let intent = env
+            .new_object(
+                "android/content/Intent",
+                "(Ljava/lang/String;Landroid/net/Uri;)V",
+                &[JValue::Object(&action), JValue::Object(&uri)],
+            )
+            .check_exception(env)?;
+        // FLAG_ACTIVITY_NEW_TASK: required when starting from a non-activity context.
+        env.call_method(
+            &intent,
+            "addFlags",
+            "(I)Landroid/content/Intent;",
+            &[JValue::Int(0x1000_0000)],
+        )
+        .check_exception(env)?;
+
+        let context = self.get_system_context(env)?;
+        env.call_method(
+            &context,
+            "startActivity",
+            "(Landroid/content/Intent;)V",
+            &[JValue::Object(&intent)],
+        )
+        .check_exception(env)?;
+        Ok(())
+    }
+
+    /// Create a `width`x`height` layer at (`x`, `y`) on the default display,
+    /// above regular app windows.
+    ///
+    /// The dex only creates full-screen windows, so this drives the hidden
+    /// `SurfaceControl.Builder(SurfaceSession)` API directly.
+    pub fn create_overlay_surface(
+        &self,
+        env: &mut JNIEnv<'a>,
+        name: &str,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) -> Result<OverlaySurface> {
+        let session = env
+            .new_object("android/view/SurfaceSession", "()V", &[])
+            .check_exception(env)?;
+        let layer = self
+            .build_overlay_layer(env, &session, name, width, height)
+            .and_then(|control| {
+                match self.show_overlay_layer(env, &control, x, y) {
+                    Ok((surface, window)) => Ok((control, surface, window)),
+                    Err(e) => {
+                        // Shown layers would stay on screen with nothing to draw.
+                        if let Err(cleanup) = self.remove_layer(env, &control) {
+                            tracing::debug!("Removing the overlay layer failed: {cleanup:#}");
+                        }
+                        Err(e)
+                    }
+                }
+            });
+        match layer {
+            Ok((control, surface, window)) => Ok(OverlaySurface {
+                window,
+                surface: env.new_global_ref(surface)?,
+                control: env.new_global_ref(control)?,
+                session: env.new_global_ref(session)?,
+            }),
+            Err(e) => {
+                if let Err(cleanup) = env
+                    .call_method(&session, "kill", "()V", &[])
+                    .check_exception(env)
+                {
+                    tracing::debug!("Killing the surface session failed: {cleanup:#}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// An unshown `width`x`height` RGBA layer on `session`.
+    fn build_overlay_layer(
+        &self,
+        env: &mut JNIEnv<'a>,
+        session: &JObject<'a>,
+        name: &str,
+        width: i32,
+        height: i32,
+    ) -> Result<JObject<'a>> {
+        let builder = env
+            .new_object(
+                "android/view/SurfaceControl$Builder",
+                "(Landroid/view/SurfaceSession;)V",
+                &[JValue::Object(session)],
+            )
+            .check_exception(env)?;
+        let name = env.new_string(name).check_exception(env)?;
+        env.call_method(
+            &builder,
+            "setName",
+            "(Ljava/lang/String;)Landroid/view/SurfaceControl$Builder;",
+            &[JValue::Object(&name)],
+        )
+        .check_exception(env)?;
+        env.call_method(
+            &builder,
+            "setBufferSize",
+            "(II)Landroid/view/SurfaceControl$Builder;",
+            &[JValue::Int(width), JValue::Int(height)],
+        )
+        .check_exception(env)?;
+        env.call_method(
+            &builder,
+            "setFormat",
+            "(I)Landroid/view/SurfaceControl$Builder;",
+            &[JValue::Int(PIXEL_FORMAT_RGBA_8888)],
+        )
+        .check_exception(env)?;
+        let control = env
+            .call_method(&builder, "build", "()Landroid/view/SurfaceControl;", &[])
+            .check_exception(env)?
+            .l()?;
+        Ok(control)
+    }
+
+    /// Put `control` on the default display at (`x`, `y`) above everything
+    /// else, show it and return a `Surface` and window to draw into it.
+    fn show_overlay_layer(
+        &self,
+        env: &mut JNIEnv<'a>,
+        control: &JObject<'a>,
+        x: i32,
+        y: i32,
+    ) -> Result<(JObject<'a>, NativeWindow)> {
+        let transaction = env
+            .new_object("android/view/SurfaceControl$Transaction", "()V", &[])
+            .check_exception(env)?;
+        let control_arg = JValue::Object(control);
+        for (method, sig, args) in [
+            (
+                "setLayerStack",
+                "(Landroid/view/SurfaceControl;I)Landroid/view/SurfaceControl$Transaction;",
+                vec![control_arg, JValue::Int(0)],
+            ),
+            (
+                "setLayer",
+                "(Landroid/view/SurfaceControl;I)Landroid/view/SurfaceControl$Transaction;",
+                vec![control_arg, JValue::Int(OVERLAY_LAYER)],
+            ),
+            (
+                "setPosition",
+                "(Landroid/view/SurfaceControl;FF)Landroid/view/SurfaceControl$Transaction;",
+                vec![
+                    control_arg,
+                    JValue::Float(x as f32),
+                    JValue::Float(y as f32),
+                ],
+            ),
+            (
+                "show",
+                "(Landroid/view/SurfaceControl;)Landroid/view/SurfaceControl$Transaction;",
+                vec![control_arg],
+            ),
+        ] {
+            env.call_method(&transaction, method, sig, &args)
+                .check_exception(env)?;
+        }
+        env.call_method(&transaction, "apply", "()V", &[])
+            .check_exception(env)?;
+
+        let surface = env
+            .new_object(
+                "android/view/Surface",
+                "(Landroid/view/SurfaceControl;)V",
+                &[JValue::Object(control)],
+            )
+            .check_exception(env)?;
+        let window = unsafe {
+            NativeWindow::from_surface(env.get_raw(), surface.as_raw()).ok_or(anyhow::anyhow!(
+                "Failed to create NativeWindow from overlay surface"
+            ))?
+        };
+
+        Ok((surface, window))
+    }
+
+    /// Whether SurfaceFlinger lets this process create secure layers (ones
+    /// that screenshots and recordings show as black), by building a 1x1
+    /// one off screen and releasing it again. Only privileged callers such
+    /// as root or the graphics group may.
+    pub fn can_create_secure_surface(&self, env: &mut JNIEnv<'a>) -> Result<bool> {
+        let session = env
+            .new_object("android/view/SurfaceSession", "()V", &[])
+            .check_exception(env)?;
+        let builder = env
+            .new_object(
+                "android/view/SurfaceControl$Builder",
+                "(Landroid/view/SurfaceSession;)V",
+                &[JValue::Object(&session)],
+            )
+            .check_exception(env)?;
+        let name = env.new_string("secure probe").check_exception(env)?;
+        env.call_method(
+            &builder,
+            "setName",
+            "(Ljava/lang/String;)Landroid/view/SurfaceControl$Builder;",
+            &[JValue::Object(&name)],
+        )
+        .check_exception(env)?;
+        env.call_method(
+            &builder,
+            "setBufferSize",
+            "(II)Landroid/view/SurfaceControl$Builder;",
+            &[JValue::Int(1), JValue::Int(1)],
+        )
+        .check_exception(env)?;
+        env.call_method(
+            &builder,
+            "setSecure",
+            "(Z)Landroid/view/SurfaceControl$Builder;",
+            &[JValue::Bool(1)],
+        )
+        .check_exception(env)?;
+        let built = env
+            .call_method(&builder, "build", "()Landroid/view/SurfaceControl;", &[])
+            .check_exception(env);
+        let allowed = match built {
+            Ok(control) => {
+                let control = control.l()?;
+                env.call_method(&control, "release", "()V", &[])
+                    .check_exception(env)?;
+                env.delete_local_ref(control)?;
+                true
+            }
+            Err(e) => {
+                tracing::debug!("Secure layer refused: {e:#}");
+                false
+            }
+        };
+        env.call_method(&session, "kill", "()V", &[])
+            .check_exception(env)?;
+        for local in [name.into(), builder, session] {
+            env.delete_local_ref(local)?;
+        }
+        Ok(allowed)
+    }
+
+    /// Move a layer created by [`Self::create_overlay_surface`] to (`x`, `y`)
+    /// and give it `width`x`height` buffers.
+    pub fn move_overlay_surface(
+        &self,
+        env: &mut JNIEnv<'a>,
+        surface: &OverlaySurface,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) -> Result<()> {
+        let transaction = env
+            .new_object("android/view/SurfaceControl$Transaction", "()V", &[])
+            .check_exception(env)?;
+        let control_arg = JValue::Object(surface.control.as_obj());
+        env.call_method(
+            &transaction,
+            "setPosition",
+            "(Landroid/view/SurfaceControl;FF)Landroid/view/SurfaceControl$Transaction;",
+            &[
+                control_arg,
+                JValue::Float(x as f32),
+                JValue::Float(y as f32),
+            ],
+        )
+        .check_exception(env)?;
+        env.call_method(
+            &transaction,
+            "setBufferSize",
+            "(Landroid/view/SurfaceControl;II)Landroid/view/SurfaceControl$Transaction;",
+            &[control_arg, JValue::Int(width), JValue::Int(height)],
+        )
+        .check_exception(env)?;
+        env.call_method(&transaction, "apply", "()V", &[])
+            .check_exception(env)?;
+        Ok(())
+    }
+
+    /// Remove a layer created by [`Self::create_overlay_surface`] and release
+    /// its surface, control and session.
+    pub fn destroy_overlay_surface(
+        &self,
+        env: &mut JNIEnv<'a>,
+        surface: OverlaySurface,
+    ) -> Result<()> {
+        let removed = self.remove_layer(env, surface.control.as_obj());
+        env.call_method(surface.surface.as_obj(), "release", "()V", &[])
+            .check_exception(env)?;
+        env.call_method(surface.control.as_obj(), "release", "()V", &[])
+            .check_exception(env)?;
+        env.call_method(surface.session.as_obj(), "kill", "()V", &[])
+            .check_exception(env)?;
+        removed
+    }
+
+    /// Take `control`'s layer off screen for good.
+    fn remove_layer(&self, env: &mut JNIEnv<'a>, control: &JObject<'a>) -> Result<()> {
+        let transaction = env
+            .new_object("android/view/SurfaceControl$Transaction", "()V", &[])
+            .check_exception(env)?;
+        env.call_method(
+            &transaction,
+            "remove",
+            "(Landroid/view/SurfaceControl;)Landroid/view/SurfaceControl$Transaction;",
+            &[JValue::Object(control)],
+        )
+        .check_exception(env)?;
+        env.call_method(&transaction, "apply", "()V", &[])
+            .check_exception(env)?;
+        Ok(())
+    }
+
+    /// Token of the built-in display, for the `SurfaceControl` capture APIs.
+    ///
+    /// The physical display APIs moved from `SurfaceControl` to the system
+    /// server's `DisplayControl` on Android 14; Android 9 only has
+    /// `getInternalDisplayToken`.
+    fn get_display_token(&self, env: &mut JNIEnv<'a>) -> Result<JObject<'a>> {
+        let device = DeviceInfo::current()?;
+        let token = if device.is_at_least(34) {
+            let display_control = display_control_class(env)?;
+            first_physical_display_token(env, &display_control)?
+        } else if device.is_at_least(29) {
+            let surface_control = env
+                .find_class("android/view/SurfaceControl")
+                .check_exception(env)?;
+            first_physical_display_token(env, &surface_control)?
+        } else {
+            env.call_static_method(
+                "android/view/SurfaceControl",
+                "getInternalDisplayToken",
+                "()Landroid/os/IBinder;",
+                &[],
+            )
+            .check_exception(env)?
+            .l()?
+        };
+        if token.is_null() {
+            anyhow::bail!("no physical display token");
+        }
+        Ok(token)
+    }
+
+    /// Screenshot of the whole display as a software `Bitmap`.
+    ///
+    /// Uses `ScreenCapture.captureDisplay` (Android 14+),
+    /// `SurfaceControl.captureDisplay` (12-13) or `SurfaceControl.screenshot`
+    /// (9-11), whichever exists. All of them need `READ_FRAME_BUFFER`, i.e.
+    /// running as shell or root; otherwise they throw or return null.
+    pub fn capture_display(
+        &self,
+        env: &mut JNIEnv<'a>,
+        width: i32,
+        height: i32,
+    ) -> Result<JObject<'a>> {
+        let mut errors = Vec::new();
+        let mut bitmap = None;
+        for (holder, args_class) in [
+            (
+                "android/window/ScreenCapture",
+                "android/window/ScreenCapture$DisplayCaptureArgs",
+            ),
+            (
+                "android/view/SurfaceControl",
+                "android/view/SurfaceControl$DisplayCaptureArgs",
+            ),
+        ] {
+            match self.capture_display_with(env, holder, args_class) {
+                Ok(b) => {
+                    bitmap = Some(b);
+                    break;
+                }
+                Err(e) => errors.push(format!("{holder}.captureDisplay: {e:#}")),
+            }
+        }
+        if bitmap.is_none() {
+            match self.legacy_screenshot(env, width, height) {
+                Ok(b) => bitmap = Some(b),
+                Err(e) => errors.push(format!("SurfaceControl.screenshot: {e:#}")),
+            }
+        }
+        let Some(bitmap) = bitmap else {
+            anyhow::bail!("no screen capture API worked: {}", errors.join("; "));
+        };
+        if bitmap.is_null() {
+            anyhow::bail!("screen capture returned nothing (missing READ_FRAME_BUFFER?)");
+        }
+
+        // Captures are HARDWARE bitmaps, whose pixels can't be read directly.
+        let config = env
+            .get_static_field(
+                "android/graphics/Bitmap$Config",
+                "ARGB_8888",
+                "Landroid/graphics/Bitmap$Config;",
+            )
+            .check_exception(env)?
+            .l()?;
+        let software = env
+            .call_method(
+                &bitmap,
+                "copy",
+                "(Landroid/graphics/Bitmap$Config;Z)Landroid/graphics/Bitmap;",
+                &[JValue::Object(&config), JValue::Bool(0)],
+            )
+            .check_exception(env)?
+            .l()?;
+        if software.is_null() {
+            anyhow::bail!("failed to copy screen capture into a software bitmap");
+        }
+        Ok(software)
+    }
+
+    /// The pre-Android 12 `SurfaceControl.screenshot(Rect, int, int, int)`.
+    fn legacy_screenshot(
+        &self,
+        env: &mut JNIEnv<'a>,
+        width: i32,
+        height: i32,
+    ) -> Result<JObject<'a>> {
+        let crop = env
+            .new_object("android/graphics/Rect", "()V", &[])
+            .check_exception(env)?;
+        let bitmap = env
+            .call_static_method(
+                "android/view/SurfaceControl",
+                "screenshot",
+                "(Landroid/graphics/Rect;III)Landroid/graphics/Bitmap;",
+                &[
+                    JValue::Object(&crop),
+                    JValue::Int(width),
+                    JValue::Int(height),
+                    JValue::Int(0), // ROTATION_0
+                ],
+            )
+            .check_exception(env)?
+            .l()?;
+        Ok(bitmap)
+    }
+
+    /// `captureDisplay(DisplayCaptureArgs)` on `holder`, which is either
+    /// `ScreenCapture` or `SurfaceControl` depending on the Android version.
+    fn capture_display_with(
+        &self,
+        env: &mut JNIEnv<'a>,
+        holder: &str,
+        args_class: &str,
+    ) -> Result<JObject<'a>> {
+        let token = self.get_display_token(env)?;
+        let builder = env
+            .new_object(
+                format!("{args_class}$Builder"),
+                "(Landroid/os/IBinder;)V",
+                &[JValue::Object(&token)],
+            )
+            .check_exception(env)?;
+        let args = env
+            .call_method(&builder, "build", format!("()L{args_class};"), &[])
+            .check_exception(env)?
+            .l()?;
+        let buffer = env
+            .call_static_method(
+                holder,
+                "captureDisplay",
+                format!("(L{args_class};)L{holder}$ScreenshotHardwareBuffer;"),
+                &[JValue::Object(&args)],
+            )
+            .check_exception(env)?
+            .l()?;
+        if buffer.is_null() {
+            anyhow::bail!("captureDisplay returned null (missing READ_FRAME_BUFFER?)");
+        }
+        let bitmap = env
+            .call_method(&buffer, "asBitmap", "()Landroid/graphics/Bitmap;", &[])
+            .check_exception(env)?
+            .l()?;
+        Ok(bitmap)
     }
 
     /// Inject a touch event via the Java InputManager bridge.
@@ -106,3 +1420,56 @@ impl<'a> JavaBridge<'a> {
         Ok(())
     }
 }
+
+/// `DisplayControl`, loaded from [`SERVICES_JAR`] together with
+/// `libandroid_servers.so`, which registers its natives for that loader.
+fn display_control_class<'a>(env: &mut JNIEnv<'a>) -> Result<JClass<'a>> {
+    let loader = path_class_loader(env, SERVICES_JAR)?;
+    let class = loader.find_class(env, "com.android.server.display.DisplayControl")?;
+    let runtime = env
+        .call_static_method(
+            "java/lang/Runtime",
+            "getRuntime",
+            "()Ljava/lang/Runtime;",
+            &[],
+        )
+        .check_exception(env)?
+        .l()?;
+    let library = env.new_string("android_servers").check_exception(env)?;
+    env.call_method(
+        &runtime,
+        "loadLibrary0",
+        "(Ljava/lang/Class;Ljava/lang/String;)V",
+        &[JValue::Object(&class), JValue::Object(&library)],
+    )
+    .check_exception(env)?;
+    Ok(class)
+}
+
+/// `getPhysicalDisplayToken(getPhysicalDisplayIds()[0])` on `holder`.
+fn first_physical_display_token<'a>(
+    env: &mut JNIEnv<'a>,
+    holder: &JClass<'a>,
+) -> Result<JObject<'a>> {
+    let ids = JLongArray::from(
+        env.call_static_method(holder, "getPhysicalDisplayIds", "()[J", &[])
+            .check_exception(env)?
+            .l()?,
+    );
+    if ids.is_null() || env.get_array_length(&ids)? == 0 {
+        anyhow::bail!("no physical displays");
+    }
+    let mut first = [0i64; 1];
+    env.get_long_array_region(&ids, 0, &mut first)
+        .check_exception(env)?;
+    let token = env
+        .call_static_method(
+            holder,
+            "getPhysicalDisplayToken",
+            "(J)Landroid/os/IBinder;",
+            &[JValue::Long(first[0])],
+        )
+        .check_exception(env)?
+        .l()?;
+    Ok(token)
+}
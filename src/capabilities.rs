@@ -0,0 +1,264 @@
+//! What this process is allowed to do. Running as root, as the `shell` user
+//! or inside an app gives very different access, so this is probed once at
+//! startup and subsystems that can't work are switched off with a warning
+//! instead of failing one call at a time.
+
+use std::ffi::CStr;
+use std::fmt;
+use std::fs;
+
+use jni::JNIEnv;
+use tracing::{info, warn};
+
+use crate::android::DeviceInfo;
+use crate::bridge::JavaBridge;
+
+/// Capabilities worth showing, by bit number.
+const CAPABILITY_NAMES: [(u32, &str); 8] = [
+    (1, "DAC_OVERRIDE"),
+    (2, "DAC_READ_SEARCH"),
+    (6, "SETGID"),
+    (7, "SETUID"),
+    (12, "NET_ADMIN"),
+    (19, "SYS_PTRACE"),
+    (21, "SYS_ADMIN"),
+    (23, "SYS_NICE"),
+];
+
+/// Result of probing the process at startup.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub uid: u32,
+    pub euid: u32,
+    /// `CapEff` from `/proc/self/status`.
+    pub effective: u64,
+    /// e.g. `u:r:shell:s0`; `None` without SELinux.
+    pub selinux_context: Option<String>,
+    /// Supplementary groups, with their names where known.
+    pub groups: Vec<(u32, Option<String>)>,
+    /// How many `/dev/input/event*` nodes could be opened.
+    pub input_nodes: usize,
+    /// Why the first one that couldn't be opened failed.
+    pub input_error: Option<String>,
+    /// `INJECT_EVENTS`, for passing touches on to the apps underneath.
+    pub inject_events: bool,
+    /// `READ_FRAME_BUFFER`, for screen captures.
+    pub read_frame_buffer: bool,
+    /// Whether SurfaceFlinger accepts secure layers from us.
+    pub secure_surfaces: bool,
+}
+
+impl Capabilities {
+    /// Probe the process, and the permissions the system grants it through
+    /// the bridge. Logs a warning for every subsystem that won't work.
+    pub fn probe<'a>(bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>) -> Self {
+        let mut check_permission = |permission: &str| {
+            bridge
+                .check_permission(env, permission)
+                .map_err(|e| warn!("Cannot check {permission}: {e:#}"))
+                .unwrap_or(false)
+        };
+        let inject_events = check_permission("android.permission.INJECT_EVENTS");
+        let read_frame_buffer = check_permission("android.permission.READ_FRAME_BUFFER");
+        let secure_surfaces = bridge
+            .can_create_secure_surface(env)
+            .map_err(|e| warn!("Cannot check for secure surfaces: {e:#}"))
+            .unwrap_or(false);
+
+        let (input_nodes, input_error) = probe_input_nodes();
+        let capabilities = Self {
+            uid: unsafe { libc::getuid() },
+            euid: unsafe { libc::geteuid() },
+            effective: effective_capabilities().unwrap_or(0),
+            selinux_context: fs::read_to_string("/proc/self/attr/current")
+                .ok()
+                .map(|context| context.trim_end_matches(['\0', '\n']).to_owned())
+                .filter(|context| !context.is_empty()),
+            groups: groups(),
+            input_nodes,
+            input_error,
+            inject_events,
+            read_frame_buffer,
+            secure_surfaces,
+        };
+        info!("{capabilities}");
+        capabilities.warn_missing();
+        capabilities
+    }
+
+    /// Whether touch input can be read from `/dev/input`.
+    pub fn can_read_input(&self) -> bool {
+        self.input_nodes > 0
+    }
+
+    pub fn has_capability(&self, bit: u32) -> bool {
+        bit < 64 && self.effective & (1 << bit) != 0
+    }
+
+    fn warn_missing(&self) {
+        if !self.can_read_input() {
+            warn!(
+                "Touch input disabled: cannot open input devices ({}). Run as root or as shell, which is in the input group",
+                self.input_error.as_deref().unwrap_or("none found")
+            );
+        }
+        if !self.inject_events {
+            warn!(
+                "INJECT_EVENTS not granted: touches outside the overlay won't reach the apps underneath"
+            );
+        }
+        if !self.read_frame_buffer {
+            warn!("READ_FRAME_BUFFER not granted: screen capture disabled");
+        }
+    }
+
+    /// The diagnostics panel.
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        let yes_no = |ok: bool| {
+            if ok {
+                egui::RichText::new("yes").color(egui::Color32::GREEN)
+            } else {
+                egui::RichText::new("no").color(egui::Color32::RED)
+            }
+        };
+
+        egui::Grid::new("capabilities")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("User");
+                ui.label(format!("uid {} / euid {}", self.uid, self.euid));
+                ui.end_row();
+
+                ui.label("Capabilities");
+                ui.label(self.capability_names());
+                ui.end_row();
+
+                ui.label("SELinux");
+                let mode = DeviceInfo::current()
+                    .map(|device| format!("{:?}", device.selinux))
+                    .unwrap_or_default();
+                ui.label(format!(
+                    "{} {mode}",
+                    self.selinux_context.as_deref().unwrap_or("none")
+                ));
+                ui.end_row();
+
+                ui.label("Groups");
+                ui.label(self.group_names()).on_hover_text(
+                    self.groups
+                        .iter()
+                        .map(|(gid, _)| gid.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                );
+                ui.end_row();
+
+                ui.label("Touch input");
+                let input = ui.label(yes_no(self.can_read_input()));
+                if let Some(error) = &self.input_error {
+                    input.on_hover_text(error);
+                }
+                ui.end_row();
+
+                ui.label("Touch pass-through");
+                ui.label(yes_no(self.inject_events));
+                ui.end_row();
+
+                ui.label("Screen capture");
+                ui.label(yes_no(self.read_frame_buffer));
+                ui.end_row();
+
+                ui.label("Secure surfaces");
+                ui.label(yes_no(self.secure_surfaces));
+                ui.end_row();
+            });
+    }
+
+    fn capability_names(&self) -> String {
+        if self.effective == 0 {
+            return "none".to_owned();
+        }
+        let names = CAPABILITY_NAMES
+            .iter()
+            .filter(|(bit, _)| self.has_capability(*bit))
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+        format!("{:#x} ({})", self.effective, names.join(" "))
+    }
+
+    fn group_names(&self) -> String {
+        self.groups
+            .iter()
+            .map(|(gid, name)| name.clone().unwrap_or_else(|| gid.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Running as uid {} (euid {}), context {}, capabilities {:#x}, groups [{}]",
+            self.uid,
+            self.euid,
+            self.selinux_context.as_deref().unwrap_or("none"),
+            self.effective,
+            self.group_names()
+        )
+    }
+}
+
+fn effective_capabilities() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let value = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(value.trim(), 16).ok()
+}
+
+/// Supplementary groups. bionic's `getgrgid` knows the Android ids
+/// (`input`, `graphics`, ...) without an `/etc/group`.
+fn groups() -> Vec<(u32, Option<String>)> {
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    if count <= 0 {
+        return Vec::new();
+    }
+    let mut gids = vec![0; count as usize];
+    let count = unsafe { libc::getgroups(count, gids.as_mut_ptr()) };
+    gids.truncate(count.max(0) as usize);
+    gids.into_iter()
+        .map(|gid| {
+            let group = unsafe { libc::getgrgid(gid) };
+            let name = (!group.is_null()).then(|| {
+                unsafe { CStr::from_ptr((*group).gr_name) }
+                    .to_string_lossy()
+                    .into_owned()
+            });
+            (gid, name)
+        })
+        .collect()
+}
+
+/// Try to open every event node, the same way the input thread does.
+fn probe_input_nodes() -> (usize, Option<String>) {
+    let entries = match fs::read_dir("/dev/input") {
+        Ok(entries) => entries,
+        Err(e) => return (0, Some(format!("/dev/input: {e}"))),
+    };
+    let mut opened = 0;
+    let mut error = None;
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with("event") {
+            continue;
+        }
+        match fs::File::open(entry.path()) {
+            Ok(_) => opened += 1,
+            Err(e) => {
+                error.get_or_insert_with(|| format!("{}: {e}", entry.path().display()));
+            }
+        }
+    }
+    (opened, error)
+}
@@ -0,0 +1,353 @@
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::{Context, Result};
+use jni::objects::{GlobalRef, JObject, JValue};
+use jni::{JNIEnv, JavaVM, NativeMethod};
+use tracing::{debug, info, warn};
+
+use crate::bridge::JavaBridge;
+use crate::dex::util::inject_dex;
+use crate::dex::writer::ClassBuilder;
+use crate::jni::jni_result_ext::JniResultExt;
+
+/// Generated class implementing the listener interface with a native method.
+const LISTENER_CLASS: &str = "android_egui_ext.ClipboardListener";
+const LISTENER_INTERFACE: &str = "android.content.ClipboardManager$OnPrimaryClipChangedListener";
+
+/// Set by the listener, cleared once the JNI thread has read the new clip.
+/// The listener class is registered once per process, so this is global.
+static CLIP_CHANGED: AtomicBool = AtomicBool::new(false);
+
+const PASTE_REQUEST_ID: &str = "clipboard::paste_request";
+
+/// Ask the backend to paste the Android clipboard into the focused widget
+/// on the next frame. Usable from UI code that has no access to the bridge.
+pub fn request_paste(ctx: &egui::Context) {
+    ctx.data_mut(|d| d.insert_temp(egui::Id::new(PASTE_REQUEST_ID), true));
+    ctx.request_repaint();
+}
+
+/// Attach a "Paste" context menu (opened by long-press or secondary click)
+/// to a text field.
+pub fn paste_menu(response: &egui::Response) {
+    response.context_menu(|ui| {
+        if ui.button("Paste").clicked() {
+            response.request_focus();
+            request_paste(ui.ctx());
+            ui.close();
+        }
+    });
+}
+
+/// Turn Ctrl+C / Ctrl+X key presses into egui's clipboard events.
+///
+/// Returns `true` if the Android clipboard should be read and delivered as
+/// an `Event::Paste`, either for Ctrl+V or a [`request_paste`]. Reading it
+/// is a JNI call, so it happens on the JNI thread and arrives a frame later.
+pub fn process_events(ctx: &egui::Context, events: &mut Vec<egui::Event>) -> bool {
+    let mut paste = false;
+    events.retain_mut(|event| {
+        let egui::Event::Key {
+            key,
+            pressed: true,
+            modifiers,
+            ..
+        } = event
+        else {
+            return true;
+        };
+        if !(modifiers.ctrl || modifiers.command) {
+            return true;
+        }
+        match key {
+            egui::Key::C => *event = egui::Event::Copy,
+            egui::Key::X => *event = egui::Event::Cut,
+            egui::Key::V => {
+                paste = true;
+                return false;
+            }
+            _ => {}
+        }
+        true
+    });
+
+    let requested = ctx.data_mut(|d| d.remove_temp::<bool>(egui::Id::new(PASTE_REQUEST_ID)));
+    paste || requested == Some(true)
+}
+
+type ChangeCallback = Box<dyn FnMut(&str)>;
+
+/// Render-thread side of change notifications, fed with the text of each
+/// `JniEvent::ClipboardChanged`.
+#[derive(Default)]
+pub struct Clipboard {
+    on_change: Option<ChangeCallback>,
+}
+
+impl Clipboard {
+    /// Called with the new text whenever the Android clipboard changes,
+    /// including changes made by other apps.
+    pub fn set_on_change(&mut self, on_change: impl FnMut(&str) + 'static) {
+        self.on_change = Some(Box::new(on_change));
+    }
+
+    /// The JNI thread saw the clipboard change to `text`.
+    pub fn changed(&mut self, text: &str) {
+        if let Some(on_change) = &mut self.on_change {
+            on_change(text);
+        }
+    }
+}
+
+/// Text egui wants copied (`OutputCommand::CopyText`).
+pub fn copied_texts(output: &egui::PlatformOutput) -> impl Iterator<Item = &str> {
+    output.commands.iter().filter_map(|command| match command {
+        egui::OutputCommand::CopyText(text) => Some(text.as_str()),
+        _ => None,
+    })
+}
+
+/// `ClipboardListener.onPrimaryClipChanged`, called on the looper thread.
+extern "system" fn on_primary_clip_changed(_env: JNIEnv, _this: JObject) {
+    CLIP_CHANGED.store(true, Ordering::Release);
+}
+
+/// A `ClipboardManager` with our listener added, dispatching on a looper
+/// thread of its own.
+///
+/// The system service's `ClipboardManager` posts listener calls to the main
+/// looper, which nothing runs in our process, so a second manager is made
+/// with the hidden `ClipboardManager(Context, Handler)` constructor.
+struct ClipListener {
+    looper: GlobalRef,
+    thread: Option<thread::JoinHandle<()>>,
+    /// The manager and listener, once added.
+    registration: Option<(GlobalRef, GlobalRef)>,
+}
+
+impl ClipListener {
+    fn start<'a>(bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>) -> Result<Self> {
+        let dex = ClassBuilder::new(LISTENER_CLASS)
+            .implements(LISTENER_INTERFACE)
+            .native_method("onPrimaryClipChanged", "()V")?
+            .to_dex()?;
+        let class = inject_dex(env, &dex)?.find_class(env, LISTENER_CLASS)?;
+        env.register_native_methods(
+            &class,
+            &[NativeMethod {
+                name: "onPrimaryClipChanged".into(),
+                sig: "()V".into(),
+                fn_ptr: on_primary_clip_changed as *mut c_void,
+            }],
+        )
+        .check_exception(env)?;
+        let listener = env.alloc_object(&class).check_exception(env)?;
+
+        let (looper, thread) = start_looper(env.get_java_vm()?)?;
+        let mut this = Self {
+            looper,
+            thread: Some(thread),
+            registration: None,
+        };
+        match this.register(bridge, env, listener) {
+            Ok(()) => Ok(this),
+            Err(e) => {
+                this.stop(env);
+                Err(e)
+            }
+        }
+    }
+
+    fn register<'a>(
+        &mut self,
+        bridge: &JavaBridge<'a>,
+        env: &mut JNIEnv<'a>,
+        listener: JObject<'a>,
+    ) -> Result<()> {
+        let handler = env
+            .new_object(
+                "android/os/Handler",
+                "(Landroid/os/Looper;)V",
+                &[JValue::Object(self.looper.as_obj())],
+            )
+            .check_exception(env)?;
+        let context = bridge.get_system_context(env)?;
+        let manager = env
+            .new_object(
+                "android/content/ClipboardManager",
+                "(Landroid/content/Context;Landroid/os/Handler;)V",
+                &[JValue::Object(&context), JValue::Object(&handler)],
+            )
+            .check_exception(env)
+            .context("ClipboardManager(Context, Handler)")?;
+        env.call_method(
+            &manager,
+            "addPrimaryClipChangedListener",
+            "(Landroid/content/ClipboardManager$OnPrimaryClipChangedListener;)V",
+            &[JValue::Object(&listener)],
+        )
+        .check_exception(env)?;
+        self.registration = Some((env.new_global_ref(manager)?, env.new_global_ref(listener)?));
+        Ok(())
+    }
+
+    /// Remove the listener and end the looper thread.
+    fn stop(&mut self, env: &mut JNIEnv) {
+        if let Some((manager, listener)) = self.registration.take()
+            && let Err(e) = env
+                .call_method(
+                    &manager,
+                    "removePrimaryClipChangedListener",
+                    "(Landroid/content/ClipboardManager$OnPrimaryClipChangedListener;)V",
+                    &[JValue::Object(listener.as_obj())],
+                )
+                .check_exception(env)
+        {
+            warn!("Failed to remove the clipboard listener: {e:#}");
+        }
+        if let Err(e) = env
+            .call_method(&self.looper, "quit", "()V", &[])
+            .check_exception(env)
+        {
+            warn!("Failed to stop the clipboard looper: {e:#}");
+            // Joining would block forever.
+            return;
+        }
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Clipboard looper thread panicked");
+        }
+    }
+}
+
+/// Start a thread running a `Looper` until it is quit, and return that
+/// looper.
+fn start_looper(vm: JavaVM) -> Result<(GlobalRef, thread::JoinHandle<()>)> {
+    let (looper_tx, looper_rx) = mpsc::channel();
+    let thread = thread::Builder::new()
+        .name("clipboard-looper".into())
+        .spawn(move || {
+            let _alt_stack = crate::crash::AltStack::install();
+            let mut env = match vm.attach_current_thread() {
+                Ok(env) => env,
+                Err(e) => {
+                    let _ = looper_tx.send(Err(e.into()));
+                    return;
+                }
+            };
+            let looper = prepare_looper(&mut env);
+            let prepared = looper.is_ok();
+            let _ = looper_tx.send(looper);
+            if prepared
+                && let Err(e) = env
+                    .call_static_method("android/os/Looper", "loop", "()V", &[])
+                    .check_exception(&mut env)
+            {
+                warn!("Clipboard looper failed: {e:#}");
+            }
+        })?;
+    match looper_rx.recv() {
+        Ok(Ok(looper)) => Ok((looper, thread)),
+        Ok(Err(e)) => {
+            let _ = thread.join();
+            Err(e)
+        }
+        Err(_) => {
+            let _ = thread.join();
+            anyhow::bail!("Clipboard looper thread exited")
+        }
+    }
+}
+
+fn prepare_looper(env: &mut JNIEnv) -> Result<GlobalRef> {
+    env.call_static_method("android/os/Looper", "prepare", "()V", &[])
+        .check_exception(env)?;
+    let looper = env
+        .call_static_method(
+            "android/os/Looper",
+            "myLooper",
+            "()Landroid/os/Looper;",
+            &[],
+        )
+        .check_exception(env)?
+        .l()?;
+    Ok(env.new_global_ref(looper)?)
+}
+
+/// JNI-thread side of the clipboard: reads, writes and change notifications
+/// from Android's `ClipboardManager`.
+pub struct ClipboardWatcher {
+    /// The clip as last read or written, to skip notifications about our
+    /// own copies.
+    last_text: Option<String>,
+    listener: Option<ClipListener>,
+}
+
+impl ClipboardWatcher {
+    pub fn new() -> Self {
+        Self {
+            last_text: None,
+            listener: None,
+        }
+    }
+
+    /// Listen for clipboard changes, including ones made by other apps.
+    pub fn listen<'a>(&mut self, bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>) {
+        match ClipListener::start(bridge, env) {
+            Ok(listener) => {
+                info!("Listening for clipboard changes");
+                self.listener = Some(listener);
+            }
+            Err(e) => warn!("Cannot listen for clipboard changes: {e:#}"),
+        }
+    }
+
+    pub fn read<'a>(&self, bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>) -> Option<String> {
+        bridge
+            .get_clipboard_text(env)
+            .map_err(|e| warn!("Failed to read clipboard: {e:#}"))
+            .ok()
+            .flatten()
+    }
+
+    pub fn write<'a>(&mut self, bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>, text: &str) {
+        match bridge.set_clipboard_text(env, text) {
+            Ok(true) => {
+                debug!("Copied {} bytes to clipboard", text.len());
+                // Our own copy is not an external change.
+                self.last_text = Some(text.to_owned());
+            }
+            Ok(false) => warn!("Clipboard service rejected copied text"),
+            Err(e) => warn!("Failed to set clipboard: {e:#}"),
+        }
+    }
+
+    /// The new text if the listener reported a change since the last call.
+    /// Only reads the clipboard after a notification, so cheap to call often.
+    pub fn take_change<'a>(
+        &mut self,
+        bridge: &JavaBridge<'a>,
+        env: &mut JNIEnv<'a>,
+    ) -> Option<String> {
+        if !CLIP_CHANGED.swap(false, Ordering::AcqRel) {
+            return None;
+        }
+        let text = self.read(bridge, env);
+        if text == self.last_text {
+            return None;
+        }
+        self.last_text = text.clone();
+        text
+    }
+
+    /// Stop listening.
+    pub fn stop(&mut self, env: &mut JNIEnv) {
+        if let Some(mut listener) = self.listener.take() {
+            listener.stop(env);
+        }
+    }
+}
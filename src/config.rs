@@ -0,0 +1,323 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use tracing::level_filters::LevelFilter;
+
+use crate::android::JavaVmOptions;
+use crate::renderer::EglConfigPreferences;
+
+/// Where saved state goes unless `--data-dir` says otherwise. Writable by
+/// both `shell` and `root`; the binary itself lives at
+/// `/data/local/tmp/android-egui-ext`, hence the suffix.
+const DEFAULT_DATA_DIR: &str = "/data/local/tmp/android-egui-ext-data";
+
+/// Environment variable holding the options in library mode, where the
+/// command line belongs to the host process.
+pub const ARGS_ENV: &str = "ANDROID_EGUI_EXT_ARGS";
+
+/// Tag of our logcat entries unless `--log-tag` says otherwise.
+const DEFAULT_LOG_TAG: &str = "android-egui-ext";
+
+/// Where log output goes (`--log`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogTarget {
+    Stdout,
+    Logcat,
+    /// Both, so logs survive running detached or through `su` without a
+    /// terminal.
+    #[default]
+    Both,
+}
+
+impl LogTarget {
+    pub fn stdout(self) -> bool {
+        matches!(self, Self::Stdout | Self::Both)
+    }
+
+    pub fn logcat(self) -> bool {
+        matches!(self, Self::Logcat | Self::Both)
+    }
+}
+
+/// How the standalone binary gets a VM (`--launch-mode`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LaunchMode {
+    /// Create the VM through `libandroid_runtime.so`, falling back to
+    /// `app_process` if that fails before the VM is up.
+    #[default]
+    Auto,
+    /// Always create the VM through `libandroid_runtime.so`.
+    Runtime,
+    /// Run `app_process` with the embedded dex and an entry class that loads
+    /// the library.
+    AppProcess,
+}
+
+/// Runtime settings taken from the command line (or, in library mode, the
+/// environment).
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Multiplier applied on top of the display density (`--ui-scale`).
+    pub ui_scale: Option<f32>,
+    /// Directory for persisted UI and app state (`--data-dir`).
+    pub data_dir: Option<PathBuf>,
+    /// `egui::Style` in RON to use as the theme (`--style-file`).
+    pub style_file: Option<PathBuf>,
+    /// Preferred display refresh rate in Hz (`--frame-rate`).
+    pub frame_rate: Option<f32>,
+    /// MSAA samples to ask EGL for, `0` for none (`--msaa`).
+    pub msaa_samples: u32,
+    /// Render into an sRGB window surface where supported (`--srgb`).
+    pub srgb: bool,
+    /// Extra `JNI_CreateJavaVM` options (`--vm-option`, repeatable).
+    pub vm_options: Vec<String>,
+    /// File with more VM options, one per line (`--vm-options-file`).
+    pub vm_options_file: Option<PathBuf>,
+    /// Enable CheckJNI (`--check-jni`).
+    pub check_jni: bool,
+    /// Fail to start on VM options ART doesn't know instead of ignoring them
+    /// (`--strict-vm-options`).
+    pub strict_vm_options: bool,
+    /// How to start the VM (`--launch-mode`).
+    pub launch_mode: LaunchMode,
+    /// `libandroid_egui_ext.so` for `app_process` mode (`--library`).
+    pub library: Option<PathBuf>,
+    /// Where logs go (`--log`).
+    pub log_target: LogTarget,
+    /// Tag of our logcat entries (`--log-tag`).
+    pub log_tag: Option<String>,
+    /// Most verbose level logged (`--log-level`).
+    pub log_level: Option<LevelFilter>,
+    /// Arguments we didn't recognize, reported once logging is up.
+    pub unknown_args: Vec<String>,
+}
+
+impl Config {
+    pub fn data_dir(&self) -> PathBuf {
+        self.data_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR))
+    }
+
+    pub fn log_tag(&self) -> &str {
+        self.log_tag.as_deref().unwrap_or(DEFAULT_LOG_TAG)
+    }
+
+    /// `--log-level`, else `RUST_LOG` if it is a plain level, else info.
+    pub fn log_level(&self) -> LevelFilter {
+        self.log_level
+            .or_else(|| std::env::var("RUST_LOG").ok()?.parse().ok())
+            .unwrap_or(LevelFilter::INFO)
+    }
+
+    /// What to ask of the EGL config and window surface.
+    pub fn egl_preferences(&self) -> EglConfigPreferences {
+        EglConfigPreferences {
+            msaa_samples: self.msaa_samples,
+            prefer_srgb: self.srgb,
+            ..Default::default()
+        }
+    }
+
+    /// Options for the VM we create, from `--vm-options-file`,
+    /// `--vm-option` and `--check-jni` in that order.
+    pub fn java_vm_options(&self) -> Result<JavaVmOptions> {
+        let mut options = JavaVmOptions::new().ignore_unrecognized(!self.strict_vm_options);
+        if let Some(path) = &self.vm_options_file {
+            options = options.options_file(path)?;
+        }
+        options = options.options(self.vm_options.iter().cloned());
+        if self.check_jni {
+            options = options.check_jni();
+        }
+        Ok(options)
+    }
+
+    pub fn from_args() -> Result<Self> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Options from `ANDROID_EGUI_EXT_ARGS`, split on whitespace. Empty if
+    /// it isn't set.
+    pub fn from_env() -> Result<Self> {
+        let args = std::env::var(ARGS_ENV).unwrap_or_default();
+        Self::parse(args.split_whitespace().map(str::to_owned))
+    }
+
+    /// Options from an argument list without the program name, e.g. the
+    /// one `app_process` hands its main class.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            // Flags without a value.
+            match arg.as_str() {
+                "--check-jni" => {
+                    config.check_jni = true;
+                    continue;
+                }
+                "--srgb" => {
+                    config.srgb = true;
+                    continue;
+                }
+                "--strict-vm-options" => {
+                    config.strict_vm_options = true;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let (key, inline_value) = match arg.split_once('=') {
+                Some((key, value)) => (key.to_owned(), Some(value.to_owned())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .with_context(|| format!("{key} expects a value"))
+            };
+
+            match key.as_str() {
+                "--ui-scale" => {
+                    let scale = value()?
+                        .parse::<f32>()
+                        .context("--ui-scale expects a number")?;
+                    anyhow::ensure!(scale > 0.0, "--ui-scale must be positive");
+                    config.ui_scale = Some(scale);
+                }
+                "--msaa" => {
+                    config.msaa_samples = value()?
+                        .parse::<u32>()
+                        .context("--msaa expects a sample count")?;
+                }
+                "--data-dir" => config.data_dir = Some(PathBuf::from(value()?)),
+                "--style-file" => config.style_file = Some(PathBuf::from(value()?)),
+                "--vm-option" => config.vm_options.push(value()?),
+                "--vm-options-file" => config.vm_options_file = Some(PathBuf::from(value()?)),
+                "--launch-mode" => {
+                    config.launch_mode = match value()?.as_str() {
+                        "auto" => LaunchMode::Auto,
+                        "runtime" => LaunchMode::Runtime,
+                        "app-process" => LaunchMode::AppProcess,
+                        other => anyhow::bail!(
+                            "--launch-mode expects auto, runtime or app-process, not {other:?}"
+                        ),
+                    }
+                }
+                "--library" => config.library = Some(PathBuf::from(value()?)),
+                "--log" => {
+                    config.log_target = match value()?.as_str() {
+                        "stdout" => LogTarget::Stdout,
+                        "logcat" => LogTarget::Logcat,
+                        "both" => LogTarget::Both,
+                        other => {
+                            anyhow::bail!("--log expects stdout, logcat or both, not {other:?}")
+                        }
+                    }
+                }
+                "--log-tag" => config.log_tag = Some(value()?),
+                "--log-level" => {
+                    let level = value()?;
+                    config.log_level = Some(level.parse().with_context(|| {
+                        format!(
+                            "--log-level expects off, error, warn, info, debug or trace, not {level:?}"
+                        )
+                    })?);
+                }
+                "--frame-rate" => {
+                    let rate = value()?
+                        .parse::<f32>()
+                        .context("--frame-rate expects a number")?;
+                    anyhow::ensure!(rate > 0.0, "--frame-rate must be positive");
+                    config.frame_rate = Some(rate);
+                }
+                _ => config.unknown_args.push(key),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Config> {
+        Config::parse(args.split_whitespace().map(str::to_owned))
+    }
+
+    #[test]
+    fn empty_command_line_gives_defaults() {
+        let config = parse("").unwrap();
+        assert_eq!(config.ui_scale, None);
+        assert_eq!(config.launch_mode, LaunchMode::Auto);
+        assert_eq!(config.log_target, LogTarget::Both);
+        assert!(!config.check_jni);
+        assert!(config.unknown_args.is_empty());
+    }
+
+    #[test]
+    fn values_may_be_inline_or_separate() {
+        let config = parse("--ui-scale=1.5 --msaa 4 --data-dir /tmp/x").unwrap();
+        assert_eq!(config.ui_scale, Some(1.5));
+        assert_eq!(config.msaa_samples, 4);
+        assert_eq!(config.data_dir(), PathBuf::from("/tmp/x"));
+    }
+
+    #[test]
+    fn vm_options_keep_their_own_equals_signs() {
+        let config = parse("--vm-option=-Dkey=value --vm-option -Xmx256m --check-jni").unwrap();
+        assert_eq!(config.vm_options, ["-Dkey=value", "-Xmx256m"]);
+        assert_eq!(
+            config.java_vm_options().unwrap().as_slice(),
+            ["-Dkey=value", "-Xmx256m", "-Xcheck:jni"]
+        );
+    }
+
+    #[test]
+    fn flags_take_no_value() {
+        let config = parse("--srgb --strict-vm-options --check-jni --frame-rate 90").unwrap();
+        assert!(config.srgb);
+        assert!(config.strict_vm_options);
+        assert!(config.check_jni);
+        assert_eq!(config.frame_rate, Some(90.0));
+    }
+
+    #[test]
+    fn enums_are_parsed() {
+        let config = parse("--launch-mode app-process --log logcat").unwrap();
+        assert_eq!(config.launch_mode, LaunchMode::AppProcess);
+        assert_eq!(config.log_target, LogTarget::Logcat);
+        assert!(parse("--launch-mode fork").is_err());
+        assert!(parse("--log syslog").is_err());
+    }
+
+    #[test]
+    fn log_level_is_parsed() {
+        let config = parse("--log-level debug").unwrap();
+        assert_eq!(config.log_level(), LevelFilter::DEBUG);
+        assert_eq!(
+            parse("--log-level=OFF").unwrap().log_level,
+            Some(LevelFilter::OFF)
+        );
+        assert!(parse("--log-level loud").is_err());
+    }
+
+    #[test]
+    fn bad_values_are_errors() {
+        assert!(parse("--ui-scale").is_err());
+        assert!(parse("--ui-scale big").is_err());
+        assert!(parse("--ui-scale 0").is_err());
+        assert!(parse("--msaa -1").is_err());
+        assert!(parse("--frame-rate=-60").is_err());
+    }
+
+    #[test]
+    fn unknown_arguments_are_collected() {
+        let config = parse("--fullscreen --color=red").unwrap();
+        assert_eq!(config.unknown_args, ["--fullscreen", "--color"]);
+    }
+}
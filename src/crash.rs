@@ -0,0 +1,443 @@
+//! Crash reports: a panic hook and handlers for fatal signals that unwind
+//! the stack, symbolicate it with xdl (which also reads `.symtab` and
+//! minidebuginfo of stripped system libraries) and write the result to a
+//! file in the data directory and to logcat's crash buffer.
+//!
+//! Install before creating the VM. ART's sigchain then keeps our SIGSEGV
+//! handler as the fallback for faults that aren't its own (implicit null
+//! checks, stack overflow checks); installed later, we would replace it.
+//!
+//! Handlers run on an alternate signal stack, so a stack overflow can be
+//! reported too: the thread calling [`install`] gets one for good, and
+//! every thread this crate spawns sets up an [`AltStack`] first.
+//!
+//! Reports are formatted into a static buffer and written with plain
+//! syscalls, so the handler itself doesn't allocate. Symbolication and
+//! liblog still aren't async-signal-safe; it is best effort, since the
+//! process is going down anyway.
+
+use std::cell::UnsafeCell;
+use std::ffi::{CStr, CString, c_int, c_void};
+use std::fmt::Write as _;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::logcat::{self, Buffer, Priority};
+
+const SIGNALS: [c_int; 3] = [libc::SIGSEGV, libc::SIGBUS, libc::SIGABRT];
+
+/// Enough for the handler, the unwinder and xdl.
+const ALT_STACK_SIZE: usize = 256 * 1024;
+
+const MAX_FRAMES: usize = 64;
+
+/// Longest report; the rest of a longer backtrace is cut off.
+const REPORT_SIZE: usize = 16 * 1024;
+
+/// Where reports go and how they are tagged, set by [`install`].
+struct Target {
+    dir: PathBuf,
+    tag: CString,
+}
+
+static TARGET: OnceLock<Target> = OnceLock::new();
+
+/// Handlers that were installed before ours, restored before re-raising so
+/// debuggerd still writes its tombstone.
+static PREVIOUS: OnceLock<[libc::sigaction; SIGNALS.len()]> = OnceLock::new();
+
+/// Set while a report is written, so a crash inside the reporter (or the
+/// abort that follows a panic) doesn't report again.
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+/// The report being written; only touched by whoever set [`REPORTING`].
+static REPORT: Report = Report(UnsafeCell::new(FixedBuffer::new()));
+
+struct Report(UnsafeCell<ReportBuffer>);
+
+unsafe impl Sync for Report {}
+
+type ReportBuffer = FixedBuffer<REPORT_SIZE>;
+
+/// Text formatted in place, dropping whatever doesn't fit.
+struct FixedBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedBuffer<N> {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        let end = bytes.len().min(N - self.len);
+        self.bytes[self.len..self.len + end].copy_from_slice(&bytes[..end]);
+        self.len += end;
+    }
+
+    fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+
+    /// `None` if it is full, as the text may have been cut off.
+    fn as_c_str(&mut self) -> Option<&CStr> {
+        if self.len == N {
+            return None;
+        }
+        self.bytes[self.len] = 0;
+        CStr::from_bytes_with_nul(&self.bytes[..=self.len]).ok()
+    }
+}
+
+impl<const N: usize> std::fmt::Write for FixedBuffer<N> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        // Whole characters only, so the text stays valid UTF-8.
+        let mut end = s.len().min(N - self.len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.push_bytes(&s.as_bytes()[..end]);
+        Ok(())
+    }
+}
+
+/// The report buffer, emptied.
+///
+/// # Safety
+///
+/// Only while holding [`REPORTING`], and not twice at once.
+unsafe fn report() -> &'static mut ReportBuffer {
+    let report = unsafe { &mut *REPORT.0.get() };
+    report.len = 0;
+    report
+}
+
+/// Install the panic hook and fatal signal handlers, with an alternate
+/// signal stack for the calling thread.
+pub fn install(config: &Config) -> Result<()> {
+    let dir = config.data_dir();
+    // Not from the signal handler, which can't allocate.
+    let _ = std::fs::create_dir_all(&dir);
+    let _ = TARGET.set(Target {
+        dir,
+        tag: CString::new(config.log_tag().replace('\0', "")).unwrap_or_default(),
+    });
+
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic| {
+        if !REPORTING.swap(true, Ordering::SeqCst) {
+            let report = unsafe { report() };
+            let mut name = [0; 16];
+            let _ = writeln!(
+                report,
+                "panic in thread '{}': {}",
+                thread_name(&mut name),
+                panic
+            );
+            let frames = Frames::capture();
+            write_backtrace(report, frames.as_slice());
+            save(report.as_str());
+            // With unwinding the process may live on and crash for real later.
+            if cfg!(panic = "unwind") {
+                REPORTING.store(false, Ordering::SeqCst);
+            }
+        }
+        previous_hook(panic);
+    }));
+
+    // The calling thread keeps it for good.
+    std::mem::forget(AltStack::new()?);
+
+    let mut previous = [unsafe { std::mem::zeroed::<libc::sigaction>() }; SIGNALS.len()];
+    for (signal, previous) in SIGNALS.iter().zip(&mut previous) {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        if unsafe { libc::sigaction(*signal, &action, previous) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    let _ = PREVIOUS.set(previous);
+
+    info!("Crash reports go to {}", config.data_dir().display());
+    Ok(())
+}
+
+/// An alternate signal stack of [`ALT_STACK_SIZE`] for the current thread,
+/// until dropped. The one std sets up for its own threads only has room to
+/// print a stack overflow message, not for the unwinder and xdl.
+pub struct AltStack {
+    stack: *mut c_void,
+    previous: libc::stack_t,
+}
+
+impl AltStack {
+    /// Set one up if crash reporting is installed; call first thing in a new
+    /// thread and keep it until the thread is done.
+    pub fn install() -> Option<Self> {
+        PREVIOUS.get()?;
+        Self::new()
+            .map_err(|e| warn!("No signal stack for crash reports on this thread: {e:#}"))
+            .ok()
+    }
+
+    fn new() -> Result<Self> {
+        let stack = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                ALT_STACK_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if stack == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let alt_stack = libc::stack_t {
+            ss_sp: stack,
+            ss_flags: 0,
+            ss_size: ALT_STACK_SIZE,
+        };
+        let mut previous = unsafe { std::mem::zeroed::<libc::stack_t>() };
+        if unsafe { libc::sigaltstack(&alt_stack, &mut previous) } != 0 {
+            let error = std::io::Error::last_os_error();
+            unsafe { libc::munmap(stack, ALT_STACK_SIZE) };
+            return Err(error.into());
+        }
+        Ok(Self { stack, previous })
+    }
+}
+
+impl Drop for AltStack {
+    fn drop(&mut self) {
+        // Back to std's, which is still there until the thread exits.
+        unsafe {
+            libc::sigaltstack(&self.previous, std::ptr::null_mut());
+            libc::munmap(self.stack, ALT_STACK_SIZE);
+        }
+    }
+}
+
+extern "C" fn on_signal(signal: c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    if !REPORTING.swap(true, Ordering::SeqCst) {
+        let report = unsafe { report() };
+        describe_signal(report, signal, info);
+        let mut frames = Frames::capture();
+        // The unwinder may not see through the signal frame; the faulting
+        // pc comes first either way.
+        if let Some(pc) = unsafe { faulting_pc(context) }
+            && frames.as_slice().first() != Some(&pc)
+        {
+            frames.prepend(pc);
+        }
+        write_backtrace(report, frames.as_slice());
+        save(report.as_str());
+    }
+
+    // Hand over to whatever was there before (debuggerd, or the default
+    // action). Returning re-runs the faulting instruction; abort() raises
+    // again by itself.
+    if let Some(previous) = PREVIOUS.get()
+        && let Some(index) = SIGNALS.iter().position(|s| *s == signal)
+    {
+        unsafe { libc::sigaction(signal, &previous[index], std::ptr::null_mut()) };
+    }
+    if !info.is_null() && unsafe { (*info).si_code } <= 0 {
+        // Sent by kill()/tgkill(); nothing will raise it again.
+        unsafe { libc::raise(signal) };
+    }
+}
+
+fn describe_signal(report: &mut ReportBuffer, signal: c_int, info: *const libc::siginfo_t) {
+    let name = match signal {
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGABRT => "SIGABRT",
+        _ => "signal",
+    };
+    let mut thread = [0; 16];
+    let _ = write!(
+        report,
+        "{name} ({signal}) in thread '{}' (tid {})",
+        thread_name(&mut thread),
+        unsafe { libc::gettid() }
+    );
+    if !info.is_null() {
+        let (code, addr) = unsafe { ((*info).si_code, (*info).si_addr()) };
+        let _ = write!(report, ", code {code}, fault addr {addr:p}");
+    }
+    let _ = report.write_char('\n');
+}
+
+/// The kernel's name for the current thread (std sets it from
+/// `thread::Builder::name`), without allocating.
+fn thread_name(buf: &mut [u8; 16]) -> &str {
+    unsafe { libc::prctl(libc::PR_GET_NAME, buf.as_mut_ptr()) };
+    CStr::from_bytes_until_nul(buf)
+        .ok()
+        .and_then(|name| name.to_str().ok())
+        .unwrap_or("<unnamed>")
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn faulting_pc(context: *mut c_void) -> Option<usize> {
+    let context = context as *const libc::ucontext_t;
+    (!context.is_null()).then(|| unsafe { (*context).uc_mcontext.pc as usize })
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn faulting_pc(context: *mut c_void) -> Option<usize> {
+    let context = context as *const libc::ucontext_t;
+    (!context.is_null())
+        .then(|| unsafe { (*context).uc_mcontext.gregs[libc::REG_RIP as usize] as usize })
+}
+
+#[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+unsafe fn faulting_pc(_context: *mut c_void) -> Option<usize> {
+    None
+}
+
+/// Return addresses of the current stack, without allocating.
+struct Frames {
+    pcs: [usize; MAX_FRAMES],
+    len: usize,
+}
+
+/// `_URC_NO_REASON` / `_URC_END_OF_STACK`.
+const URC_NO_REASON: c_int = 0;
+const URC_END_OF_STACK: c_int = 5;
+
+unsafe extern "C" {
+    fn _Unwind_Backtrace(
+        trace: extern "C" fn(*mut c_void, *mut c_void) -> c_int,
+        arg: *mut c_void,
+    ) -> c_int;
+    fn _Unwind_GetIP(context: *mut c_void) -> usize;
+}
+
+impl Frames {
+    fn capture() -> Self {
+        extern "C" fn collect(context: *mut c_void, arg: *mut c_void) -> c_int {
+            let frames = unsafe { &mut *(arg as *mut Frames) };
+            let pc = unsafe { _Unwind_GetIP(context) };
+            if pc == 0 || frames.len == MAX_FRAMES {
+                return URC_END_OF_STACK;
+            }
+            frames.pcs[frames.len] = pc;
+            frames.len += 1;
+            URC_NO_REASON
+        }
+
+        let mut frames = Self {
+            pcs: [0; MAX_FRAMES],
+            len: 0,
+        };
+        unsafe { _Unwind_Backtrace(collect, &mut frames as *mut Self as *mut c_void) };
+        frames
+    }
+
+    fn prepend(&mut self, pc: usize) {
+        let len = self.len.min(MAX_FRAMES - 1);
+        self.pcs.copy_within(..len, 1);
+        self.pcs[0] = pc;
+        self.len = len + 1;
+    }
+
+    fn as_slice(&self) -> &[usize] {
+        &self.pcs[..self.len]
+    }
+}
+
+/// Append tombstone-style lines: `#00 pc <offset>  <library> (<symbol>+<offset>)`.
+fn write_backtrace(report: &mut ReportBuffer, pcs: &[usize]) {
+    let _ = report.write_str("\nbacktrace:\n");
+    let mut cache = std::ptr::null_mut();
+    for (index, &pc) in pcs.iter().enumerate() {
+        // Return addresses point after the call; look up the call itself.
+        let lookup = if index == 0 { pc } else { pc.saturating_sub(1) };
+        let mut info: xdl_rs::xdl_info_t = unsafe { std::mem::zeroed() };
+        let found = unsafe { xdl_rs::addr(lookup as *mut c_void, &mut info, &mut cache) } != 0;
+
+        let _ = write!(report, "  #{index:02} pc ");
+        if !found || info.dli_fname.is_null() {
+            let _ = writeln!(report, "{pc:016x}  <unknown>");
+            continue;
+        }
+        let library = unsafe { CStr::from_ptr(info.dli_fname) }
+            .to_str()
+            .unwrap_or("?");
+        let _ = write!(report, "{:016x}  {}", pc - info.dli_fbase as usize, library);
+        if !info.dli_sname.is_null() {
+            let symbol = unsafe { CStr::from_ptr(info.dli_sname) }
+                .to_str()
+                .unwrap_or("?");
+            let _ = write!(report, " ({}+{})", symbol, pc - info.dli_saddr as usize);
+        }
+        let _ = report.write_char('\n');
+    }
+    unsafe { xdl_rs::addr_clean(&mut cache) };
+}
+
+/// Write the report to stderr, logcat and a `crash-<time>.txt` file, without
+/// allocating.
+fn save(report: &str) {
+    write_fd(libc::STDERR_FILENO, report);
+    let Some(target) = TARGET.get() else {
+        return;
+    };
+    logcat::write(Buffer::Crash, Priority::Fatal, &target.tag, report);
+
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut path = FixedBuffer::<{ libc::PATH_MAX as usize }>::new();
+    path.push_bytes(target.dir.as_os_str().as_bytes());
+    let _ = write!(path, "/crash-{secs}.txt");
+    let Some(path) = path.as_c_str() else {
+        return;
+    };
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC,
+            0o644,
+        )
+    };
+    let path = path.to_str().unwrap_or("crash report");
+    if fd < 0 {
+        write_fd(libc::STDERR_FILENO, "Cannot write ");
+        write_fd(libc::STDERR_FILENO, path);
+        write_fd(libc::STDERR_FILENO, "\n");
+        return;
+    }
+    write_fd(fd, report);
+    unsafe { libc::close(fd) };
+    write_fd(libc::STDERR_FILENO, "Crash report saved to ");
+    write_fd(libc::STDERR_FILENO, path);
+    write_fd(libc::STDERR_FILENO, "\n");
+}
+
+fn write_fd(fd: c_int, text: &str) {
+    let mut rest = text.as_bytes();
+    while !rest.is_empty() {
+        let written = unsafe { libc::write(fd, rest.as_ptr() as *const c_void, rest.len()) };
+        if written <= 0 {
+            return;
+        }
+        rest = &rest[written as usize..];
+    }
+}
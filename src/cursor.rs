@@ -0,0 +1,174 @@
+use egui::{Color32, CursorIcon, Pos2, Shape, Stroke, pos2, vec2};
+
+/// Cursor drawn by egui itself, since the overlay surface has no system
+/// pointer of its own.
+///
+/// It is only shown while a mouse is in use: pointer movement without any
+/// accompanying touch events. Touching the screen hides it again.
+#[derive(Default)]
+pub struct SoftwareCursor {
+    icon: CursorIcon,
+    pos: Option<Pos2>,
+    mouse_active: bool,
+}
+
+impl SoftwareCursor {
+    /// Inspect the input events egui got this frame (in points) to track the
+    /// pointer and whether it is driven by a mouse or a finger.
+    pub fn observe_events(&mut self, events: &[egui::Event]) {
+        let has_touch = events
+            .iter()
+            .any(|e| matches!(e, egui::Event::Touch { .. }));
+        for event in events {
+            match event {
+                egui::Event::PointerMoved(pos) => {
+                    self.pos = Some(*pos);
+                    if !has_touch {
+                        self.mouse_active = true;
+                    }
+                }
+                egui::Event::PointerGone => self.pos = None,
+                egui::Event::Touch { .. } => self.mouse_active = false,
+                _ => {}
+            }
+        }
+    }
+
+    /// Cursor requested by the previous frame's `PlatformOutput`.
+    pub fn set_icon(&mut self, icon: CursorIcon) {
+        self.icon = icon;
+    }
+
+    /// Paint the cursor on top of everything. Call at the end of the UI pass.
+    pub fn paint(&self, ctx: &egui::Context) {
+        let Some(pos) = self.pos else {
+            return;
+        };
+        if !self.mouse_active || self.icon == CursorIcon::None {
+            return;
+        }
+
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Debug,
+            egui::Id::new("software_cursor"),
+        ));
+        for shape in cursor_shapes(self.icon, pos) {
+            painter.add(shape);
+        }
+    }
+}
+
+fn outline() -> Stroke {
+    Stroke::new(1.0, Color32::BLACK)
+}
+
+fn double_arrow(center: Pos2, dir: egui::Vec2) -> Vec<Shape> {
+    let half = dir.normalized() * 9.0;
+    let side = half.rot90() * 0.45;
+    let head = |tip: Pos2, back: egui::Vec2| {
+        Shape::convex_polygon(
+            vec![tip, tip + back * 0.5 + side, tip + back * 0.5 - side],
+            Color32::WHITE,
+            outline(),
+        )
+    };
+    vec![
+        Shape::line_segment(
+            [center - half, center + half],
+            Stroke::new(3.0, Color32::BLACK),
+        ),
+        Shape::line_segment(
+            [center - half, center + half],
+            Stroke::new(1.5, Color32::WHITE),
+        ),
+        head(center + half, -half),
+        head(center - half, half),
+    ]
+}
+
+fn cursor_shapes(icon: CursorIcon, pos: Pos2) -> Vec<Shape> {
+    match icon {
+        CursorIcon::Text | CursorIcon::VerticalText => {
+            let (w, h) = (4.0, 9.0);
+            let stroke_w = Stroke::new(3.0, Color32::BLACK);
+            let stroke_b = Stroke::new(1.5, Color32::WHITE);
+            let lines = [
+                [pos2(pos.x, pos.y - h), pos2(pos.x, pos.y + h)],
+                [pos2(pos.x - w, pos.y - h), pos2(pos.x + w, pos.y - h)],
+                [pos2(pos.x - w, pos.y + h), pos2(pos.x + w, pos.y + h)],
+            ];
+            lines
+                .iter()
+                .map(|l| Shape::line_segment(*l, stroke_w))
+                .chain(lines.iter().map(|l| Shape::line_segment(*l, stroke_b)))
+                .collect()
+        }
+        CursorIcon::PointingHand | CursorIcon::Grab | CursorIcon::Grabbing => {
+            let radius = if icon == CursorIcon::Grabbing {
+                5.0
+            } else {
+                7.0
+            };
+            vec![
+                Shape::circle_filled(pos, radius, Color32::WHITE),
+                Shape::circle_stroke(pos, radius, outline()),
+            ]
+        }
+        CursorIcon::NotAllowed | CursorIcon::NoDrop => {
+            let r = 7.0;
+            let slash = vec2(r, -r) * std::f32::consts::FRAC_1_SQRT_2;
+            vec![
+                Shape::circle_stroke(pos, r, Stroke::new(2.0, Color32::RED)),
+                Shape::line_segment([pos - slash, pos + slash], Stroke::new(2.0, Color32::RED)),
+            ]
+        }
+        CursorIcon::Wait | CursorIcon::Progress => vec![
+            Shape::circle_stroke(pos, 7.0, Stroke::new(3.0, Color32::BLACK)),
+            Shape::circle_stroke(pos, 7.0, Stroke::new(1.5, Color32::WHITE)),
+        ],
+        CursorIcon::Crosshair | CursorIcon::Cell => {
+            let d = 8.0;
+            vec![
+                Shape::line_segment([pos - vec2(d, 0.0), pos + vec2(d, 0.0)], outline()),
+                Shape::line_segment([pos - vec2(0.0, d), pos + vec2(0.0, d)], outline()),
+            ]
+        }
+        CursorIcon::ResizeHorizontal
+        | CursorIcon::ResizeEast
+        | CursorIcon::ResizeWest
+        | CursorIcon::ResizeColumn => double_arrow(pos, vec2(1.0, 0.0)),
+        CursorIcon::ResizeVertical
+        | CursorIcon::ResizeNorth
+        | CursorIcon::ResizeSouth
+        | CursorIcon::ResizeRow => double_arrow(pos, vec2(0.0, 1.0)),
+        CursorIcon::ResizeNwSe | CursorIcon::ResizeNorthWest | CursorIcon::ResizeSouthEast => {
+            double_arrow(pos, vec2(1.0, 1.0))
+        }
+        CursorIcon::ResizeNeSw | CursorIcon::ResizeNorthEast | CursorIcon::ResizeSouthWest => {
+            double_arrow(pos, vec2(1.0, -1.0))
+        }
+        CursorIcon::Move | CursorIcon::AllScroll => {
+            let mut shapes = double_arrow(pos, vec2(1.0, 0.0));
+            shapes.extend(double_arrow(pos, vec2(0.0, 1.0)));
+            shapes
+        }
+        _ => {
+            // Classic arrow with its tip at the pointer position.
+            let points = [
+                (0.0, 0.0),
+                (0.0, 16.0),
+                (4.0, 12.0),
+                (7.0, 18.0),
+                (9.5, 17.0),
+                (6.5, 11.0),
+                (12.0, 11.0),
+            ]
+            .map(|(x, y)| pos + vec2(x, y))
+            .to_vec();
+            vec![
+                Shape::closed_line(points.clone(), Stroke::new(2.0, Color32::BLACK)),
+                Shape::convex_polygon(points, Color32::WHITE, Stroke::NONE),
+            ]
+        }
+    }
+}
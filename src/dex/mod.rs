@@ -7,6 +7,7 @@ use jni::{
 use crate::jni::jni_result_ext::JniResultExt;
 
 pub mod util;
+pub mod writer;
 
 #[derive(Debug)]
 pub struct ClassLoader<'local>(pub JObject<'local>);
@@ -1,19 +1,22 @@
 use anyhow::{Context, Result};
 use jni::{JNIEnv, objects::JValue};
 
-use crate::{android::get_api_level, dex::ClassLoader, jni::jni_result_ext::JniResultExt};
+use crate::{android::DeviceInfo, dex::ClassLoader, jni::jni_result_ext::JniResultExt};
 
 pub fn inject_dex<'local>(
     env: &mut JNIEnv<'local>,
     dex_bytes: &[u8],
 ) -> Result<ClassLoader<'local>> {
-    let api = get_api_level().context("getting android version")?;
-    debug_assert_ne!(api, 0);
-    if api >= 26 {
+    let device = DeviceInfo::current().context("getting android version")?;
+    // InMemoryDexClassLoader is API 26+.
+    if device.is_at_least(26) {
         tracing::info!("Injecting dex from memory");
         return load_dex_from_memory(env, dex_bytes);
     }
-    unimplemented!("Loading dex not not implemented for api level {api}");
+    anyhow::bail!(
+        "Loading a dex from memory needs API 26, this is API {}",
+        device.sdk_int
+    );
 }
 
 fn load_dex_from_memory<'local>(
@@ -49,6 +52,25 @@ fn load_dex_from_memory<'local>(
         .find_class("dalvik/system/InMemoryDexClassLoader")
         .check_exception(env)?;
 
+    let system_class_loader = system_class_loader(env)?;
+
+    let class_loader = env
+        .new_object(
+            in_memory_dex_class_loader_class,
+            "([Ljava/nio/ByteBuffer;Ljava/lang/ClassLoader;)V",
+            &[
+                JValue::Object(&dex_buffers),
+                JValue::Object(&system_class_loader.0),
+            ],
+        )
+        .check_exception(env)?;
+
+    Ok(ClassLoader(class_loader))
+}
+
+/// `ClassLoader.getSystemClassLoader()`: the class path of the process, e.g.
+/// `CLASSPATH` under `app_process`.
+pub fn system_class_loader<'local>(env: &mut JNIEnv<'local>) -> Result<ClassLoader<'local>> {
     let class_loader_class = env
         .find_class("java/lang/ClassLoader")
         .check_exception(env)?;
@@ -63,14 +85,22 @@ fn load_dex_from_memory<'local>(
         .check_exception(env)?
         .l()?;
 
+    Ok(ClassLoader(system_class_loader))
+}
+
+/// A `PathClassLoader` over `path` (a jar or dex) with the system class
+/// loader as parent. Native libraries are looked up in the system paths.
+pub fn path_class_loader<'local>(
+    env: &mut JNIEnv<'local>,
+    path: &str,
+) -> Result<ClassLoader<'local>> {
+    let parent = system_class_loader(env)?;
+    let path = env.new_string(path).check_exception(env)?;
     let class_loader = env
         .new_object(
-            in_memory_dex_class_loader_class,
-            "([Ljava/nio/ByteBuffer;Ljava/lang/ClassLoader;)V",
-            &[
-                JValue::Object(&dex_buffers),
-                JValue::Object(&system_class_loader),
-            ],
+            "dalvik/system/PathClassLoader",
+            "(Ljava/lang/String;Ljava/lang/ClassLoader;)V",
+            &[JValue::Object(&path), JValue::Object(&parent.0)],
         )
         .check_exception(env)?;
 
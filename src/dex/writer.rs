@@ -0,0 +1,770 @@
+//! Builds single-class dex files at runtime, for the few classes the
+//! embedded dex lacks, e.g. a listener whose methods are implemented in Rust
+//! and bound with `RegisterNatives`, or a small stub whose [`Code`] calls
+//! into other classes.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Context, Result, bail, ensure};
+
+const ACC_PUBLIC: u32 = 0x1;
+const ACC_STATIC: u32 = 0x8;
+const ACC_NATIVE: u32 = 0x100;
+
+const HEADER_SIZE: u32 = 0x70;
+const ENDIAN_CONSTANT: u32 = 0x1234_5678;
+const NO_INDEX: u32 = u32::MAX;
+
+const TYPE_HEADER_ITEM: u16 = 0x0000;
+const TYPE_STRING_ID_ITEM: u16 = 0x0001;
+const TYPE_TYPE_ID_ITEM: u16 = 0x0002;
+const TYPE_PROTO_ID_ITEM: u16 = 0x0003;
+const TYPE_METHOD_ID_ITEM: u16 = 0x0005;
+const TYPE_CLASS_DEF_ITEM: u16 = 0x0006;
+const TYPE_MAP_LIST: u16 = 0x1000;
+const TYPE_TYPE_LIST: u16 = 0x1001;
+const TYPE_CLASS_DATA_ITEM: u16 = 0x2000;
+const TYPE_CODE_ITEM: u16 = 0x2001;
+const TYPE_STRING_DATA_ITEM: u16 = 0x2002;
+
+/// A method signature split into descriptors, ordered the way `proto_ids`
+/// must be: by return type, then parameters.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Proto {
+    return_type: String,
+    parameters: Vec<String>,
+}
+
+impl Proto {
+    /// Parse a JNI-style signature such as `(Ljava/lang/String;I)V`.
+    fn parse(signature: &str) -> Result<Self> {
+        let (parameters, return_type) = signature
+            .strip_prefix('(')
+            .and_then(|rest| rest.split_once(')'))
+            .with_context(|| format!("malformed signature {signature:?}"))?;
+        let mut rest = parameters;
+        let mut parsed = Vec::new();
+        while !rest.is_empty() {
+            let (descriptor, tail) = split_descriptor(rest)
+                .with_context(|| format!("malformed signature {signature:?}"))?;
+            parsed.push(descriptor.to_owned());
+            rest = tail;
+        }
+        ensure!(
+            return_type == "V" || split_descriptor(return_type) == Some((return_type, "")),
+            "malformed signature {signature:?}"
+        );
+        Ok(Self {
+            return_type: return_type.to_owned(),
+            parameters: parsed,
+        })
+    }
+
+    /// One character per type, return type first, references as `L`.
+    fn shorty(&self) -> String {
+        std::iter::once(&self.return_type)
+            .chain(&self.parameters)
+            .map(|descriptor| match descriptor.as_bytes()[0] {
+                b'[' => 'L',
+                c => c as char,
+            })
+            .collect()
+    }
+
+    /// Registers taken by the parameters; `long` and `double` take two.
+    fn ins_size(&self) -> u16 {
+        self.parameters
+            .iter()
+            .map(|descriptor| match descriptor.as_str() {
+                "J" | "D" => 2,
+                _ => 1,
+            })
+            .sum()
+    }
+}
+
+/// Split the first field type descriptor off `s`.
+fn split_descriptor(s: &str) -> Option<(&str, &str)> {
+    let dims = s.bytes().take_while(|&c| c == b'[').count();
+    let end = match *s.as_bytes().get(dims)? {
+        b'Z' | b'B' | b'S' | b'C' | b'I' | b'J' | b'F' | b'D' => dims + 1,
+        b'L' => dims + s[dims..].find(';')? + 1,
+        _ => return None,
+    };
+    Some(s.split_at(end))
+}
+
+/// `a.b.C` to `La/b/C;`.
+fn class_descriptor(name: &str) -> String {
+    format!("L{};", name.replace('.', "/"))
+}
+
+/// A method of any class. Class, name then proto orders `method_ids`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct MethodRef {
+    class: String,
+    name: String,
+    proto: Proto,
+}
+
+struct Method {
+    name: String,
+    proto: Proto,
+    access_flags: u32,
+    code: Option<Code>,
+}
+
+impl Method {
+    fn key(&self, class: &str) -> MethodRef {
+        MethodRef {
+            class: class.to_owned(),
+            name: self.name.clone(),
+            proto: self.proto.clone(),
+        }
+    }
+
+    /// Static methods go in the class data's direct list.
+    fn is_direct(&self) -> bool {
+        self.access_flags & ACC_STATIC != 0
+    }
+}
+
+/// A method body in Dalvik bytecode. Strings and methods are referenced by
+/// name and turned into indices by [`ClassBuilder::to_dex`]. Only the few
+/// instructions a stub needs are supported.
+pub struct Code {
+    registers: u16,
+    insns: Vec<Insn>,
+}
+
+enum Insn {
+    ConstString { register: u8, value: String },
+    InvokeStatic { method: MethodRef, args: Vec<u8> },
+    MoveResultObject(u8),
+    ReturnVoid,
+}
+
+impl Code {
+    /// A body using `registers` registers, the parameters being the last
+    /// ones.
+    pub fn new(registers: u16) -> Self {
+        Self {
+            registers,
+            insns: Vec::new(),
+        }
+    }
+
+    /// `const-string`.
+    pub fn const_string(mut self, register: u8, value: &str) -> Self {
+        self.insns.push(Insn::ConstString {
+            register,
+            value: value.to_owned(),
+        });
+        self
+    }
+
+    /// `invoke-static` of a method of `class` (a binary name, `a.b.C`) with
+    /// a JNI-style signature. At most five argument registers, all below 16.
+    pub fn invoke_static(
+        mut self,
+        class: &str,
+        name: &str,
+        signature: &str,
+        args: &[u8],
+    ) -> Result<Self> {
+        let proto = Proto::parse(signature)?;
+        ensure!(
+            args.len() <= 5 && args.iter().all(|&register| register < 16),
+            "invoke-static {class}.{name}: arguments must be at most five registers below 16"
+        );
+        ensure!(
+            args.len() == proto.ins_size() as usize,
+            "invoke-static {class}.{name}: {} argument registers for {signature}",
+            args.len()
+        );
+        self.insns.push(Insn::InvokeStatic {
+            method: MethodRef {
+                class: class_descriptor(class),
+                name: name.to_owned(),
+                proto,
+            },
+            args: args.to_vec(),
+        });
+        Ok(self)
+    }
+
+    /// `move-result-object`, right after an invoke returning a reference.
+    pub fn move_result_object(mut self, register: u8) -> Self {
+        self.insns.push(Insn::MoveResultObject(register));
+        self
+    }
+
+    /// `return-void`.
+    pub fn return_void(mut self) -> Self {
+        self.insns.push(Insn::ReturnVoid);
+        self
+    }
+
+    /// Registers needed to pass arguments to the methods it calls.
+    fn outs_size(&self) -> u16 {
+        self.insns
+            .iter()
+            .map(|insn| match insn {
+                Insn::InvokeStatic { args, .. } => args.len() as u16,
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Write the instructions, in 16-bit code units.
+    fn encode(
+        &self,
+        string_idx: &BTreeMap<String, usize>,
+        method_idx: &BTreeMap<MethodRef, usize>,
+    ) -> Vec<u16> {
+        let mut units = Vec::new();
+        for insn in &self.insns {
+            match insn {
+                Insn::ConstString { register, value } => {
+                    units.push(u16::from(*register) << 8 | 0x1a);
+                    units.push(string_idx[value] as u16);
+                }
+                Insn::InvokeStatic { method, args } => {
+                    let mut regs = [0u16; 5];
+                    for (slot, &register) in regs.iter_mut().zip(args) {
+                        *slot = u16::from(register);
+                    }
+                    units.push((args.len() as u16) << 12 | regs[4] << 8 | 0x71);
+                    units.push(method_idx[method] as u16);
+                    units.push(regs[0] | regs[1] << 4 | regs[2] << 8 | regs[3] << 12);
+                }
+                Insn::MoveResultObject(register) => units.push(u16::from(*register) << 8 | 0x0c),
+                Insn::ReturnVoid => units.push(0x0e),
+            }
+        }
+        units
+    }
+}
+
+/// One public class, built up and then written out as a dex file of its own.
+pub struct ClassBuilder {
+    descriptor: String,
+    superclass: String,
+    interfaces: Vec<String>,
+    methods: Vec<Method>,
+}
+
+impl ClassBuilder {
+    /// A class extending `java.lang.Object`, by binary name (`a.b.C`).
+    pub fn new(name: &str) -> Self {
+        Self {
+            descriptor: class_descriptor(name),
+            superclass: "Ljava/lang/Object;".to_owned(),
+            interfaces: Vec::new(),
+            methods: Vec::new(),
+        }
+    }
+
+    /// Implement an interface, by binary name (`a.b.C$Inner`).
+    pub fn implements(mut self, interface: &str) -> Self {
+        self.interfaces.push(class_descriptor(interface));
+        self
+    }
+
+    /// Add a `public native` instance method with a JNI-style signature.
+    /// Instances are made with `AllocObject`, so no constructor is needed.
+    pub fn native_method(mut self, name: &str, signature: &str) -> Result<Self> {
+        self.methods.push(Method {
+            name: name.to_owned(),
+            proto: Proto::parse(signature)?,
+            access_flags: ACC_PUBLIC | ACC_NATIVE,
+            code: None,
+        });
+        Ok(self)
+    }
+
+    /// Add a `public static native` method with a JNI-style signature.
+    pub fn static_native_method(mut self, name: &str, signature: &str) -> Result<Self> {
+        self.methods.push(Method {
+            name: name.to_owned(),
+            proto: Proto::parse(signature)?,
+            access_flags: ACC_PUBLIC | ACC_STATIC | ACC_NATIVE,
+            code: None,
+        });
+        Ok(self)
+    }
+
+    /// Add a `public static` method with a JNI-style signature and a body.
+    pub fn static_method(mut self, name: &str, signature: &str, code: Code) -> Result<Self> {
+        let proto = Proto::parse(signature)?;
+        ensure!(
+            code.registers >= proto.ins_size(),
+            "{name}{signature} needs more than {} registers",
+            code.registers
+        );
+        self.methods.push(Method {
+            name: name.to_owned(),
+            proto,
+            access_flags: ACC_PUBLIC | ACC_STATIC,
+            code: Some(code),
+        });
+        Ok(self)
+    }
+
+    /// Write the class as a version 035 dex file.
+    pub fn to_dex(&self) -> Result<Vec<u8>> {
+        // Every id section has to be sorted, so collect everything first.
+        let mut strings = BTreeSet::new();
+        let mut types = BTreeSet::new();
+        let mut protos = BTreeSet::new();
+        types.insert(self.descriptor.clone());
+        types.insert(self.superclass.clone());
+        types.extend(self.interfaces.iter().cloned());
+        let mut method_keys = BTreeSet::new();
+        for method in &self.methods {
+            ensure!(
+                method_keys.insert(method.key(&self.descriptor)),
+                "duplicate method {} in {}",
+                method.name,
+                self.descriptor
+            );
+        }
+        for insn in self
+            .methods
+            .iter()
+            .filter_map(|m| m.code.as_ref())
+            .flat_map(|c| &c.insns)
+        {
+            match insn {
+                Insn::ConstString { value, .. } => {
+                    strings.insert(value.clone());
+                }
+                Insn::InvokeStatic { method, .. } => {
+                    method_keys.insert(method.clone());
+                }
+                Insn::MoveResultObject(_) | Insn::ReturnVoid => {}
+            }
+        }
+        for method in &method_keys {
+            strings.insert(method.name.clone());
+            strings.insert(method.proto.shorty());
+            types.insert(method.class.clone());
+            types.insert(method.proto.return_type.clone());
+            types.extend(method.proto.parameters.iter().cloned());
+            protos.insert(method.proto.clone());
+        }
+        strings.extend(types.iter().cloned());
+        // Byte order only matches the required UTF-16 order for ASCII.
+        if let Some(s) = strings.iter().find(|s| !s.is_ascii()) {
+            bail!("non-ASCII name {s:?}");
+        }
+
+        let string_idx = index_of(&strings);
+        let type_idx = index_of(&types);
+        let proto_idx = index_of(&protos);
+        let method_idx = index_of(&method_keys);
+
+        let string_ids_off = HEADER_SIZE;
+        let type_ids_off = string_ids_off + 4 * strings.len() as u32;
+        let proto_ids_off = type_ids_off + 4 * types.len() as u32;
+        let method_ids_off = proto_ids_off + 12 * protos.len() as u32;
+        let class_defs_off = method_ids_off + 8 * method_keys.len() as u32;
+        let data_off = class_defs_off + 32;
+
+        let mut data = Section::new(data_off);
+
+        // Parameter lists and the interface list share one format.
+        let mut type_lists = BTreeMap::new();
+        let lists = protos
+            .iter()
+            .map(|p| &p.parameters)
+            .chain(std::iter::once(&self.interfaces))
+            .filter(|list| !list.is_empty());
+        for list in lists {
+            if type_lists.contains_key(list) {
+                continue;
+            }
+            data.align(4);
+            type_lists.insert(list.clone(), data.offset());
+            data.u32(list.len() as u32);
+            for descriptor in list {
+                data.u16(type_idx[descriptor] as u16);
+            }
+        }
+        let type_lists_off = type_lists.values().min().copied();
+
+        let mut code_offs = BTreeMap::new();
+        for method in &self.methods {
+            let Some(code) = &method.code else {
+                continue;
+            };
+            data.align(4);
+            code_offs.insert(method_idx[&method.key(&self.descriptor)], data.offset());
+            let units = code.encode(&string_idx, &method_idx);
+            data.u16(code.registers);
+            data.u16(method.proto.ins_size());
+            data.u16(code.outs_size());
+            data.u16(0); // tries
+            data.u32(0); // debug info
+            data.u32(units.len() as u32);
+            for unit in units {
+                data.u16(unit);
+            }
+        }
+        let code_items_off = code_offs.values().min().copied();
+
+        let mut string_data_offs = Vec::with_capacity(strings.len());
+        for s in &strings {
+            string_data_offs.push(data.offset());
+            data.uleb128(s.len() as u32);
+            data.bytes(s.as_bytes());
+            data.bytes(&[0]);
+        }
+
+        let class_data_off = data.offset();
+        // Direct, then virtual methods, each in method id order.
+        let (direct, virtual_methods): (Vec<_>, Vec<_>) =
+            self.methods.iter().partition(|m| m.is_direct());
+        data.uleb128(0); // static fields
+        data.uleb128(0); // instance fields
+        data.uleb128(direct.len() as u32);
+        data.uleb128(virtual_methods.len() as u32);
+        for methods in [direct, virtual_methods] {
+            let mut methods = methods
+                .iter()
+                .map(|m| (method_idx[&m.key(&self.descriptor)], m.access_flags))
+                .collect::<Vec<_>>();
+            methods.sort();
+            let mut previous = 0;
+            for (idx, access_flags) in methods {
+                data.uleb128((idx - previous) as u32);
+                data.uleb128(access_flags);
+                // Native methods have no code.
+                data.uleb128(code_offs.get(&idx).copied().unwrap_or(0));
+                previous = idx;
+            }
+        }
+
+        data.align(4);
+        let map_off = data.offset();
+        let mut map = vec![
+            (TYPE_HEADER_ITEM, 1, 0),
+            (TYPE_STRING_ID_ITEM, strings.len(), string_ids_off),
+            (TYPE_TYPE_ID_ITEM, types.len(), type_ids_off),
+            (TYPE_PROTO_ID_ITEM, protos.len(), proto_ids_off),
+            (TYPE_METHOD_ID_ITEM, method_keys.len(), method_ids_off),
+            (TYPE_CLASS_DEF_ITEM, 1, class_defs_off),
+        ];
+        if let Some(off) = type_lists_off {
+            map.push((TYPE_TYPE_LIST, type_lists.len(), off));
+        }
+        if let Some(off) = code_items_off {
+            map.push((TYPE_CODE_ITEM, code_offs.len(), off));
+        }
+        map.push((TYPE_STRING_DATA_ITEM, strings.len(), string_data_offs[0]));
+        map.push((TYPE_CLASS_DATA_ITEM, 1, class_data_off));
+        map.push((TYPE_MAP_LIST, 1, map_off));
+        map.retain(|&(_, count, _)| count > 0);
+        data.u32(map.len() as u32);
+        for (item_type, count, offset) in map {
+            data.u16(item_type);
+            data.u16(0);
+            data.u32(count as u32);
+            data.u32(offset);
+        }
+        let file_size = data.offset();
+
+        let mut out = Section::new(0);
+        out.bytes(b"dex\n035\0");
+        out.u32(0); // checksum, filled in below
+        out.bytes(&[0; 20]); // signature, filled in below
+        out.u32(file_size);
+        out.u32(HEADER_SIZE);
+        out.u32(ENDIAN_CONSTANT);
+        out.u32(0); // link_size
+        out.u32(0); // link_off
+        out.u32(map_off);
+        for (count, offset) in [
+            (strings.len(), string_ids_off),
+            (types.len(), type_ids_off),
+            (protos.len(), proto_ids_off),
+            (0, 0),
+            (method_keys.len(), method_ids_off),
+            (1, class_defs_off),
+        ] {
+            out.u32(count as u32);
+            out.u32(if count > 0 { offset } else { 0 });
+        }
+        out.u32(file_size - data_off);
+        out.u32(data_off);
+
+        for off in string_data_offs {
+            out.u32(off);
+        }
+        for descriptor in &types {
+            out.u32(string_idx[descriptor] as u32);
+        }
+        for proto in &protos {
+            out.u32(string_idx[&proto.shorty()] as u32);
+            out.u32(type_idx[&proto.return_type] as u32);
+            out.u32(type_lists.get(&proto.parameters).copied().unwrap_or(0));
+        }
+        for method in &method_keys {
+            out.u16(type_idx[&method.class] as u16);
+            out.u16(proto_idx[&method.proto] as u16);
+            out.u32(string_idx[&method.name] as u32);
+        }
+        out.u32(type_idx[&self.descriptor] as u32);
+        out.u32(ACC_PUBLIC);
+        out.u32(type_idx[&self.superclass] as u32);
+        out.u32(type_lists.get(&self.interfaces).copied().unwrap_or(0));
+        out.u32(NO_INDEX); // source file
+        out.u32(0); // annotations
+        out.u32(class_data_off);
+        out.u32(0); // static values
+        debug_assert_eq!(out.offset(), data_off);
+        out.bytes(&data.buf);
+
+        let mut dex = out.buf;
+        let signature = sha1_smol::Sha1::from(&dex[32..]).digest().bytes();
+        dex[12..32].copy_from_slice(&signature);
+        let checksum = adler2::adler32_slice(&dex[12..]);
+        dex[8..12].copy_from_slice(&checksum.to_le_bytes());
+        Ok(dex)
+    }
+}
+
+/// Position of every element in a sorted set.
+fn index_of<T: Ord + Clone>(set: &BTreeSet<T>) -> BTreeMap<T, usize> {
+    set.iter()
+        .cloned()
+        .enumerate()
+        .map(|(idx, item)| (item, idx))
+        .collect()
+}
+
+/// Little-endian output that knows its offset in the file.
+struct Section {
+    base: u32,
+    buf: Vec<u8>,
+}
+
+impl Section {
+    fn new(base: u32) -> Self {
+        Self {
+            base,
+            buf: Vec::new(),
+        }
+    }
+
+    fn offset(&self) -> u32 {
+        self.base + self.buf.len() as u32
+    }
+
+    fn align(&mut self, alignment: u32) {
+        while !self.offset().is_multiple_of(alignment) {
+            self.buf.push(0);
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn uleb128(&mut self, mut value: u32) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.buf.push(byte);
+                return;
+            }
+            self.buf.push(byte | 0x80);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(dex: &[u8], offset: u32) -> u16 {
+        let offset = offset as usize;
+        u16::from_le_bytes(dex[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(dex: &[u8], offset: u32) -> u32 {
+        let offset = offset as usize;
+        u32::from_le_bytes(dex[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// `(type, size, offset)` of every map_list entry.
+    fn map_list(dex: &[u8]) -> Vec<(u16, u32, u32)> {
+        let map_off = u32_at(dex, 52);
+        (0..u32_at(dex, map_off))
+            .map(|i| {
+                let entry = map_off + 4 + 12 * i;
+                (
+                    u16_at(dex, entry),
+                    u32_at(dex, entry + 4),
+                    u32_at(dex, entry + 8),
+                )
+            })
+            .collect()
+    }
+
+    /// The string ids, in index order. Only short ASCII strings are used
+    /// here, so the uleb128 length is one byte.
+    fn strings(dex: &[u8]) -> Vec<String> {
+        let (size, offset) = (u32_at(dex, 56), u32_at(dex, 60));
+        (0..size)
+            .map(|i| {
+                let data = u32_at(dex, offset + 4 * i) as usize;
+                let len = dex[data] as usize;
+                String::from_utf8(dex[data + 1..data + 1 + len].to_vec()).unwrap()
+            })
+            .collect()
+    }
+
+    /// Method names, in method id order.
+    fn method_names(dex: &[u8]) -> Vec<String> {
+        let strings = strings(dex);
+        let (size, offset) = (u32_at(dex, 88), u32_at(dex, 92));
+        (0..size)
+            .map(|i| strings[u32_at(dex, offset + 8 * i + 4) as usize].clone())
+            .collect()
+    }
+
+    /// `Stub.main(args)`: `System.load("hello")`.
+    fn stub_dex() -> Vec<u8> {
+        let main = Code::new(2)
+            .const_string(0, "hello")
+            .invoke_static("java.lang.System", "load", "(Ljava/lang/String;)V", &[0])
+            .unwrap()
+            .return_void();
+        ClassBuilder::new("com.example.Stub")
+            .static_method("main", "([Ljava/lang/String;)V", main)
+            .unwrap()
+            .static_native_method("run", "(JI)V")
+            .unwrap()
+            .to_dex()
+            .unwrap()
+    }
+
+    #[test]
+    fn signatures_are_parsed() {
+        let proto = Proto::parse("(Ljava/lang/String;I[J)[Ljava/lang/Object;").unwrap();
+        assert_eq!(proto.parameters, ["Ljava/lang/String;", "I", "[J"]);
+        assert_eq!(proto.return_type, "[Ljava/lang/Object;");
+        assert!(Proto::parse("()V").unwrap().parameters.is_empty());
+    }
+
+    #[test]
+    fn malformed_signatures_are_errors() {
+        for signature in [
+            "",
+            "V",
+            "(I",
+            "(Q)V",
+            "(Ljava/lang/String)V",
+            "([)V",
+            "(I)",
+            "(I)II",
+            "(I)Q",
+        ] {
+            assert!(Proto::parse(signature).is_err(), "{signature:?}");
+        }
+    }
+
+    #[test]
+    fn shorty_and_ins_size() {
+        let proto = Proto::parse("(J[ILjava/lang/Object;D)Ljava/lang/String;").unwrap();
+        assert_eq!(proto.shorty(), "LJLLD");
+        assert_eq!(proto.ins_size(), 6);
+        let proto = Proto::parse("()V").unwrap();
+        assert_eq!(proto.shorty(), "V");
+        assert_eq!(proto.ins_size(), 0);
+    }
+
+    #[test]
+    fn header_describes_the_file() {
+        let dex = stub_dex();
+        assert_eq!(&dex[..8], b"dex\n035\0");
+        assert_eq!(u32_at(&dex, 32) as usize, dex.len());
+        assert_eq!(u32_at(&dex, 36), HEADER_SIZE);
+        assert_eq!(u32_at(&dex, 40), ENDIAN_CONSTANT);
+        let (data_size, data_off) = (u32_at(&dex, 104), u32_at(&dex, 108));
+        assert_eq!((data_off + data_size) as usize, dex.len());
+        assert_eq!(u32_at(&dex, 96), 1);
+        assert_eq!(u32_at(&dex, 100) + 32, data_off);
+
+        let checksum = adler2::adler32_slice(&dex[12..]);
+        assert_eq!(u32_at(&dex, 8), checksum);
+        let signature = sha1_smol::Sha1::from(&dex[32..]).digest().bytes();
+        assert_eq!(dex[12..32], signature);
+    }
+
+    #[test]
+    fn map_list_is_sorted_by_offset() {
+        let dex = stub_dex();
+        let map = map_list(&dex);
+        assert_eq!(map.first(), Some(&(TYPE_HEADER_ITEM, 1, 0)));
+        assert_eq!(map.last(), Some(&(TYPE_MAP_LIST, 1, u32_at(&dex, 52))));
+        assert!(map.windows(2).all(|pair| pair[0].2 < pair[1].2), "{map:?}");
+        assert!(
+            map.iter()
+                .any(|&(item, size, _)| item == TYPE_CODE_ITEM && size == 1)
+        );
+    }
+
+    #[test]
+    fn invoke_static_is_encoded() {
+        let dex = stub_dex();
+        let hello = strings(&dex).iter().position(|s| s == "hello").unwrap() as u16;
+        let load = method_names(&dex).iter().position(|s| s == "load").unwrap() as u16;
+
+        let &(_, _, code_off) = map_list(&dex)
+            .iter()
+            .find(|&&(item, ..)| item == TYPE_CODE_ITEM)
+            .unwrap();
+        // registers, ins, outs, tries
+        assert_eq!(
+            [0, 2, 4, 6].map(|field| u16_at(&dex, code_off + field)),
+            [2, 1, 1, 0]
+        );
+        let insns_size = u32_at(&dex, code_off + 12);
+        let insns = (0..insns_size)
+            .map(|i| u16_at(&dex, code_off + 16 + 2 * i))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            insns,
+            [
+                0x001a, hello, // const-string v0, "hello"
+                0x1071, load, 0x0000, // invoke-static {v0}, System.load
+                0x000e, // return-void
+            ]
+        );
+    }
+
+    #[test]
+    fn invoke_static_checks_its_arguments() {
+        let code = Code::new(2);
+        assert!(code.invoke_static("a.B", "c", "(JI)V", &[0, 1]).is_err());
+        let code = Code::new(2);
+        assert!(code.invoke_static("a.B", "c", "(I)V", &[16]).is_err());
+        assert!(
+            ClassBuilder::new("a.B")
+                .static_method("c", "(JI)V", Code::new(2).return_void())
+                .is_err()
+        );
+    }
+}
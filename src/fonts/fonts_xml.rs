@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+
+/// One `<font>` entry of a `<family>` in `fonts.xml`.
+#[derive(Debug, Clone)]
+pub struct FontEntry {
+    pub file: String,
+    pub index: u32,
+    pub weight: u32,
+    pub italic: bool,
+}
+
+/// One `<family>` element, in document order.
+#[derive(Debug, Clone)]
+pub struct FontFamilyEntry {
+    /// Named families (`sans-serif`, `serif`, ...) are primary families;
+    /// unnamed ones form the fallback chain.
+    pub name: Option<String>,
+    /// Language tags from the `lang` attribute, e.g. `["zh-Hans"]`.
+    pub langs: Vec<String>,
+    pub fonts: Vec<FontEntry>,
+}
+
+impl FontFamilyEntry {
+    pub fn is_fallback(&self) -> bool {
+        self.name.is_none()
+    }
+
+    /// The upright regular-weight font, or the closest thing to it.
+    pub fn regular(&self) -> Option<&FontEntry> {
+        self.fonts
+            .iter()
+            .filter(|f| !f.italic)
+            .min_by_key(|f| f.weight.abs_diff(400))
+            .or_else(|| self.fonts.first())
+    }
+}
+
+/// Parse `/system/etc/fonts.xml` (or `font_fallback.xml` on newer releases).
+pub fn parse(xml: &str) -> Result<Vec<FontFamilyEntry>> {
+    let doc = roxmltree::Document::parse(xml).context("parsing fonts.xml")?;
+
+    let families = doc
+        .root_element()
+        .children()
+        .filter(|n| n.has_tag_name("family"))
+        .map(|family| {
+            let fonts = family
+                .children()
+                .filter(|n| n.has_tag_name("font"))
+                .filter_map(|font| {
+                    // The file name is the element's own text; `<axis>`
+                    // children may follow it.
+                    let file = font
+                        .children()
+                        .find(|n| n.is_text())
+                        .and_then(|n| n.text())
+                        .map(str::trim)
+                        .filter(|s| !s.is_empty())?;
+                    Some(FontEntry {
+                        file: file.to_owned(),
+                        index: font
+                            .attribute("index")
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(0),
+                        weight: font
+                            .attribute("weight")
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(400),
+                        italic: font.attribute("style") == Some("italic"),
+                    })
+                })
+                .collect();
+
+            FontFamilyEntry {
+                name: family.attribute("name").map(str::to_owned),
+                langs: family
+                    .attribute("lang")
+                    .map(|langs| {
+                        langs
+                            .split([',', ' '])
+                            .filter(|l| !l.is_empty())
+                            .map(str::to_owned)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                fonts,
+            }
+        })
+        .collect();
+
+    Ok(families)
+}
@@ -0,0 +1,375 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use egui::epaint::text::{FontInsert, FontPriority, InsertFontFamily};
+use egui::{FontData, FontFamily};
+use tracing::{debug, info, warn};
+
+mod fonts_xml;
+
+const SYSTEM_FONTS_DIR: &str = "/system/fonts";
+const FONTS_XML: &[&str] = &["/system/etc/font_fallback.xml", "/system/etc/fonts.xml"];
+
+/// Well-known fallback files for devices without a usable `fonts.xml`.
+const FALLBACK_FILES: &[&str] = &[
+    "NotoSansCJK-Regular.ttc",
+    "DroidSansFallback.ttf",
+    "DroidSansFallbackFull.ttf",
+    "NotoSansThai-Regular.ttf",
+    "NotoEmoji-Regular.ttf",
+];
+
+/// `fonts.xml` language tag of the emoji family.
+const EMOJI_LANG: &str = "und-Zsye";
+
+/// Emoji fonts with outlines, tried in order when the system's emoji font is
+/// bitmap-only. egui draws them in the text color, not in color.
+const EMOJI_FALLBACK_FILES: &[&str] = &[
+    "NotoEmoji-Regular.ttf",
+    "SamsungColorEmoji.ttf",
+    "AndroidEmoji.ttf",
+];
+
+/// Which system fallback fonts to load.
+#[derive(Debug, Clone)]
+pub struct FontOptions {
+    /// `fonts.xml` language tags to pick fallback families for. A family is
+    /// selected if any of its tags starts with one of these.
+    pub langs: Vec<String>,
+    /// Where the selection is cached between runs. `None` disables caching.
+    pub cache_path: Option<PathBuf>,
+}
+
+impl Default for FontOptions {
+    fn default() -> Self {
+        Self {
+            langs: [
+                "zh-Hans", "zh-Hant", "ja", "ko", "und-Thai", "th", EMOJI_LANG,
+            ]
+            .map(String::from)
+            .to_vec(),
+            cache_path: Some(std::env::temp_dir().join("android-egui-ext/fonts.cache")),
+        }
+    }
+}
+
+/// A font face on disk selected as a fallback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemFont {
+    pub path: PathBuf,
+    pub index: u32,
+}
+
+impl SystemFont {
+    fn name(&self) -> String {
+        let file = self.path.file_name().unwrap_or_default().to_string_lossy();
+        format!("system:{}#{}", file, self.index)
+    }
+}
+
+/// Index every TTF/OTF/TTC file under `/system/fonts` by file name.
+fn scan_fonts_dir(dir: &Path) -> HashMap<String, PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        warn!("Cannot read {}", dir.display());
+        return HashMap::new();
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| matches!(e.to_ascii_lowercase().as_str(), "ttf" | "otf" | "ttc"))
+        })
+        .filter_map(|p| Some((p.file_name()?.to_str()?.to_owned(), p)))
+        .collect()
+}
+
+/// Whether the face at `index` has vector outlines (`glyf` or `CFF`).
+///
+/// Bitmap-only fonts such as `NotoColorEmoji.ttf` (CBDT) cannot be
+/// rasterized by egui and would only produce empty glyphs.
+fn has_outlines(data: &[u8], index: u32) -> bool {
+    let be16 = |at: usize| Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?));
+    let be32 = |at: usize| Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?));
+
+    let table_dir = || {
+        let offset = if data.starts_with(b"ttcf") {
+            be32(12 + 4 * index as usize)? as usize
+        } else {
+            0
+        };
+        let num_tables = be16(offset + 4)? as usize;
+        Some((0..num_tables).any(|i| {
+            let record = offset + 12 + 16 * i;
+            matches!(
+                data.get(record..record + 4),
+                Some(b"glyf" | b"CFF " | b"CFF2")
+            )
+        }))
+    };
+
+    table_dir().unwrap_or(false)
+}
+
+fn select_from_fonts_xml(
+    xml_path: &str,
+    available: &HashMap<String, PathBuf>,
+    langs: &[String],
+) -> Result<Vec<SystemFont>> {
+    let xml = fs::read_to_string(xml_path).with_context(|| format!("reading {xml_path}"))?;
+    let families = fonts_xml::parse(&xml)?;
+
+    let mut seen = HashSet::new();
+    let mut selected = Vec::new();
+
+    for family in families.iter().filter(|f| f.is_fallback()) {
+        let wanted = family
+            .langs
+            .iter()
+            .any(|tag| langs.iter().any(|l| tag.starts_with(l.as_str())));
+        if !wanted {
+            continue;
+        }
+        let Some(font) = family.regular() else {
+            continue;
+        };
+        let Some(path) = available.get(&font.file) else {
+            debug!("{} listed in {} but not installed", font.file, xml_path);
+            continue;
+        };
+        // The CJK collection is listed once per language with a different
+        // face index; egui can't switch faces by locale, so one is enough.
+        if seen.insert(path.clone()) {
+            selected.push(SystemFont {
+                path: path.clone(),
+                index: font.index,
+            });
+        }
+    }
+
+    Ok(selected)
+}
+
+/// The first font of [`EMOJI_FALLBACK_FILES`] that is installed, has outlines
+/// and isn't in `selected` yet.
+fn select_emoji_fallback(
+    available: &HashMap<String, PathBuf>,
+    selected: &[SystemFont],
+) -> Option<SystemFont> {
+    EMOJI_FALLBACK_FILES
+        .iter()
+        .filter_map(|name| available.get(*name))
+        .filter(|path| !selected.iter().any(|font| &font.path == *path))
+        .find(|path| fs::read(path).is_ok_and(|data| has_outlines(&data, 0)))
+        .map(|path| SystemFont {
+            path: path.clone(),
+            index: 0,
+        })
+}
+
+fn select_by_file_name(available: &HashMap<String, PathBuf>) -> Vec<SystemFont> {
+    FALLBACK_FILES
+        .iter()
+        .filter_map(|name| available.get(*name))
+        .map(|path| SystemFont {
+            path: path.clone(),
+            index: 0,
+        })
+        .collect()
+}
+
+/// Cache key: changes whenever the wanted languages or the system fonts do.
+fn fingerprint(langs: &[String]) -> String {
+    let mtime = |path: &str| {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs())
+    };
+    let xml_mtimes = FONTS_XML
+        .iter()
+        .map(|p| mtime(p).to_string())
+        .collect::<Vec<_>>();
+    format!(
+        "v2|{}|{}|{}",
+        langs.join(","),
+        xml_mtimes.join(","),
+        mtime(SYSTEM_FONTS_DIR)
+    )
+}
+
+fn read_cache(path: &Path, fingerprint: &str) -> Option<Vec<SystemFont>> {
+    let data = fs::read_to_string(path).ok()?;
+    let mut lines = data.lines();
+    if lines.next()? != fingerprint {
+        return None;
+    }
+    lines
+        .map(|line| {
+            let (index, path) = line.split_once('\t')?;
+            Some(SystemFont {
+                path: PathBuf::from(path),
+                index: index.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn write_cache(path: &Path, fingerprint: &str, fonts: &[SystemFont]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut data = format!("{fingerprint}\n");
+    for font in fonts {
+        data.push_str(&format!("{}\t{}\n", font.index, font.path.display()));
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+/// Pick fallback fonts for `options.langs`, following the order of the
+/// system's `fonts.xml`. The result is cached in `options.cache_path`.
+pub fn discover_system_fonts(options: &FontOptions) -> Result<Vec<SystemFont>> {
+    let fingerprint = fingerprint(&options.langs);
+    if let Some(cached) = options
+        .cache_path
+        .as_deref()
+        .and_then(|p| read_cache(p, &fingerprint))
+    {
+        debug!("Using cached font selection ({} fonts)", cached.len());
+        return Ok(cached);
+    }
+
+    let available = scan_fonts_dir(Path::new(SYSTEM_FONTS_DIR));
+    info!(
+        "Found {} font files in {}",
+        available.len(),
+        SYSTEM_FONTS_DIR
+    );
+
+    let mut candidates = None;
+    for xml_path in FONTS_XML {
+        match select_from_fonts_xml(xml_path, &available, &options.langs) {
+            Ok(fonts) if !fonts.is_empty() => {
+                candidates = Some(fonts);
+                break;
+            }
+            Ok(_) => debug!("{} has no matching fallback families", xml_path),
+            Err(e) => debug!("{:#}", e),
+        }
+    }
+    let candidates = candidates.unwrap_or_else(|| {
+        warn!("No usable fonts.xml, picking fallback fonts by file name");
+        select_by_file_name(&available)
+    });
+
+    let mut skipped_bitmap = false;
+    let mut selected = candidates
+        .into_iter()
+        .filter(|font| match fs::read(&font.path) {
+            Ok(data) if has_outlines(&data, font.index) => true,
+            Ok(_) => {
+                info!(
+                    "Skipping bitmap-only font {} (egui cannot rasterize it)",
+                    font.path.display()
+                );
+                skipped_bitmap = true;
+                false
+            }
+            Err(e) => {
+                warn!("Cannot read {}: {}", font.path.display(), e);
+                false
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // The emoji family is usually a color bitmap font (`NotoColorEmoji.ttf`);
+    // a monochrome outline font at least shows the symbols instead of boxes.
+    if skipped_bitmap
+        && options.langs.iter().any(|lang| lang == EMOJI_LANG)
+        && let Some(font) = select_emoji_fallback(&available, &selected)
+    {
+        info!("Using {} for emoji", font.path.display());
+        selected.push(font);
+    }
+
+    if let Some(path) = &options.cache_path
+        && let Err(e) = write_cache(path, &fingerprint, &selected)
+    {
+        warn!("Failed to write font cache {}: {}", path.display(), e);
+    }
+
+    Ok(selected)
+}
+
+/// Load the discovered system fonts into `ctx` as fallbacks for both the
+/// proportional and monospace families. Returns how many were installed.
+pub fn install_system_fonts(ctx: &egui::Context, options: &FontOptions) -> Result<usize> {
+    let fonts = discover_system_fonts(options)?;
+    let mut installed = 0;
+
+    for font in &fonts {
+        match register_font_file(
+            ctx,
+            &font.name(),
+            &font.path,
+            font.index,
+            FontPriority::Lowest,
+        ) {
+            Ok(()) => {
+                info!("Installed fallback font {}", font.name());
+                installed += 1;
+            }
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+
+    Ok(installed)
+}
+
+/// Register a font file (TTF/OTF, or a face of a TTC) for both text families.
+pub fn register_font_file(
+    ctx: &egui::Context,
+    name: &str,
+    path: impl AsRef<Path>,
+    index: u32,
+    priority: FontPriority,
+) -> Result<()> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).with_context(|| format!("reading font {}", path.display()))?;
+    register_font_bytes(ctx, name, bytes, index, priority);
+    Ok(())
+}
+
+/// Register in-memory font data for both text families.
+///
+/// `FontPriority::Highest` makes it the primary font, `Lowest` a fallback.
+/// Takes effect on the next frame.
+pub fn register_font_bytes(
+    ctx: &egui::Context,
+    name: &str,
+    bytes: Vec<u8>,
+    index: u32,
+    priority: FontPriority,
+) {
+    let mut data = FontData::from_owned(bytes);
+    data.index = index;
+
+    ctx.add_font(FontInsert::new(
+        name,
+        data,
+        [FontFamily::Proportional, FontFamily::Monospace]
+            .into_iter()
+            .map(|family| InsertFontFamily {
+                family,
+                priority: priority.clone(),
+            })
+            .collect(),
+    ));
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use egui::{ColorImage, TextureHandle, TextureOptions};
+use image::ImageEncoder;
+use jni::JNIEnv;
+
+use crate::bridge::JavaBridge;
+
+/// Decode a PNG, JPEG or WebP image.
+pub fn decode(bytes: &[u8]) -> Result<ColorImage> {
+    let image = image::load_from_memory(bytes).context("decoding image")?;
+    let rgba = image.to_rgba8();
+    let size = [rgba.width() as usize, rgba.height() as usize];
+    Ok(ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()))
+}
+
+pub fn load_file(path: impl AsRef<Path>) -> Result<ColorImage> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    decode(&bytes).with_context(|| format!("decoding {}", path.display()))
+}
+
+/// Convert `Bitmap.getPixels` output (non-premultiplied `0xAARRGGBB`).
+pub fn from_argb(size: [usize; 2], pixels: &[i32]) -> ColorImage {
+    let rgba = pixels
+        .iter()
+        .flat_map(|&argb| {
+            let [a, r, g, b] = (argb as u32).to_be_bytes();
+            [r, g, b, a]
+        })
+        .collect::<Vec<_>>();
+    ColorImage::from_rgba_unmultiplied(size, &rgba)
+}
+
+/// Encode as PNG, e.g. a [`crate::renderer::Renderer::capture_frame`] result.
+pub fn encode_png(image: &ColorImage) -> Result<Vec<u8>> {
+    let rgba = image
+        .pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_srgba_unmultiplied())
+        .collect::<Vec<_>>();
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png)
+        .write_image(
+            &rgba,
+            image.width() as u32,
+            image.height() as u32,
+            image::ExtendedColorType::Rgba8,
+        )
+        .context("encoding PNG")?;
+    Ok(png)
+}
+
+pub fn save_png(image: &ColorImage, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let png = encode_png(image)?;
+    std::fs::write(path, png).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Screenshot of the whole display (everything SurfaceFlinger composites,
+/// minus layers hidden from screenshots such as our own overlay).
+pub fn capture_display<'a>(
+    bridge: &JavaBridge<'a>,
+    env: &mut JNIEnv<'a>,
+    width: i32,
+    height: i32,
+) -> Result<ColorImage> {
+    let bitmap = bridge
+        .capture_display(env, width, height)
+        .context("capturing display (needs shell or root)")?;
+    let (size, pixels) = bridge.get_bitmap_pixels(env, &bitmap)?;
+    Ok(from_argb(size, &pixels))
+}
+
+fn drawable_to_image<'a>(
+    bridge: &JavaBridge<'a>,
+    env: &mut JNIEnv<'a>,
+    drawable: &jni::objects::JObject<'a>,
+) -> Result<ColorImage> {
+    let bitmap = bridge.get_bitmap_from_drawable(env, drawable)?;
+    let (size, pixels) = bridge.get_bitmap_pixels(env, &bitmap)?;
+    Ok(from_argb(size, &pixels))
+}
+
+/// The launcher icon of an installed package.
+pub fn load_app_icon<'a>(
+    bridge: &JavaBridge<'a>,
+    env: &mut JNIEnv<'a>,
+    package_name: &str,
+) -> Result<ColorImage> {
+    let drawable = bridge.get_application_icon(env, package_name)?;
+    drawable_to_image(bridge, env, &drawable)
+        .with_context(|| format!("loading icon of {package_name}"))
+}
+
+/// A drawable resource of an installed package, e.g.
+/// `load_package_drawable(.., "com.android.settings", "drawable", "ic_settings")`.
+pub fn load_package_drawable<'a>(
+    bridge: &JavaBridge<'a>,
+    env: &mut JNIEnv<'a>,
+    package_name: &str,
+    resource_type: &str,
+    resource_name: &str,
+) -> Result<ColorImage> {
+    let drawable = bridge.get_package_drawable(env, package_name, resource_type, resource_name)?;
+    drawable_to_image(bridge, env, &drawable)
+}
+
+/// Keyed cache of uploaded textures.
+///
+/// egui frees a texture when the last [`TextureHandle`] to it is dropped, so
+/// entries stay alive until they are removed or replaced.
+pub struct TextureCache {
+    ctx: egui::Context,
+    options: TextureOptions,
+    entries: HashMap<String, TextureHandle>,
+}
+
+impl TextureCache {
+    pub fn new(ctx: &egui::Context) -> Self {
+        Self {
+            ctx: ctx.clone(),
+            options: TextureOptions::LINEAR,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<TextureHandle> {
+        self.entries.get(key).cloned()
+    }
+
+    /// Upload `image` under `key`, replacing any previous texture.
+    pub fn insert(&mut self, key: &str, image: ColorImage) {
+        let handle = self.ctx.load_texture(key, image, self.options);
+        self.entries.insert(key.to_owned(), handle);
+    }
+
+    /// Return the cached texture or upload the result of `load`.
+    pub fn get_or_insert_with(
+        &mut self,
+        key: &str,
+        load: impl FnOnce() -> Result<ColorImage>,
+    ) -> Result<TextureHandle> {
+        if let Some(handle) = self.get(key) {
+            return Ok(handle);
+        }
+        self.insert(key, load()?);
+        Ok(self.entries[key].clone())
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+}
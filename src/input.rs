@@ -2,6 +2,8 @@ use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::Read;
 use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::thread;
 
@@ -254,18 +256,76 @@ fn find_touch_devices() -> Vec<String> {
     devices
 }
 
+/// Convert an event produced by the input thread from physical pixels to
+/// egui points. Events without a position are returned unchanged.
+pub fn event_to_points(event: egui::Event, pixels_per_point: f32) -> egui::Event {
+    let to_points = |pos: egui::Pos2| (pos.to_vec2() / pixels_per_point).to_pos2();
+    match event {
+        egui::Event::Touch {
+            device_id,
+            id,
+            phase,
+            pos,
+            force,
+        } => egui::Event::Touch {
+            device_id,
+            id,
+            phase,
+            pos: to_points(pos),
+            force,
+        },
+        egui::Event::PointerMoved(pos) => egui::Event::PointerMoved(to_points(pos)),
+        egui::Event::PointerButton {
+            pos,
+            button,
+            pressed,
+            modifiers,
+        } => egui::Event::PointerButton {
+            pos: to_points(pos),
+            button,
+            pressed,
+            modifiers,
+        },
+        other => other,
+    }
+}
+
+/// The reader thread from [`start_input_thread`]. Dropping it stops the
+/// thread (within one epoll timeout) and joins it.
+pub struct InputThread {
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for InputThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take()
+            && handle.join().is_err()
+        {
+            warn!("Input thread panicked");
+        }
+    }
+}
+
 /// Start a background thread reading raw Linux touch events.
 /// Emits properly sequenced egui events (Touch Start/Move/End + PointerButton + PointerMoved/Gone).
+///
+/// Positions are in physical screen pixels; use [`event_to_points`] before
+/// handing them to egui.
 pub fn start_input_thread(
     screen_width: f32,
     screen_height: f32,
     display_rotation: i32,
-) -> mpsc::Receiver<Vec<egui::Event>> {
+) -> (mpsc::Receiver<Vec<egui::Event>>, InputThread) {
     let (tx, rx) = mpsc::channel::<Vec<egui::Event>>();
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
 
-    thread::Builder::new()
+    let handle = thread::Builder::new()
         .name("input-reader".into())
         .spawn(move || {
+            let _alt_stack = crate::crash::AltStack::install();
             let devices = find_touch_devices();
             if devices.is_empty() {
                 warn!("No input devices found.");
@@ -366,7 +426,8 @@ pub fn start_input_thread(
 
             info!("Input thread listening for events...");
 
-            loop {
+            // The 50 ms epoll timeout bounds how long stopping takes.
+            while !thread_stop.load(Ordering::Relaxed) {
                 let nfds = unsafe {
                     libc::epoll_wait(
                         epoll_fd,
@@ -598,8 +659,17 @@ pub fn start_input_thread(
                     }
                 }
             }
+
+            unsafe { libc::close(epoll_fd) };
+            debug!("Input thread stopped");
         })
         .expect("Failed to spawn input thread");
 
-    rx
+    (
+        rx,
+        InputThread {
+            stop,
+            handle: Some(handle),
+        },
+    )
 }
@@ -0,0 +1,325 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use jni::JNIEnv;
+use jni::objects::JObject;
+use ndk::native_window::NativeWindow;
+use tracing::{debug, info, warn};
+
+use crate::bridge::{DisplayModes, JavaBridge, MainSurface};
+use crate::clipboard::ClipboardWatcher;
+use crate::images;
+use crate::overlay_windows::OverlaySurfaces;
+use crate::platform_output;
+use crate::theme::NightModeWatcher;
+
+/// How long the JNI thread waits for a command before checking for
+/// clipboard changes and running its periodic polls (night mode, display).
+const IDLE_TICK: Duration = Duration::from_millis(100);
+
+/// How often the display refresh rate is checked; it changes when the
+/// system switches modes, e.g. after [`JniCommand::SetFrameRate`].
+const DISPLAY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Local references each command or poll may create. This thread never
+/// returns to Java, so they are only freed by popping a local frame.
+const LOCAL_FRAME_CAPACITY: i32 = 64;
+
+/// Work for the JNI thread, sent by the render thread.
+pub enum JniCommand {
+    /// Forward a touch to Android's `InputManager` (see
+    /// [`JavaBridge::inject_touch_event`]).
+    InjectTouch {
+        action: i32,
+        pointer_id: i64,
+        x: i32,
+        y: i32,
+    },
+    /// Read the clipboard; answered with [`JniEvent::Paste`].
+    ReadClipboard,
+    SetClipboard(String),
+    OpenUrl(String),
+    /// Create a layer for a viewport; answered with
+    /// [`JniEvent::OverlayCreated`].
+    CreateOverlay {
+        id: egui::ViewportId,
+        name: String,
+        /// Screen rect in physical pixels.
+        rect: egui::Rect,
+    },
+    /// Move or resize a viewport's layer.
+    MoveOverlay {
+        id: egui::ViewportId,
+        /// Screen rect in physical pixels.
+        rect: egui::Rect,
+    },
+    DestroyOverlay(egui::ViewportId),
+    /// Screenshot the whole display; answered with
+    /// [`JniEvent::DisplayCaptured`].
+    CaptureDisplay {
+        width: i32,
+        height: i32,
+    },
+    /// List display modes; answered with [`JniEvent::DisplayModes`].
+    QueryDisplayModes,
+    /// Preferred frame rate of the overlay surface, `0.0` for no preference.
+    SetFrameRate(f32),
+    /// Whether to watch Android's night mode, i.e. the theme is "System".
+    FollowNightMode(bool),
+    /// Where the main surface takes touches, in physical pixels (see
+    /// [`JavaBridge::set_touchable_region`]). Answered with
+    /// [`JniEvent::TouchableRegion`] when the outcome changes.
+    SetTouchableRegion(Vec<egui::Rect>),
+    /// Replace the main surface after the render thread released its EGL
+    /// surface; answered with [`JniEvent::WindowRecreated`].
+    RecreateWindow,
+}
+
+/// Results and notifications from the JNI thread.
+pub enum JniEvent {
+    Paste(String),
+    /// The Android clipboard changed, possibly by another app.
+    ClipboardChanged(String),
+    NightMode(bool),
+    /// `None` if the surface could not be created.
+    OverlayCreated {
+        id: egui::ViewportId,
+        window: Option<(NativeWindow, egui::Pos2)>,
+    },
+    DisplayCaptured(Result<egui::ColorImage>),
+    DisplayModes(Result<DisplayModes>),
+    /// The display refresh rate in Hz, sent at startup and on change.
+    RefreshRate(f32),
+    /// Whether the compositor now routes touches outside the overlay's
+    /// windows to the apps underneath.
+    TouchableRegion(bool),
+    /// The main surface's Java `Surface` became invalid; the render thread
+    /// should release its EGL surface and send [`JniCommand::RecreateWindow`].
+    WindowLost,
+    /// The new main surface, whose frame rate and touchable region have
+    /// to be set again.
+    WindowRecreated(Result<NativeWindow>),
+}
+
+/// The render thread's end of the JNI thread: fire-and-forget commands in,
+/// events out. Neither direction ever blocks.
+pub struct JniHandle {
+    commands: Sender<JniCommand>,
+    events: Receiver<JniEvent>,
+}
+
+impl JniHandle {
+    pub fn send(&self, command: JniCommand) {
+        if self.commands.send(command).is_err() {
+            warn!("JNI thread is gone, dropping command");
+        }
+    }
+
+    /// Events that arrived since the last call.
+    pub fn events(&self) -> impl Iterator<Item = JniEvent> + '_ {
+        self.events.try_iter()
+    }
+}
+
+/// The JNI thread's end. Runs on a thread attached to the VM, which must be
+/// the one the [`JavaBridge`] was created on since it holds local refs.
+pub struct JniWorker {
+    commands: Receiver<JniCommand>,
+    events: Sender<JniEvent>,
+    clipboard: ClipboardWatcher,
+    night_mode: NightModeWatcher,
+    overlays: OverlaySurfaces,
+    main_surface: MainSurface,
+    refresh_rate: Option<f32>,
+    last_display_poll: Option<Instant>,
+    touchable_region: Option<bool>,
+    /// Whether [`JniEvent::WindowLost`] was sent for the current surface.
+    window_lost: bool,
+}
+
+/// `main_surface` is the surface frame rate requests apply to.
+pub fn channel(main_surface: MainSurface) -> (JniHandle, JniWorker) {
+    let (command_tx, command_rx) = mpsc::channel();
+    let (event_tx, event_rx) = mpsc::channel();
+    (
+        JniHandle {
+            commands: command_tx,
+            events: event_rx,
+        },
+        JniWorker {
+            commands: command_rx,
+            events: event_tx,
+            clipboard: ClipboardWatcher::new(),
+            night_mode: NightModeWatcher::new(),
+            overlays: OverlaySurfaces::default(),
+            main_surface,
+            refresh_rate: None,
+            last_display_poll: None,
+            touchable_region: None,
+            window_lost: false,
+        },
+    )
+}
+
+impl JniWorker {
+    /// Process commands until the render thread drops its [`JniHandle`],
+    /// then remove the surfaces this thread created.
+    pub fn run<'a>(mut self, bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>) {
+        with_local_frame(env, |env| self.clipboard.listen(bridge, env));
+        loop {
+            let command = match self.commands.recv_timeout(IDLE_TICK) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            with_local_frame(env, |env| {
+                if let Some(command) = command {
+                    self.handle(bridge, env, command);
+                }
+                self.poll(bridge, env);
+            });
+        }
+        debug!("JNI thread stopping");
+
+        with_local_frame(env, |env| {
+            self.clipboard.stop(env);
+            self.overlays.destroy_all(bridge, env);
+            match bridge.destroy_native_window(env, self.main_surface) {
+                Ok(()) => info!("Native window destroyed"),
+                Err(e) => warn!("Failed to destroy the native window: {e:#}"),
+            }
+        });
+    }
+
+    fn poll<'a>(&mut self, bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>) {
+        if let Some(text) = self.clipboard.take_change(bridge, env) {
+            self.emit(JniEvent::ClipboardChanged(text));
+        }
+        if let Some(night_mode) = self.night_mode.poll(bridge, env) {
+            self.emit(JniEvent::NightMode(night_mode));
+        }
+
+        if self
+            .last_display_poll
+            .is_some_and(|last| last.elapsed() < DISPLAY_POLL_INTERVAL)
+        {
+            return;
+        }
+        self.last_display_poll = Some(Instant::now());
+        match bridge.get_refresh_rate(env) {
+            Ok(rate) if Some(rate) != self.refresh_rate => {
+                info!("Display refresh rate: {:.1} Hz", rate);
+                self.refresh_rate = Some(rate);
+                self.emit(JniEvent::RefreshRate(rate));
+            }
+            Ok(_) => {}
+            Err(e) => debug!("Failed to read refresh rate: {e:#}"),
+        }
+
+        if !self.window_lost {
+            match bridge.is_native_window_valid(env, &self.main_surface) {
+                Ok(true) => {}
+                Ok(false) => {
+                    warn!("The main surface became invalid");
+                    self.window_lost = true;
+                    self.emit(JniEvent::WindowLost);
+                }
+                Err(e) => debug!("Failed to check the main surface: {e:#}"),
+            }
+        }
+    }
+
+    fn emit(&self, event: JniEvent) {
+        // Only fails once the render thread is gone, which ends `run` anyway.
+        let _ = self.events.send(event);
+    }
+
+    fn handle<'a>(&mut self, bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>, command: JniCommand) {
+        match command {
+            JniCommand::InjectTouch {
+                action,
+                pointer_id,
+                x,
+                y,
+            } => {
+                // Ignore errors – the JNI inject is best-effort
+                let _ = bridge.inject_touch_event(env, action, pointer_id, x, y);
+            }
+            JniCommand::ReadClipboard => {
+                if let Some(text) = self.clipboard.read(bridge, env) {
+                    self.emit(JniEvent::Paste(text));
+                }
+            }
+            JniCommand::SetClipboard(text) => self.clipboard.write(bridge, env, &text),
+            JniCommand::OpenUrl(url) => platform_output::open_url(bridge, env, &url),
+            JniCommand::CreateOverlay { id, name, rect } => {
+                let window = self.overlays.create(bridge, env, id, &name, rect);
+                self.emit(JniEvent::OverlayCreated {
+                    id,
+                    window: window.map(|window| (window, rect.min)),
+                });
+            }
+            JniCommand::MoveOverlay { id, rect } => self.overlays.move_to(bridge, env, id, rect),
+            JniCommand::DestroyOverlay(id) => self.overlays.destroy(bridge, env, id),
+            JniCommand::CaptureDisplay { width, height } => {
+                let image = images::capture_display(bridge, env, width, height);
+                self.emit(JniEvent::DisplayCaptured(image));
+            }
+            JniCommand::QueryDisplayModes => {
+                let modes = bridge.get_display_modes(env);
+                self.emit(JniEvent::DisplayModes(modes));
+            }
+            JniCommand::SetFrameRate(fps) => {
+                match bridge.set_frame_rate(env, &self.main_surface, fps) {
+                    Ok(()) => info!("Requested {fps} Hz for the overlay surface"),
+                    Err(e) => warn!("{e:#}"),
+                }
+                // Pick up the resulting mode switch soon.
+                self.last_display_poll = None;
+            }
+            JniCommand::FollowNightMode(follow) => self.night_mode.set_enabled(follow),
+            JniCommand::SetTouchableRegion(rects) => {
+                let applied = match bridge.set_touchable_region(env, &self.main_surface, &rects) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        // Only worth a warning the first time.
+                        if self.touchable_region != Some(false) {
+                            warn!("Cannot set the touchable region: {e:#}");
+                        }
+                        false
+                    }
+                };
+                if self.touchable_region != Some(applied) {
+                    self.touchable_region = Some(applied);
+                    self.emit(JniEvent::TouchableRegion(applied));
+                }
+            }
+            JniCommand::RecreateWindow => {
+                let result = bridge.recreate_native_window(env, &mut self.main_surface);
+                if result.is_ok() {
+                    info!("Main surface recreated");
+                    self.window_lost = false;
+                    self.touchable_region = None;
+                }
+                self.emit(JniEvent::WindowRecreated(
+                    result.map(|()| self.main_surface.window.clone()),
+                ));
+            }
+        }
+    }
+}
+
+/// Run `f` in a fresh JNI local frame, freeing every local reference it
+/// created.
+fn with_local_frame<'a, T>(env: &mut JNIEnv<'a>, f: impl FnOnce(&mut JNIEnv<'a>) -> T) -> T {
+    if let Err(e) = env.push_local_frame(LOCAL_FRAME_CAPACITY) {
+        warn!("PushLocalFrame failed: {e}");
+        return f(env);
+    }
+    let result = f(env);
+    // Nothing is carried out of the frame.
+    let _ = unsafe { env.pop_local_frame(&JObject::null()) };
+    result
+}
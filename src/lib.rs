@@ -0,0 +1,939 @@
+use ::jni::JNIEnv;
+use anyhow::{Context, Result};
+use ndk::native_window::NativeWindow;
+use tracing::info;
+
+use crate::android::runtime::AndroidRuntime;
+use crate::jni_thread::{JniCommand, JniEvent};
+
+pub use crate::shutdown::{Phase, exit_code};
+
+mod android;
+mod app_process;
+mod bridge;
+mod capabilities;
+mod clipboard;
+mod config;
+mod crash;
+mod cursor;
+mod dex;
+mod fonts;
+mod images;
+mod input;
+mod jni;
+mod jni_thread;
+mod library;
+mod logcat;
+mod overlay_windows;
+mod persistence;
+mod platform_output;
+mod renderer;
+mod shutdown;
+mod sysprop;
+mod theme;
+
+const SETTINGS_PACKAGE: &str = "com.android.settings";
+/// A framework drawable shown next to the app icon.
+const FRAMEWORK_DRAWABLE: &str = "ic_dialog_info";
+/// Texture cache key of the latest full-display screenshot.
+const DISPLAY_CAPTURE_KEY: &str = "display_capture";
+/// How often the live screen thumbnail is refreshed.
+const LIVE_CAPTURE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// How often the render loop checks for a new window while the main
+/// surface is being recreated.
+const WINDOW_WAIT: std::time::Duration = std::time::Duration::from_millis(16);
+
+/// The part of [`App`] that survives restarts.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct AppState {
+    checkbox_val: bool,
+    text: String,
+    theme: theme::ThemeChoice,
+    /// Whole-overlay opacity, applied by the renderer.
+    opacity: f32,
+    /// Extra opacity of the main window alone.
+    window_alpha: f32,
+    /// Show the FPS counter in its own small overlay surface.
+    fps_overlay: bool,
+    /// Preferred display refresh rate while the overlay is shown.
+    frame_rate: Option<f32>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            checkbox_val: false,
+            text: String::new(),
+            theme: theme::ThemeChoice::default(),
+            opacity: 1.0,
+            window_alpha: 1.0,
+            fps_overlay: false,
+            frame_rate: None,
+        }
+    }
+}
+
+struct App {
+    state: AppState,
+    touch_pos: Option<egui::Pos2>,
+    touch_count: u32,
+    last_event: String,
+    /// Screen size passed in so we can show % info in UI
+    screen_size: egui::Vec2,
+    textures: images::TextureCache,
+    /// Path typed into the image viewer.
+    image_path: String,
+    /// Texture cache key of the opened image file, and the texture or why it
+    /// couldn't be loaded.
+    image_file: Option<(String, Result<egui::TextureHandle, String>)>,
+    theme: theme::ThemeManager,
+    /// Style file given with `--style-file`, offered in the theme picker.
+    custom_style: Option<std::path::PathBuf>,
+    /// Screenshot requests from the UI, taken by the render loop.
+    save_frame: bool,
+    capture_display: bool,
+    /// Keep refreshing the screen thumbnail.
+    live_capture: bool,
+    capture_status: String,
+    display_modes: Option<bridge::DisplayModes>,
+    /// Refresh rate the display currently runs at.
+    refresh_rate: Option<f32>,
+    properties: sysprop::PropertyBrowser,
+    capabilities: capabilities::Capabilities,
+}
+
+impl App {
+    fn new(
+        ctx: &egui::Context,
+        screen_w: f32,
+        screen_h: f32,
+        state: AppState,
+        night_mode: bool,
+        capabilities: capabilities::Capabilities,
+    ) -> Self {
+        Self {
+            state,
+            touch_pos: None,
+            touch_count: 0,
+            last_event: "none".to_string(),
+            screen_size: egui::vec2(screen_w, screen_h),
+            textures: images::TextureCache::new(ctx),
+            image_path: String::new(),
+            image_file: None,
+            theme: theme::ThemeManager::new(night_mode),
+            custom_style: None,
+            save_frame: false,
+            capture_display: false,
+            live_capture: false,
+            capture_status: String::new(),
+            display_modes: None,
+            refresh_rate: None,
+            properties: sysprop::PropertyBrowser::default(),
+            capabilities,
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context) {
+        // Collect touch/pointer events from egui's input state for this frame.
+        ctx.input(|i| {
+            for event in &i.events {
+                match event {
+                    egui::Event::Touch { phase, pos, .. } => {
+                        match phase {
+                            egui::TouchPhase::Start => {
+                                self.touch_count += 1;
+                                self.touch_pos = Some(*pos);
+                                self.last_event =
+                                    format!("Touch DOWN ({:.0}, {:.0})", pos.x, pos.y);
+                            }
+                            egui::TouchPhase::Move => {
+                                self.touch_pos = Some(*pos);
+                                self.last_event =
+                                    format!("Touch MOVE ({:.0}, {:.0})", pos.x, pos.y);
+                            }
+                            egui::TouchPhase::End | egui::TouchPhase::Cancel => {
+                                self.touch_pos = None;
+                                self.last_event =
+                                    format!("Touch UP ({:.0}, {:.0})", pos.x, pos.y);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let dt = ctx.input(|i| i.unstable_dt);
+        let fps = if dt > 0.0 { 1.0 / dt } else { 0.0 };
+
+        // ── Touch ripple indicator ───────────────────────────────────────────
+        // Drawn on the foreground layer OUTSIDE the window so it appears on top
+        // of everything and always follows the finger position correctly.
+        if let Some(pos) = self.touch_pos {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Tooltip,
+                egui::Id::from("touch_ripple_layer"),
+            ));
+            painter.circle(
+                pos,
+                24.0,
+                egui::Color32::from_rgba_unmultiplied(255, 200, 0, 60),
+                egui::Stroke::new(3.0, egui::Color32::from_rgb(255, 200, 0)),
+            );
+            // Inner dot
+            painter.circle_filled(pos, 5.0, egui::Color32::from_rgb(255, 200, 0));
+        }
+        self.theme.apply(ctx, &self.state.theme);
+
+        if self.state.fps_overlay {
+            show_fps_overlay(ctx);
+        }
+
+        // ── Main window ───────────────────────────────────────────────────────
+        egui::Window::new(format!("EGUI - FPS: {:.1}", fps))
+            .id(egui::Id::from("MainWindow"))
+            .default_pos(ctx.viewport_rect().center())
+            .default_width(400.0)
+            .default_height(300.0)
+            .resizable(true)
+            .title_bar(true)
+            //.anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.heading("Welcome to EGUI");
+                ui.separator();
+
+                ui.group(|ui| {
+                    ui.label("Touch Input Status");
+                    ui.label(
+                        egui::RichText::new(&self.last_event).color(egui::Color32::YELLOW),
+                    );
+                    ui.label(format!("Total touches: {}", self.touch_count));
+                    ui.label(format!(
+                        "Screen: {:.0} x {:.0}",
+                        self.screen_size.x, self.screen_size.y
+                    ));
+                    ui.label(format!("Pixels per point: {:.2}", ctx.pixels_per_point()));
+                    if let Some(pos) = self.touch_pos {
+                        // Show both absolute points and % of screen so we can verify mapping
+                        let viewport = ctx.viewport_rect().size();
+                        let pct_x = pos.x / viewport.x * 100.0;
+                        let pct_y = pos.y / viewport.y * 100.0;
+                        ui.label(egui::RichText::new(format!(
+                            "pt ({:.0}, {:.0})  =  {:.1}% , {:.1}%",
+                            pos.x, pos.y, pct_x, pct_y
+                        )).color(egui::Color32::from_rgb(100, 220, 255)));
+                    } else {
+                        ui.label(egui::RichText::new("No active touch").weak());
+                    }
+                });
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Buttons:");
+                    if ui.button("Click Me").clicked() {
+                        self.last_event = "Button 'Click Me' tapped!".to_string();
+                    }
+                    if ui.button("Another Button").clicked() {
+                        self.last_event = "Button 'Another' tapped!".to_string();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("UI scale:");
+                    let mut zoom = ctx.zoom_factor();
+                    if ui
+                        .add(egui::Slider::new(&mut zoom, 0.5..=3.0).step_by(0.05))
+                        .changed()
+                    {
+                        ctx.set_zoom_factor(zoom);
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Opacity:");
+                    // Keep a floor so the overlay can't be made unreachable.
+                    ui.add(egui::Slider::new(&mut self.state.opacity, 0.2..=1.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Window alpha:");
+                    ui.add(egui::Slider::new(&mut self.state.window_alpha, 0.2..=1.0));
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Theme:");
+                    theme::ThemeManager::picker(
+                        ui,
+                        &mut self.state.theme,
+                        self.custom_style.as_deref(),
+                    );
+                    if matches!(self.state.theme, theme::ThemeChoice::Custom(_))
+                        && ui.button("Reload").clicked()
+                    {
+                        self.theme.reload();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Text:");
+                    let response = ui.text_edit_singleline(&mut self.state.text);
+                    clipboard::paste_menu(&response);
+                });
+
+                ui.collapsing("Images", |ui| self.images_ui(ui));
+
+                ui.collapsing("Screenshots", |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Save overlay PNG").clicked() {
+                            self.save_frame = true;
+                        }
+                        ui.add_enabled_ui(self.capabilities.read_frame_buffer, |ui| {
+                            if ui
+                                .button("Capture screen")
+                                .on_disabled_hover_text("Needs READ_FRAME_BUFFER (shell or root)")
+                                .clicked()
+                            {
+                                self.capture_display = true;
+                            }
+                            ui.checkbox(&mut self.live_capture, "Live");
+                        });
+                    });
+                    if !self.capture_status.is_empty() {
+                        ui.label(egui::RichText::new(&self.capture_status).weak());
+                    }
+                    if let Some(screen) = self.textures.get(DISPLAY_CAPTURE_KEY) {
+                        ui.add(egui::Image::new(&screen).max_height(200.0));
+                    }
+                });
+
+                ui.collapsing("Display", |ui| self.display_ui(ui));
+
+                ui.collapsing("System properties", |ui| self.properties.ui(ui));
+
+                ui.collapsing("Diagnostics", |ui| self.capabilities.ui(ui));
+
+                ui.checkbox(&mut self.state.fps_overlay, "Floating FPS counter");
+
+                ui.hyperlink_to("egui documentation", "https://docs.rs/egui");
+
+                ui.collapsing("Expandable Section", |ui| {
+                    ui.label("Additional details.");
+                    ui.checkbox(&mut self.state.checkbox_val, "Sample Checkbox");
+                    if self.state.checkbox_val {
+                        ui.label(
+                            egui::RichText::new("Checkbox is ON").color(egui::Color32::GREEN),
+                        );
+                    }
+                });
+            });
+    }
+}
+
+impl App {
+    fn images_ui(&mut self, ui: &mut egui::Ui) {
+        for key in [SETTINGS_PACKAGE, FRAMEWORK_DRAWABLE] {
+            ui.horizontal(|ui| {
+                match self.textures.get(key) {
+                    Some(icon) => {
+                        ui.add(egui::Image::new(&icon).fit_to_exact_size(egui::vec2(48.0, 48.0)));
+                    }
+                    None => {
+                        ui.label(egui::RichText::new("Unavailable").weak());
+                    }
+                }
+                ui.label(key);
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            ui.text_edit_singleline(&mut self.image_path);
+            if ui.button("Open").clicked() {
+                let key = format!("file:{}", self.image_path);
+                let image = self
+                    .textures
+                    .get_or_insert_with(&key, || images::load_file(&self.image_path))
+                    .map_err(|e| format!("{e:#}"));
+                self.image_file = Some((key, image));
+            }
+            if self.image_file.is_some()
+                && ui.button("Close").clicked()
+                && let Some((key, _)) = self.image_file.take()
+            {
+                self.textures.remove(&key);
+            }
+        });
+        match &self.image_file {
+            Some((_, Ok(image))) => {
+                ui.add(egui::Image::new(image).max_height(200.0));
+            }
+            Some((_, Err(e))) => {
+                ui.label(egui::RichText::new(e).weak());
+            }
+            None => {}
+        }
+    }
+
+    fn display_ui(&mut self, ui: &mut egui::Ui) {
+        if let Some(rate) = self.refresh_rate {
+            ui.label(format!("Refresh rate: {rate:.1} Hz"));
+        }
+        let Some(display_modes) = &self.display_modes else {
+            ui.label(egui::RichText::new("Display modes unavailable").weak());
+            return;
+        };
+
+        for mode in &display_modes.modes {
+            let mut text = format!(
+                "{}x{} @ {:.1} Hz",
+                mode.width, mode.height, mode.refresh_rate
+            );
+            if !mode.hdr_types.is_empty() {
+                let hdr = mode
+                    .hdr_types
+                    .iter()
+                    .map(|&t| bridge::DisplayMode::hdr_type_name(t))
+                    .collect::<Vec<_>>();
+                text += &format!(" ({})", hdr.join(", "));
+            }
+            let text = egui::RichText::new(text);
+            ui.label(if mode.id == display_modes.active {
+                text.strong()
+            } else {
+                text
+            });
+        }
+
+        let mut rates = display_modes
+            .modes
+            .iter()
+            .map(|mode| mode.refresh_rate)
+            .collect::<Vec<_>>();
+        rates.sort_by(f32::total_cmp);
+        rates.dedup_by(|a, b| (*a - *b).abs() < 0.5);
+
+        ui.horizontal(|ui| {
+            ui.label("Frame rate:");
+            let selected = match self.state.frame_rate {
+                Some(rate) => format!("{rate:.0} Hz"),
+                None => "Default".to_owned(),
+            };
+            egui::ComboBox::from_id_salt("frame_rate")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.state.frame_rate, None, "Default");
+                    for rate in rates {
+                        ui.selectable_value(
+                            &mut self.state.frame_rate,
+                            Some(rate),
+                            format!("{rate:.0} Hz"),
+                        );
+                    }
+                });
+        });
+    }
+}
+
+/// A separate viewport, so it lives on its own small native surface instead
+/// of the full-screen one.
+fn show_fps_overlay(ctx: &egui::Context) {
+    ctx.show_viewport_deferred(
+        egui::ViewportId::from_hash_of("fps_overlay"),
+        egui::ViewportBuilder::default()
+            .with_title("FPS")
+            .with_position([16.0, 48.0])
+            .with_inner_size([96.0, 32.0]),
+        |ctx, class| {
+            let dt = ctx.input(|i| i.unstable_dt);
+            let text = format!("{:.0} FPS", if dt > 0.0 { 1.0 / dt } else { 0.0 });
+            if class == egui::ViewportClass::Embedded {
+                egui::Window::new("FPS").show(ctx, |ui| ui.label(text));
+            } else {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.centered_and_justified(|ui| ui.label(egui::RichText::new(text).strong()));
+                });
+            }
+        },
+    );
+}
+
+/// Set up logging to stdout and/or logcat as configured. Tolerates a
+/// subscriber that is already installed, e.g. when loaded into a process
+/// twice.
+///
+/// `owns_process` is false inside an app, whose liblog output is left alone.
+fn init_logging(config: &config::Config, owns_process: bool) {
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    let target = config.log_target;
+    let stdout = target.stdout().then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_level(true)
+            .without_time()
+    });
+    let logcat = target
+        .logcat()
+        .then(|| logcat::LogcatLayer::new(config.log_tag()));
+    let installed = tracing_subscriber::registry()
+        .with(config.log_level())
+        .with(stdout)
+        .with(logcat)
+        .try_init()
+        .is_ok();
+    if !installed {
+        return;
+    }
+
+    // Java's android.util.Log already ends up in logcat; copy it to stdout.
+    if owns_process && target.stdout() && !logcat::forward_to_tracing(config.log_tag()) {
+        tracing::debug!("Cannot forward android.util.Log to stdout before Android 11");
+    }
+    for arg in &config.unknown_args {
+        tracing::warn!("Ignoring unknown argument: {arg}");
+    }
+}
+
+/// Run as a standalone executable: create a VM of our own, register the
+/// framework natives and show the overlay until it exits or a signal stops
+/// it, then tear the VM down. Depending on `--launch-mode`, run
+/// `app_process` as a child instead.
+///
+/// Errors carry the [`Phase`] they happened in; see [`exit_code`].
+pub fn run_standalone() -> Result<()> {
+    let config = config::Config::from_args().context(Phase::Config)?;
+    init_logging(&config, true);
+    // Before the VM exists, so ART's signal chaining keeps our handlers.
+    if let Err(e) = crash::install(&config) {
+        tracing::warn!("Crash reporting unavailable: {e:#}");
+    }
+
+    shutdown::install_signal_handlers().context(Phase::Config)?;
+
+    let device = android::DeviceInfo::current().context(Phase::Runtime)?;
+    info!("{device}");
+
+    if config.launch_mode == config::LaunchMode::AppProcess {
+        return app_process::run(&config);
+    }
+
+    let vm_options = config.java_vm_options().context(Phase::Config)?;
+    let (_runtime, vm) = match start_vm(&vm_options) {
+        Ok(started) => started,
+        // app_process runs as a child, so whatever got set up here is harmless.
+        Err(e) if config.launch_mode == config::LaunchMode::Auto => {
+            tracing::warn!("{e:#}; falling back to app_process");
+            return app_process::run(&config);
+        }
+        Err(e) => return Err(e),
+    };
+    let mut env = vm.attach_current_thread().context(Phase::Vm)?;
+
+    let bridge = bridge::JavaBridge::new(&mut env).context(Phase::Bridge)?;
+    info!("Bridge initialized");
+
+    bridge.call_main(&mut env).context(Phase::Bridge)?;
+
+    let result = run_overlay(&bridge, &mut env, config);
+
+    // Detach before the VM goes away; the bridge's local refs go with it.
+    drop(env);
+    shutdown::start_watchdog();
+    let teardown = unsafe { vm.destroy() };
+    match teardown {
+        Ok(()) => info!("Java VM destroyed"),
+        Err(e) if result.is_ok() => return Err(anyhow::Error::new(e).context(Phase::Teardown)),
+        Err(e) => tracing::warn!("DestroyJavaVM failed: {e}"),
+    }
+    result
+}
+
+/// Load the runtime, create the VM (patching `AndroidRuntime::mJavaVM`) and
+/// register the framework natives. The runtime must outlive the VM.
+fn start_vm(vm_options: &android::JavaVmOptions) -> Result<(AndroidRuntime, ::jni::JavaVM)> {
+    let mut runtime = AndroidRuntime::load().context(Phase::Runtime)?;
+    runtime.init_invocation().context(Phase::Runtime)?;
+    let vm = runtime.create_java_vm(vm_options).context(Phase::Vm)?;
+    // Already attached by JNI_CreateJavaVM, so this doesn't detach on drop.
+    let mut env = vm.attach_current_thread().context(Phase::Vm)?;
+    runtime.start_registration(&mut env).context(Phase::Vm)?;
+    drop(env);
+    Ok((runtime, vm))
+}
+
+/// Show the overlay on a thread attached to a VM that is already set up,
+/// serving JNI requests on it until the render thread exits. The window and
+/// overlay layers are removed before returning.
+fn run_overlay<'a>(
+    bridge: &bridge::JavaBridge<'a>,
+    env: &mut JNIEnv<'a>,
+    config: config::Config,
+) -> Result<()> {
+    let capabilities = capabilities::Capabilities::probe(bridge, env);
+
+    let (width, height, rotation) = bridge.get_display_size(env).context(Phase::Window)?;
+
+    let (width, height) = if rotation == 0 || rotation == 2 {
+        (height, width)
+    } else {
+        (width, height)
+    };
+
+    let surface = bridge
+        .create_native_window(env, width, height)
+        .context(Phase::Window)?;
+    let window = surface.window.clone();
+    info!("Window Size : {}x{}", window.width(), window.height());
+
+    let density = bridge
+        .get_display_density(env)
+        .or_else(|e| {
+            tracing::warn!("DisplayMetrics unavailable ({e}), falling back to ro.sf.lcd_density");
+            android::get_lcd_density().map(|dpi| dpi as f32 / 160.0)
+        })
+        .context(Phase::Window)?;
+
+    // Read once here so "System" starts in the right flavour; the JNI thread
+    // keeps it up to date afterwards.
+    let night_mode = bridge.is_night_mode(env).unwrap_or_else(|e| {
+        tracing::warn!("Failed to read night mode: {e:#}");
+        false
+    });
+
+    let mut icons = Vec::new();
+    match images::load_app_icon(bridge, env, SETTINGS_PACKAGE) {
+        Ok(icon) => icons.push((SETTINGS_PACKAGE, icon)),
+        Err(e) => tracing::warn!("{e:#}"),
+    }
+    match images::load_package_drawable(bridge, env, "android", "drawable", FRAMEWORK_DRAWABLE) {
+        Ok(icon) => icons.push((FRAMEWORK_DRAWABLE, icon)),
+        Err(e) => tracing::warn!("{e:#}"),
+    }
+
+    // The render thread owns EGL and the UI; this thread keeps the JNI env
+    // and the bridge (whose class ref is only valid here) and serves the
+    // render thread's requests until it exits.
+    let (jni, jni_worker) = jni_thread::channel(surface);
+    let startup = RenderStartup {
+        config,
+        window,
+        width,
+        height,
+        rotation,
+        density,
+        night_mode,
+        icons,
+        capabilities,
+    };
+    let render_thread = std::thread::Builder::new()
+        .name("render".to_owned())
+        .spawn(move || {
+            let _alt_stack = crash::AltStack::install();
+            render_loop(startup, jni)
+        })
+        .context(Phase::Render)?;
+    info!("Render thread started");
+
+    jni_worker.run(bridge, env);
+
+    match render_thread.join() {
+        Ok(result) => result.context(Phase::Render),
+        Err(_) => Err(anyhow::anyhow!("Render thread panicked").context(Phase::Render)),
+    }
+}
+
+/// Everything the render thread needs from the JNI-side setup.
+struct RenderStartup {
+    config: config::Config,
+    window: NativeWindow,
+    width: i32,
+    height: i32,
+    rotation: i32,
+    density: f32,
+    /// Android's night mode at startup.
+    night_mode: bool,
+    /// Icons loaded through the bridge, by texture cache key.
+    icons: Vec<(&'static str, egui::ColorImage)>,
+    capabilities: capabilities::Capabilities,
+}
+
+fn render_loop(startup: RenderStartup, jni: jni_thread::JniHandle) -> Result<()> {
+    let RenderStartup {
+        config,
+        window,
+        width,
+        height,
+        rotation,
+        density,
+        night_mode,
+        icons,
+        capabilities,
+    } = startup;
+
+    let mut renderer = renderer::Renderer::with_preferences(&window, &config.egl_preferences())?;
+    info!("Renderer using {:?}", renderer.gles_version);
+
+    renderer.set_native_pixels_per_point(density);
+    let mut storage = persistence::Storage::open(&config.data_dir());
+    storage.restore_memory(&renderer.egui_context);
+    // An explicit --ui-scale wins over the zoom factor restored from memory.
+    if let Some(scale) = config.ui_scale {
+        renderer.set_ui_scale(scale);
+    }
+
+    match fonts::install_system_fonts(&renderer.egui_context, &fonts::FontOptions::default()) {
+        Ok(count) => info!("Installed {count} system fallback fonts"),
+        Err(e) => tracing::warn!("System font discovery failed: {e:#}"),
+    }
+
+    // Start the input reader thread.
+    // It reads raw Linux multitouch events from /dev/input and converts them to egui events.
+    // Stopped and joined when dropped at the end of this function.
+    // Without access to the devices there is nothing to read.
+    let (input_rx, _input_thread) = if capabilities.can_read_input() {
+        let (input_rx, input_thread) =
+            input::start_input_thread(width as f32, height as f32, rotation);
+        info!("Input thread started");
+        (Some(input_rx), Some(input_thread))
+    } else {
+        (None, None)
+    };
+    let inject_events = capabilities.inject_events;
+
+    let mut app = App::new(
+        &renderer.egui_context,
+        width as f32,
+        height as f32,
+        storage.get(persistence::APP_KEY).unwrap_or_default(),
+        night_mode,
+        capabilities,
+    );
+    if let Some(rate) = config.frame_rate {
+        app.state.frame_rate = Some(rate);
+    }
+    if let Some(path) = &config.style_file {
+        app.custom_style = Some(path.clone());
+        app.state.theme = theme::ThemeChoice::Custom(path.clone());
+    }
+    for (key, icon) in icons {
+        app.textures.insert(key, icon);
+    }
+
+    let mut output_handler = platform_output::PlatformOutputHandler::new();
+    output_handler.set_on_ime(|ime| match ime {
+        Some(ime) => tracing::debug!("Text input active at {:?}", ime.cursor_rect),
+        None => tracing::debug!("Text input inactive"),
+    });
+    output_handler.set_on_event(|event| tracing::debug!("{}", event.widget_info().description()));
+    let mut cursor = cursor::SoftwareCursor::default();
+    let mut clipboard = clipboard::Clipboard::default();
+    clipboard.set_on_change(|text| info!("Clipboard changed ({} bytes)", text.len()));
+
+    let mut overlay_touches = std::collections::HashSet::new();
+    // Sent to the compositor whenever it changes. Once it is applied there,
+    // touches outside it already reach the apps and aren't injected.
+    let mut touchable_region_sent = None;
+    let mut compositor_passes_touches = false;
+    let mut overlay_windows = overlay_windows::OverlayWindows::default();
+    // Requested below whenever `app.state.frame_rate` differs.
+    let mut frame_rate_sent = None;
+    let mut follow_night_mode_sent = None;
+    jni.send(JniCommand::QueryDisplayModes);
+    let mut display_capture_pending = false;
+    let mut last_display_capture = std::time::Instant::now();
+    // Set while the JNI thread replaces a lost main surface.
+    let mut window_requested = false;
+
+    info!("Starting Render Loop");
+    loop {
+        let mut frame_events = Vec::new();
+
+        // Answers and notifications from the JNI thread.
+        for event in jni.events() {
+            match event {
+                JniEvent::Paste(text) => frame_events.push(egui::Event::Paste(text)),
+                JniEvent::ClipboardChanged(text) => clipboard.changed(&text),
+                JniEvent::NightMode(night_mode) => app.theme.set_night_mode(night_mode),
+                JniEvent::OverlayCreated { id, window } => {
+                    overlay_windows.on_created(&mut renderer, &jni, id, window)
+                }
+                JniEvent::DisplayModes(Ok(modes)) => app.display_modes = Some(modes),
+                JniEvent::DisplayModes(Err(e)) => {
+                    tracing::warn!("Cannot list display modes: {e:#}")
+                }
+                JniEvent::RefreshRate(rate) => {
+                    renderer.set_refresh_rate(rate);
+                    app.refresh_rate = Some(rate);
+                }
+                JniEvent::TouchableRegion(applied) => {
+                    let by = if applied { "compositor" } else { "injection" };
+                    info!("Passing touches through by {by}");
+                    compositor_passes_touches = applied;
+                }
+                JniEvent::DisplayCaptured(result) => {
+                    display_capture_pending = false;
+                    app.capture_status = match result {
+                        Ok(image) => {
+                            let status = format!("Screen {}x{}", image.width(), image.height());
+                            app.textures.insert(DISPLAY_CAPTURE_KEY, image);
+                            status
+                        }
+                        Err(e) => {
+                            tracing::warn!("Screen capture failed: {e:#}");
+                            // Don't retry every second when permissions are missing.
+                            app.live_capture = false;
+                            format!("{e:#}")
+                        }
+                    };
+                }
+                JniEvent::WindowLost => renderer.release_surface(),
+                JniEvent::WindowRecreated(result) => {
+                    window_requested = false;
+                    let window = result.context("Recreating the main surface")?;
+                    renderer.replace_window(&window)?;
+                    // Settings of the old surface don't carry over.
+                    touchable_region_sent = None;
+                    frame_rate_sent = None;
+                }
+            }
+        }
+
+        if renderer.needs_window() {
+            if !window_requested {
+                window_requested = true;
+                jni.send(JniCommand::RecreateWindow);
+            }
+            if shutdown::requested() {
+                info!("Shutdown requested");
+                break;
+            }
+            // Nothing to draw into until the new window arrives.
+            std::thread::sleep(WINDOW_WAIT);
+            continue;
+        }
+
+        // Drain all pending touch events from the input thread before rendering.
+        // try_recv is non-blocking so the render loop never stalls waiting for input.
+        while let Some(events) = input_rx.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            frame_events.extend(events.iter().cloned());
+
+            // Forward touches that land outside our windows to Android's own
+            // InputManager via JNI, so the apps underneath still receive them.
+            // A touch sequence stays with whoever got its Start event.
+            for event in &events {
+                if let egui::Event::Touch { id, phase, pos, .. } = event {
+                    let on_overlay = match phase {
+                        egui::TouchPhase::Start => {
+                            let inside = renderer.input_region().contains(*pos);
+                            if inside {
+                                overlay_touches.insert(id.0);
+                            }
+                            inside
+                        }
+                        egui::TouchPhase::Move => overlay_touches.contains(&id.0),
+                        egui::TouchPhase::End | egui::TouchPhase::Cancel => {
+                            overlay_touches.remove(&id.0)
+                        }
+                    };
+                    if on_overlay || !inject_events || compositor_passes_touches {
+                        continue;
+                    }
+                    let action = match phase {
+                        egui::TouchPhase::Start => 0,
+                        egui::TouchPhase::End | egui::TouchPhase::Cancel => 1,
+                        egui::TouchPhase::Move => 2,
+                    };
+                    jni.send(JniCommand::InjectTouch {
+                        action,
+                        pointer_id: id.0 as i64,
+                        x: pos.x as i32,
+                        y: pos.y as i32,
+                    });
+                }
+            }
+        }
+
+        // Forward raw egui events into the renderer's next RawInput batch.
+        if clipboard::process_events(&renderer.egui_context, &mut frame_events) {
+            jni.send(JniCommand::ReadClipboard);
+        }
+        renderer.push_events(frame_events);
+
+        renderer.set_opacity(app.state.opacity);
+        renderer.set_window_alpha(egui::Id::new("MainWindow"), app.state.window_alpha);
+        let platform_output = renderer.render(|ctx| {
+            ctx.input(|i| cursor.observe_events(&i.events));
+            app.update(ctx);
+            cursor.paint(ctx);
+        });
+        for text in clipboard::copied_texts(&platform_output) {
+            jni.send(JniCommand::SetClipboard(text.to_owned()));
+        }
+        for url in platform_output::urls(&platform_output) {
+            jni.send(JniCommand::OpenUrl(url.to_owned()));
+        }
+        output_handler.handle(&platform_output, renderer.pixels_per_point());
+        cursor.set_icon(platform_output.cursor_icon);
+        overlay_windows.sync(&mut renderer, &jni);
+        if touchable_region_sent.as_ref() != Some(renderer.input_region()) {
+            let region = renderer.input_region().clone();
+            jni.send(JniCommand::SetTouchableRegion(region.rects.clone()));
+            touchable_region_sent = Some(region);
+        }
+
+        if app.state.frame_rate != frame_rate_sent {
+            frame_rate_sent = app.state.frame_rate;
+            jni.send(JniCommand::SetFrameRate(frame_rate_sent.unwrap_or(0.0)));
+        }
+        let follow_night_mode = app.state.theme == theme::ThemeChoice::System;
+        if follow_night_mode_sent != Some(follow_night_mode) {
+            follow_night_mode_sent = Some(follow_night_mode);
+            jni.send(JniCommand::FollowNightMode(follow_night_mode));
+        }
+
+        // The back buffer must be read before it is swapped.
+        if std::mem::take(&mut app.save_frame) {
+            app.capture_status = match save_frame(&renderer, &config.data_dir()) {
+                Ok(path) => format!("Saved {}", path.display()),
+                Err(e) => {
+                    tracing::warn!("{e:#}");
+                    format!("{e:#}")
+                }
+            };
+        }
+        let live_due = app.live_capture && last_display_capture.elapsed() >= LIVE_CAPTURE_INTERVAL;
+        if (std::mem::take(&mut app.capture_display) || live_due) && !display_capture_pending {
+            display_capture_pending = true;
+            last_display_capture = std::time::Instant::now();
+            jni.send(JniCommand::CaptureDisplay {
+                width: renderer.width,
+                height: renderer.height,
+            });
+        }
+
+        renderer.swap_buffers()?;
+        storage.maybe_save(&renderer.egui_context, &app.state);
+
+        if shutdown::requested() {
+            info!("Shutdown requested");
+            break;
+        }
+    }
+    storage.save(&renderer.egui_context, &app.state);
+    // Dropping the renderer destroys the EGL surfaces and context; the
+    // window itself is removed by the JNI thread once our handle is gone.
+    Ok(())
+}
+
+/// Write the frame just rendered to `<dir>/overlay-<unix time>.png`.
+fn save_frame(renderer: &renderer::Renderer, dir: &std::path::Path) -> Result<std::path::PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let path = dir.join(format!("overlay-{secs}.png"));
+    images::save_png(&renderer.capture_frame(), &path)?;
+    info!("Saved overlay screenshot to {}", path.display());
+    Ok(path)
+}
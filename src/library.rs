@@ -0,0 +1,235 @@
+//! Library mode: the overlay runs inside a process that already has a VM,
+//! such as an app that calls `System.loadLibrary`, or the `app_process` that
+//! [`crate::app_process::run`] starts, whose entry class loads this library.
+//!
+//! The host's runtime is fully set up there, so no VM is created and
+//! `startReg` is skipped. The dex `main` (which prepares a main looper and a
+//! system `ActivityThread`) only runs if the process has neither (see
+//! [`prepare_framework`]).
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use jni::objects::{JClass, JObjectArray, JString};
+use jni::sys::{JNI_ERR, JNI_VERSION_1_6, jint};
+use jni::{JNIEnv, JavaVM};
+use tracing::{error, info, warn};
+
+use crate::android::DeviceInfo;
+use crate::android::runtime::AndroidRuntime;
+use crate::app_process::LIBRARY_ENV;
+use crate::bridge::JavaBridge;
+use crate::config::Config;
+use crate::jni::jni_result_ext::JniResultExt;
+use crate::shutdown::Phase;
+
+/// Set once the overlay thread has been started; loading the library again
+/// or calling [`android_egui_ext_start`] twice must not open a second one.
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Called by the VM from `System.load`/`System.loadLibrary`.
+#[unsafe(no_mangle)]
+pub extern "system" fn JNI_OnLoad(vm: *mut jni::sys::JavaVM, _reserved: *mut c_void) -> jint {
+    // Loaded by the `app_process` entry class, which calls its `run` next.
+    if std::env::var_os(LIBRARY_ENV).is_some() {
+        return JNI_VERSION_1_6;
+    }
+    let Some(config) = setup() else {
+        return JNI_ERR;
+    };
+    match unsafe { JavaVM::from_raw(vm) } {
+        Ok(vm) => {
+            spawn(move || run(vm, config));
+            JNI_VERSION_1_6
+        }
+        Err(e) => {
+            error!("JNI_OnLoad got an invalid VM: {e}");
+            JNI_ERR
+        }
+    }
+}
+
+/// Entry point for native loaders (e.g. `dlopen` from an injector) that
+/// have no `JavaVM` to hand over; it is found with `JNI_GetCreatedJavaVMs`.
+///
+/// Returns whether the overlay thread was started.
+#[unsafe(no_mangle)]
+pub extern "C" fn android_egui_ext_start() -> bool {
+    let Some(config) = setup() else {
+        return false;
+    };
+    let vm = AndroidRuntime::load().and_then(|mut runtime| runtime.existing_java_vm());
+    match vm {
+        Ok(vm) => spawn(move || run(vm, config)),
+        Err(e) => {
+            error!("Cannot find the process VM: {e:#}");
+            false
+        }
+    }
+}
+
+/// `AppProcessEntry.run(args)`, called on the `app_process` main thread
+/// once the entry class has loaded this library. Returns when the overlay
+/// closes; on failure the process exits with the [`Phase`]'s exit code.
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_example_mylibrary_AppProcessEntry_run<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    args: JObjectArray<'local>,
+) {
+    if let Err(e) = run_in_app_process(&mut env, &args) {
+        error!("{e:#}");
+        eprintln!("Error: {e:?}");
+        std::process::exit(crate::shutdown::exit_status(&e).into());
+    }
+}
+
+/// Read options from the environment and start logging. `None` if the
+/// options are invalid, which is logged with the defaults.
+fn setup() -> Option<Config> {
+    match Config::from_env() {
+        Ok(config) => {
+            start_logging(&config, false);
+            Some(config)
+        }
+        Err(e) => {
+            crate::init_logging(&Config::default(), false);
+            error!("Invalid {}: {e:#}", crate::config::ARGS_ENV);
+            None
+        }
+    }
+}
+
+fn start_logging(config: &Config, owns_process: bool) {
+    crate::init_logging(config, owns_process);
+    match DeviceInfo::current() {
+        Ok(device) => info!("{device}"),
+        Err(e) => warn!("Cannot read device info: {e:#}"),
+    }
+}
+
+/// Run the overlay on a thread of its own, so the loader returns at once.
+fn spawn(run: impl FnOnce() -> Result<()> + Send + 'static) -> bool {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        warn!("Overlay already running in this process");
+        return false;
+    }
+
+    let thread = std::thread::Builder::new()
+        .name("egui-ext".to_owned())
+        .spawn(move || {
+            let _alt_stack = crate::crash::AltStack::install();
+            if let Err(e) = run() {
+                error!("Overlay stopped: {e:#}");
+            }
+        });
+    match thread {
+        Ok(_) => true,
+        Err(e) => {
+            error!("Cannot start the overlay thread: {e}");
+            STARTED.store(false, Ordering::SeqCst);
+            false
+        }
+    }
+}
+
+fn run(vm: JavaVM, config: Config) -> Result<()> {
+    // The worker loop never returns to Java, so stay attached for good.
+    let mut env = vm.attach_current_thread_permanently()?;
+
+    let bridge = JavaBridge::new(&mut env)?;
+    info!("Bridge initialized (library mode)");
+    prepare_framework(&bridge, &mut env)?;
+
+    crate::run_overlay(&bridge, &mut env, config)
+}
+
+/// Make sure the framework state the bridge relies on exists: a main looper,
+/// an `ActivityThread` and the dex's system context. An app has the first
+/// two already; a bare process (e.g. reached through
+/// [`android_egui_ext_start`]) gets all of them from the dex `main`.
+fn prepare_framework<'a>(bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>) -> Result<()> {
+    match (main_looper_prepared(env)?, activity_thread_started(env)?) {
+        (true, true) => {}
+        (false, false) => {
+            info!("No main looper or ActivityThread yet, running the dex main");
+            bridge
+                .call_main(env)
+                .context("Preparing the framework with the dex main")?;
+        }
+        (true, false) => anyhow::bail!(
+            "The process has a main looper but no ActivityThread, so it can't host the overlay"
+        ),
+        (false, true) => anyhow::bail!(
+            "The process has an ActivityThread but no main looper, so it can't host the overlay"
+        ),
+    }
+
+    let context = bridge.get_system_context(env)?;
+    if context.is_null() {
+        anyhow::bail!("The dex has no system context in this process");
+    }
+    env.delete_local_ref(context)?;
+    Ok(())
+}
+
+fn main_looper_prepared(env: &mut JNIEnv) -> Result<bool> {
+    let looper = env
+        .call_static_method(
+            "android/os/Looper",
+            "getMainLooper",
+            "()Landroid/os/Looper;",
+            &[],
+        )
+        .check_exception(env)?
+        .l()?;
+    let prepared = !looper.is_null();
+    env.delete_local_ref(looper)?;
+    Ok(prepared)
+}
+
+/// The process is ours, started by [`crate::app_process::run`] with our
+/// command line, and this is its main thread.
+fn run_in_app_process<'local>(env: &mut JNIEnv<'local>, args: &JObjectArray<'local>) -> Result<()> {
+    let args = java_args(env, args).context(Phase::Config)?;
+    let config = Config::parse(args).context(Phase::Config)?;
+    start_logging(&config, true);
+    // ART's handlers run first and pass on the signals they don't handle.
+    if let Err(e) = crate::crash::install(&config) {
+        warn!("Crash reporting unavailable: {e:#}");
+    }
+    crate::shutdown::install_signal_handlers().context(Phase::Config)?;
+
+    let bridge = JavaBridge::from_class_path(env).context(Phase::Bridge)?;
+    info!("Bridge initialized (app_process mode)");
+    prepare_framework(&bridge, env).context(Phase::Bridge)?;
+
+    crate::run_overlay(&bridge, env, config)
+}
+
+fn java_args(env: &mut JNIEnv, args: &JObjectArray) -> Result<Vec<String>> {
+    let len = env.get_array_length(args)?;
+    let mut parsed = Vec::with_capacity(len as usize);
+    for i in 0..len {
+        let arg = JString::from(env.get_object_array_element(args, i)?);
+        parsed.push(env.get_string(&arg)?.into());
+        env.delete_local_ref(arg)?;
+    }
+    Ok(parsed)
+}
+
+fn activity_thread_started(env: &mut JNIEnv) -> Result<bool> {
+    let thread = env
+        .call_static_method(
+            "android/app/ActivityThread",
+            "currentActivityThread",
+            "()Landroid/app/ActivityThread;",
+            &[],
+        )
+        .check_exception(env)?
+        .l()?;
+    let started = !thread.is_null();
+    env.delete_local_ref(thread)?;
+    Ok(started)
+}
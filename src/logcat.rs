@@ -0,0 +1,254 @@
+//! Logging to logcat: a `tracing` layer writing through liblog, and on
+//! Android 11+ a liblog logger that copies the process's other log output
+//! (Java's `android.util.Log`, framework libraries) into `tracing`.
+
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::fmt::Write as _;
+use std::sync::OnceLock;
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use xdl_rs::Library;
+
+/// Longest text written as one entry. logd caps an entry at about 4 KiB
+/// including the tag; longer messages are split.
+const MAX_CHUNK: usize = 4000;
+
+/// `tracing` target of messages copied from liblog by [`forward_to_tracing`].
+/// [`LogcatLayer`] skips them, since they are in logcat already.
+pub const FORWARDED_TARGET: &str = "android_log";
+
+/// `android_LogPriority`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(i32)]
+pub enum Priority {
+    Verbose = 2,
+    Debug = 3,
+    Info = 4,
+    Warn = 5,
+    Error = 6,
+    Fatal = 7,
+}
+
+impl From<&Level> for Priority {
+    fn from(level: &Level) -> Self {
+        match *level {
+            Level::TRACE => Self::Verbose,
+            Level::DEBUG => Self::Debug,
+            Level::INFO => Self::Info,
+            Level::WARN => Self::Warn,
+            Level::ERROR => Self::Error,
+        }
+    }
+}
+
+/// `log_id_t`: which logcat buffer an entry goes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum Buffer {
+    Main = 0,
+    Crash = 4,
+}
+
+#[link(name = "log")]
+unsafe extern "C" {
+    fn __android_log_write(prio: c_int, tag: *const c_char, text: *const c_char) -> c_int;
+    fn __android_log_buf_write(
+        buf_id: c_int,
+        prio: c_int,
+        tag: *const c_char,
+        text: *const c_char,
+    ) -> c_int;
+}
+
+/// Write `text` to logcat, split into several entries if it is too long.
+/// Doesn't allocate, for crash reports from signal handlers.
+pub fn write(buffer: Buffer, priority: Priority, tag: &CStr, text: &str) {
+    let mut entry = [0u8; MAX_CHUNK + 1];
+    for chunk in chunks(text, MAX_CHUNK) {
+        for (byte, &c) in entry.iter_mut().zip(chunk.as_bytes()) {
+            *byte = if c == 0 { b' ' } else { c };
+        }
+        entry[chunk.len()] = 0;
+        let chunk = entry.as_ptr() as *const c_char;
+        unsafe {
+            match buffer {
+                Buffer::Main => __android_log_write(priority as c_int, tag.as_ptr(), chunk),
+                Buffer::Crash => {
+                    __android_log_buf_write(buffer as c_int, priority as c_int, tag.as_ptr(), chunk)
+                }
+            };
+        }
+    }
+}
+
+/// Split `text` into pieces of at most `max` bytes, at line breaks where
+/// possible and never inside a character.
+fn chunks(text: &str, max: usize) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        if rest.len() <= max {
+            return Some(std::mem::take(&mut rest));
+        }
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, tail) = match rest[..end].rfind('\n') {
+            Some(newline) if newline > 0 => (&rest[..newline], &rest[newline + 1..]),
+            _ => rest.split_at(end),
+        };
+        rest = tail;
+        Some(chunk)
+    })
+}
+
+/// A `tracing` layer writing each event to the main logcat buffer, as
+/// `target: message key=value ...` under a fixed tag.
+pub struct LogcatLayer {
+    tag: CString,
+}
+
+impl LogcatLayer {
+    pub fn new(tag: &str) -> Self {
+        Self {
+            tag: CString::new(tag.replace('\0', "")).unwrap_or_default(),
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogcatLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if metadata.target() == FORWARDED_TARGET {
+            return;
+        }
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let text = format!(
+            "{}: {}{}",
+            metadata.target(),
+            visitor.message,
+            visitor.fields
+        );
+        write(Buffer::Main, metadata.level().into(), &self.tag, &text);
+    }
+}
+
+/// Collects an event's message and its other fields separately, like the
+/// `fmt` layer prints them.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// `struct __android_log_message`.
+#[repr(C)]
+struct LogMessage {
+    struct_size: usize,
+    buffer_id: i32,
+    priority: i32,
+    tag: *const c_char,
+    file: *const c_char,
+    line: u32,
+    message: *const c_char,
+}
+
+type Logger = unsafe extern "C" fn(*const LogMessage);
+type SetLogger = unsafe extern "C" fn(Logger);
+
+/// What [`forward`] needs: our own tag and liblog's default logger.
+struct Forwarding {
+    tag: CString,
+    logd: Logger,
+}
+
+static FORWARDING: OnceLock<Forwarding> = OnceLock::new();
+
+/// Copy everything else this process writes through liblog (notably Java's
+/// `android.util.Log`) into `tracing` under [`FORWARDED_TARGET`], so it
+/// shows up on stdout too. It still reaches logcat as well.
+///
+/// Needs `__android_log_set_logger` (Android 11+); returns whether it was
+/// installed.
+pub fn forward_to_tracing(tag: &str) -> bool {
+    let Ok(liblog) = Library::open("liblog.so", xdl_rs::XDL_DEFAULT) else {
+        return false;
+    };
+    let functions = unsafe {
+        liblog
+            .get::<SetLogger>("__android_log_set_logger")
+            .zip(liblog.get::<Logger>("__android_log_logd_logger"))
+    };
+    let Some((set_logger, logd)) = functions else {
+        return false;
+    };
+    let forwarding = Forwarding {
+        tag: CString::new(tag.replace('\0', "")).unwrap_or_default(),
+        logd,
+    };
+    if FORWARDING.set(forwarding).is_err() {
+        return true;
+    }
+    unsafe { set_logger(forward) };
+    true
+}
+
+unsafe extern "C" fn forward(message: *const LogMessage) {
+    let Some(forwarding) = FORWARDING.get() else {
+        return;
+    };
+    unsafe { (forwarding.logd)(message) };
+
+    let message = unsafe { &*message };
+    if message.tag.is_null() || message.message.is_null() {
+        return;
+    }
+    let tag = unsafe { CStr::from_ptr(message.tag) };
+    // Our own events, from `LogcatLayer`, went to stdout directly.
+    if tag == forwarding.tag.as_c_str() {
+        return;
+    }
+    let tag = tag.to_string_lossy();
+    let text = unsafe { CStr::from_ptr(message.message) }.to_string_lossy();
+    match level(message.priority) {
+        Level::TRACE => tracing::trace!(target: FORWARDED_TARGET, "{tag}: {text}"),
+        Level::DEBUG => tracing::debug!(target: FORWARDED_TARGET, "{tag}: {text}"),
+        Level::INFO => tracing::info!(target: FORWARDED_TARGET, "{tag}: {text}"),
+        Level::WARN => tracing::warn!(target: FORWARDED_TARGET, "{tag}: {text}"),
+        Level::ERROR => tracing::error!(target: FORWARDED_TARGET, "{tag}: {text}"),
+    }
+}
+
+fn level(priority: i32) -> Level {
+    match priority {
+        ..=2 => Level::TRACE,
+        3 => Level::DEBUG,
+        4 => Level::INFO,
+        5 => Level::WARN,
+        _ => Level::ERROR,
+    }
+}
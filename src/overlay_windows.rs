@@ -0,0 +1,143 @@
+use std::collections::{HashMap, HashSet};
+
+use jni::JNIEnv;
+use ndk::native_window::NativeWindow;
+use tracing::warn;
+
+use crate::bridge::{JavaBridge, OverlaySurface};
+use crate::jni_thread::{JniCommand, JniHandle};
+use crate::renderer::Renderer;
+
+/// Render-thread bookkeeping for the renderer's secondary egui viewports.
+///
+/// After each frame, [`OverlayWindows::sync`] asks the JNI thread for a
+/// positioned layer for every newly shown deferred viewport, to move the
+/// ones the UI moved or resized and to remove the ones that closed. Created
+/// windows come back through [`OverlayWindows::on_created`].
+#[derive(Default)]
+pub struct OverlayWindows {
+    /// Surfaces requested from the JNI thread but not delivered yet.
+    pending: HashSet<egui::ViewportId>,
+    /// Viewports whose surface could not be created; not retried every frame.
+    failed: HashSet<egui::ViewportId>,
+}
+
+impl OverlayWindows {
+    pub fn sync(&mut self, renderer: &mut Renderer, jni: &JniHandle) {
+        for id in renderer.take_closed_viewports() {
+            self.failed.remove(&id);
+            jni.send(JniCommand::DestroyOverlay(id));
+        }
+        for (id, rect) in renderer.take_moved_viewports() {
+            jni.send(JniCommand::MoveOverlay { id, rect });
+        }
+
+        for (id, builder) in renderer.missing_viewports() {
+            if self.failed.contains(&id) || !self.pending.insert(id) {
+                continue;
+            }
+            let rect = renderer.viewport_screen_rect(&builder);
+            jni.send(JniCommand::CreateOverlay {
+                id,
+                name: builder.title.unwrap_or_else(|| "egui viewport".to_owned()),
+                rect,
+            });
+        }
+    }
+
+    /// A surface requested by [`Self::sync`] is ready (`Some`) or could not
+    /// be created (`None`).
+    pub fn on_created(
+        &mut self,
+        renderer: &mut Renderer,
+        jni: &JniHandle,
+        id: egui::ViewportId,
+        window: Option<(NativeWindow, egui::Pos2)>,
+    ) {
+        self.pending.remove(&id);
+        let Some((window, origin)) = window else {
+            self.failed.insert(id);
+            return;
+        };
+        if let Err(e) = renderer.attach_viewport(id, &window, origin) {
+            warn!("Cannot render viewport {:?}: {:#}", id, e);
+            self.failed.insert(id);
+            jni.send(JniCommand::DestroyOverlay(id));
+        }
+    }
+}
+
+/// JNI-thread side: owns the `SurfaceControl` layers.
+#[derive(Default)]
+pub struct OverlaySurfaces {
+    surfaces: HashMap<egui::ViewportId, OverlaySurface>,
+}
+
+impl OverlaySurfaces {
+    pub fn create<'a>(
+        &mut self,
+        bridge: &JavaBridge<'a>,
+        env: &mut JNIEnv<'a>,
+        id: egui::ViewportId,
+        name: &str,
+        rect: egui::Rect,
+    ) -> Option<NativeWindow> {
+        let surface = bridge
+            .create_overlay_surface(
+                env,
+                name,
+                rect.min.x as i32,
+                rect.min.y as i32,
+                rect.width() as i32,
+                rect.height() as i32,
+            )
+            .map_err(|e| warn!("Cannot open viewport {:?} ({}): {:#}", id, name, e))
+            .ok()?;
+        let window = surface.window.clone();
+        self.surfaces.insert(id, surface);
+        Some(window)
+    }
+
+    pub fn move_to<'a>(
+        &self,
+        bridge: &JavaBridge<'a>,
+        env: &mut JNIEnv<'a>,
+        id: egui::ViewportId,
+        rect: egui::Rect,
+    ) {
+        let Some(surface) = self.surfaces.get(&id) else {
+            return;
+        };
+        if let Err(e) = bridge.move_overlay_surface(
+            env,
+            surface,
+            rect.min.x as i32,
+            rect.min.y as i32,
+            rect.width() as i32,
+            rect.height() as i32,
+        ) {
+            warn!("Failed to move surface of viewport {:?}: {:#}", id, e);
+        }
+    }
+
+    pub fn destroy<'a>(
+        &mut self,
+        bridge: &JavaBridge<'a>,
+        env: &mut JNIEnv<'a>,
+        id: egui::ViewportId,
+    ) {
+        if let Some(surface) = self.surfaces.remove(&id)
+            && let Err(e) = bridge.destroy_overlay_surface(env, surface)
+        {
+            warn!("Failed to remove surface of viewport {:?}: {:#}", id, e);
+        }
+    }
+
+    /// Remove every layer, at shutdown.
+    pub fn destroy_all<'a>(&mut self, bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>) {
+        let ids = self.surfaces.keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            self.destroy(bridge, env, id);
+        }
+    }
+}
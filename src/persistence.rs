@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tracing::{debug, info, warn};
+
+/// How often [`Storage::maybe_save`] writes to disk.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+const STATE_FILE: &str = "state.ron";
+const EGUI_MEMORY_KEY: &str = "egui";
+/// Key under which the application keeps its own state.
+pub const APP_KEY: &str = "app";
+
+/// Key-value store persisted as RON in `<dir>/state.ron`.
+///
+/// Each value is serialized on its own, so a value that fails to load (e.g.
+/// after its type changed) doesn't take the others down with it.
+pub struct Storage {
+    path: PathBuf,
+    values: HashMap<String, String>,
+    dirty: bool,
+    last_save: Instant,
+}
+
+impl Storage {
+    /// Open the store in `dir`, starting empty if there is no readable file.
+    pub fn open(dir: &Path) -> Self {
+        let path = dir.join(STATE_FILE);
+        let values = match fs::read_to_string(&path) {
+            Ok(data) => ron::from_str(&data).unwrap_or_else(|e| {
+                warn!("Ignoring corrupt state file {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) => {
+                debug!("No saved state at {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+        info!(
+            "Loaded {} saved values from {}",
+            values.len(),
+            path.display()
+        );
+
+        Self {
+            path,
+            values,
+            dirty: false,
+            last_save: Instant::now(),
+        }
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let data = self.values.get(key)?;
+        ron::from_str(data)
+            .map_err(|e| warn!("Discarding saved '{key}': {e}"))
+            .ok()
+    }
+
+    pub fn set<T: Serialize>(&mut self, key: &str, value: &T) {
+        match ron::to_string(value) {
+            Ok(data) => {
+                if self.values.get(key) != Some(&data) {
+                    self.values.insert(key.to_owned(), data);
+                    self.dirty = true;
+                }
+            }
+            Err(e) => warn!("Failed to serialize '{key}': {e}"),
+        }
+    }
+
+    /// Restore window positions, collapsed headers and other egui memory.
+    pub fn restore_memory(&self, ctx: &egui::Context) {
+        if let Some(memory) = self.get::<egui::Memory>(EGUI_MEMORY_KEY) {
+            ctx.memory_mut(|m| *m = memory);
+            debug!("Restored egui memory");
+        }
+    }
+
+    pub fn store_memory(&mut self, ctx: &egui::Context) {
+        let memory = ctx.memory(|m| m.clone());
+        self.set(EGUI_MEMORY_KEY, &memory);
+    }
+
+    /// Snapshot egui memory and `app_state`, and write them out if
+    /// [`SAVE_INTERVAL`] has passed since the last save.
+    pub fn maybe_save<T: Serialize>(&mut self, ctx: &egui::Context, app_state: &T) {
+        if self.last_save.elapsed() < SAVE_INTERVAL {
+            return;
+        }
+        self.save(ctx, app_state);
+    }
+
+    /// Snapshot and write immediately, e.g. on shutdown.
+    pub fn save<T: Serialize>(&mut self, ctx: &egui::Context, app_state: &T) {
+        self.last_save = Instant::now();
+        self.store_memory(ctx);
+        self.set(APP_KEY, app_state);
+        if let Err(e) = self.flush() {
+            warn!("Failed to save state: {e:#}");
+        }
+    }
+
+    /// Write pending changes. The file is replaced atomically so a crash
+    /// mid-write can't leave a truncated state behind.
+    pub fn flush(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+        let data = ron::ser::to_string_pretty(&self.values, ron::ser::PrettyConfig::default())?;
+        let tmp = self.path.with_extension("ron.tmp");
+        fs::write(&tmp, data).with_context(|| format!("writing {}", tmp.display()))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("replacing {}", self.path.display()))?;
+        self.dirty = false;
+        debug!("Saved state to {}", self.path.display());
+        Ok(())
+    }
+}
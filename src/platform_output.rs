@@ -0,0 +1,117 @@
+use std::process::Command;
+use std::thread;
+
+use jni::JNIEnv;
+use tracing::{debug, info, warn};
+
+use crate::bridge::JavaBridge;
+
+/// Where egui wants text input to happen, in physical screen pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImeRequest {
+    /// The whole text edit area.
+    pub rect: egui::Rect,
+    /// The text cursor inside it; candidate windows go next to this.
+    pub cursor_rect: egui::Rect,
+}
+
+type ImeCallback = Box<dyn FnMut(Option<ImeRequest>)>;
+type EventCallback = Box<dyn FnMut(&egui::output::OutputEvent)>;
+
+/// Handles the render-thread parts of `PlatformOutput`: IME placement and
+/// widget events. URLs (see [`urls`]) and copied text need JNI and are
+/// forwarded to the JNI thread instead.
+pub struct PlatformOutputHandler {
+    last_ime: Option<ImeRequest>,
+    on_ime: Option<ImeCallback>,
+    on_event: Option<EventCallback>,
+}
+
+impl PlatformOutputHandler {
+    pub fn new() -> Self {
+        Self {
+            last_ime: None,
+            on_ime: None,
+            on_event: None,
+        }
+    }
+
+    /// Called whenever the text-entry area changes: `Some` while a text field
+    /// has focus, `None` once it loses it. Only invoked on change.
+    pub fn set_on_ime(&mut self, on_ime: impl FnMut(Option<ImeRequest>) + 'static) {
+        self.on_ime = Some(Box::new(on_ime));
+    }
+
+    /// Called for each widget event (clicks, focus changes, value changes),
+    /// e.g. to feed an accessibility service or for app-level logging.
+    pub fn set_on_event(&mut self, on_event: impl FnMut(&egui::output::OutputEvent) + 'static) {
+        self.on_event = Some(Box::new(on_event));
+    }
+
+    pub fn handle(&mut self, output: &egui::PlatformOutput, pixels_per_point: f32) {
+        let ime = output.ime.map(|ime| ImeRequest {
+            rect: ime.rect * pixels_per_point,
+            cursor_rect: ime.cursor_rect * pixels_per_point,
+        });
+        if ime != self.last_ime {
+            self.last_ime = ime;
+            if let Some(on_ime) = &mut self.on_ime {
+                on_ime(ime);
+            }
+        }
+
+        if let Some(on_event) = &mut self.on_event {
+            for event in &output.events {
+                on_event(event);
+            }
+        }
+    }
+}
+
+/// URLs egui wants opened (`OutputCommand::OpenUrl`).
+pub fn urls(output: &egui::PlatformOutput) -> impl Iterator<Item = &str> {
+    output.commands.iter().filter_map(|command| match command {
+        egui::OutputCommand::OpenUrl(open_url) => Some(open_url.url.as_str()),
+        _ => None,
+    })
+}
+
+/// Open a URL through the bridge, falling back to `am start` if the intent
+/// can't be started from the system context. `am` is not waited for on the
+/// calling (JNI) thread.
+pub fn open_url<'a>(bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>, url: &str) {
+    match bridge.open_url(env, url) {
+        Ok(()) => {
+            info!("Opened {url}");
+            return;
+        }
+        Err(e) => debug!("Intent for {url} failed ({e:#}), trying am start"),
+    }
+
+    let mut child = match Command::new("am")
+        .args(["start", "-a", "android.intent.action.VIEW", "-d", url])
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to open {url}: {e}");
+            return;
+        }
+    };
+    // `am` starts a VM of its own and takes a while; reap it in the
+    // background.
+    let url = url.to_owned();
+    let reaper = thread::Builder::new()
+        .name("am-start".into())
+        .spawn(move || {
+            let _alt_stack = crate::crash::AltStack::install();
+            match child.wait() {
+                Ok(status) if status.success() => info!("Opened {url} via am"),
+                Ok(status) => warn!("am start {url} exited with {status}"),
+                Err(e) => warn!("Failed to wait for am start {url}: {e}"),
+            }
+        });
+    if let Err(e) = reaper {
+        warn!("Cannot wait for am start: {e}");
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use egui::epaint::{ClippedPrimitive, Primitive};
+use khronos_egl as egl;
+use tracing::info;
+
+use super::egl_config::{Egl, has_extension};
+
+const EXT_BUFFER_AGE: &str = "EGL_EXT_buffer_age";
+/// `EGL_BUFFER_AGE_EXT`.
+const BUFFER_AGE: egl::Int = 0x313D;
+
+/// Extension and entry point of each flavour of swap-with-damage.
+const SWAP_WITH_DAMAGE: [(&str, &str); 2] = [
+    (
+        "EGL_KHR_swap_buffers_with_damage",
+        "eglSwapBuffersWithDamageKHR",
+    ),
+    (
+        "EGL_EXT_swap_buffers_with_damage",
+        "eglSwapBuffersWithDamageEXT",
+    ),
+];
+
+/// How many frames of damage are remembered. Back buffers older than this
+/// are repainted in full.
+const MAX_BUFFER_AGE: usize = 4;
+
+/// Above this many rects, damage is reported as their bounding box.
+const MAX_DAMAGE_RECTS: usize = 8;
+
+type SwapBuffersWithDamage = unsafe extern "system" fn(
+    egl::EGLDisplay,
+    egl::EGLSurface,
+    *const egl::Int,
+    egl::Int,
+) -> egl::Boolean;
+
+/// Where a primitive was painted, in physical pixels, and a fingerprint of
+/// what it drew.
+#[derive(Debug, Clone, Copy)]
+struct Footprint {
+    rect: egui::Rect,
+    hash: u64,
+}
+
+/// Tracks which parts of the root surface changed between frames, so only
+/// those are repainted (`EGL_EXT_buffer_age`) and reported to the
+/// compositor (`eglSwapBuffersWithDamageKHR`).
+///
+/// Without either extension every frame is a full repaint and a plain swap.
+pub(super) struct DamageTracker {
+    buffer_age: bool,
+    swap_with_damage: Option<SwapBuffersWithDamage>,
+    previous: Vec<Footprint>,
+    /// Damage of the last frames, newest first.
+    history: VecDeque<Vec<egui::Rect>>,
+    /// Damage of the frame being rendered.
+    current: Vec<egui::Rect>,
+}
+
+impl DamageTracker {
+    pub fn new(egl: &Egl, display: egl::Display) -> Self {
+        let buffer_age = has_extension(egl, display, EXT_BUFFER_AGE);
+        let swap_with_damage = SWAP_WITH_DAMAGE
+            .iter()
+            .filter(|(extension, _)| has_extension(egl, display, extension))
+            .find_map(|(_, function)| egl.get_proc_address(function))
+            .map(|f| unsafe {
+                std::mem::transmute::<extern "system" fn(), SwapBuffersWithDamage>(f)
+            });
+        info!(
+            "Damage tracking: buffer age {}, swap with damage {}",
+            buffer_age,
+            swap_with_damage.is_some()
+        );
+
+        Self {
+            buffer_age,
+            swap_with_damage,
+            previous: Vec::new(),
+            history: VecDeque::new(),
+            current: Vec::new(),
+        }
+    }
+
+    /// Forget everything, so the next frame is a full repaint. Needed
+    /// whenever the surface or its size changes.
+    pub fn reset(&mut self) {
+        self.previous.clear();
+        self.history.clear();
+        self.current.clear();
+    }
+
+    /// Compare `primitives` with the previous frame and record what changed.
+    /// Primitives using a texture in `textures` count as changed.
+    pub fn update(
+        &mut self,
+        primitives: &[ClippedPrimitive],
+        textures: &egui::TexturesDelta,
+        pixels_per_point: f32,
+        screen: egui::Rect,
+    ) {
+        let mut damage = Vec::new();
+        let mut footprints = Vec::with_capacity(primitives.len());
+
+        for primitive in primitives {
+            let clip = primitive.clip_rect * pixels_per_point;
+            let mut hasher = DefaultHasher::new();
+            for edge in [clip.min.x, clip.min.y, clip.max.x, clip.max.y] {
+                edge.to_bits().hash(&mut hasher);
+            }
+            let rect = match &primitive.primitive {
+                Primitive::Mesh(mesh) => {
+                    let bounds = mesh.calc_bounds() * pixels_per_point;
+                    if textures.set.iter().any(|(id, _)| *id == mesh.texture_id) {
+                        damage.push(bounds.intersect(clip));
+                    }
+                    mesh.texture_id.hash(&mut hasher);
+                    mesh.indices.hash(&mut hasher);
+                    for vertex in &mesh.vertices {
+                        vertex.pos.x.to_bits().hash(&mut hasher);
+                        vertex.pos.y.to_bits().hash(&mut hasher);
+                        vertex.uv.x.to_bits().hash(&mut hasher);
+                        vertex.uv.y.to_bits().hash(&mut hasher);
+                        vertex.color.hash(&mut hasher);
+                    }
+                    bounds.intersect(clip)
+                }
+                // Can't see what a callback draws; assume all of it changed.
+                Primitive::Callback(_) => {
+                    damage.push(clip);
+                    clip
+                }
+            };
+            footprints.push(Footprint {
+                rect,
+                hash: hasher.finish(),
+            });
+        }
+
+        // Anything that appeared, disappeared or changed. Unchanged
+        // primitives hash the same in both frames.
+        let old = count_hashes(&self.previous);
+        let new = count_hashes(&footprints);
+        let added = footprints
+            .iter()
+            .filter(|f| new[&f.hash] > old.get(&f.hash).copied().unwrap_or(0));
+        let removed = self
+            .previous
+            .iter()
+            .filter(|f| old[&f.hash] > new.get(&f.hash).copied().unwrap_or(0));
+        damage.extend(added.chain(removed).map(|f| f.rect));
+        self.previous = footprints;
+
+        self.current = merge(damage, screen);
+    }
+
+    /// The part of the back buffer that must be repainted this frame, in
+    /// physical pixels, or `None` if it already shows the current frame.
+    pub fn repair_region(
+        &self,
+        egl: &Egl,
+        display: egl::Display,
+        surface: egl::Surface,
+        screen: egui::Rect,
+    ) -> Option<egui::Rect> {
+        let age = if self.buffer_age {
+            egl.query_surface(display, surface, BUFFER_AGE).unwrap_or(0) as usize
+        } else {
+            0
+        };
+        // 0 means the contents are undefined (e.g. a fresh buffer).
+        if age == 0 || age - 1 > self.history.len() {
+            return Some(screen);
+        }
+
+        self.current
+            .iter()
+            .chain(self.history.iter().take(age - 1).flatten())
+            .copied()
+            .reduce(|a, b| a.union(b))
+    }
+
+    /// Present the frame, telling the compositor what changed if possible.
+    pub fn swap_buffers(
+        &mut self,
+        egl: &Egl,
+        display: egl::Display,
+        surface: egl::Surface,
+        height: i32,
+    ) -> Result<(), egl::Error> {
+        let current = std::mem::take(&mut self.current);
+        let result = match self.swap_with_damage {
+            // An empty list would mean "everything", so fall back instead.
+            Some(swap) if !current.is_empty() => {
+                // EGL rects are x, y, width, height with a bottom-left origin.
+                let rects = current
+                    .iter()
+                    .flat_map(|r| {
+                        [
+                            r.min.x as egl::Int,
+                            height - r.max.y as egl::Int,
+                            r.width() as egl::Int,
+                            r.height() as egl::Int,
+                        ]
+                    })
+                    .collect::<Vec<_>>();
+                let ok = unsafe {
+                    swap(
+                        display.as_ptr(),
+                        surface.as_ptr(),
+                        rects.as_ptr(),
+                        current.len() as egl::Int,
+                    )
+                };
+                if ok == egl::TRUE {
+                    Ok(())
+                } else {
+                    Err(egl.get_error().unwrap_or(egl::Error::BadSurface))
+                }
+            }
+            _ => egl.swap_buffers(display, surface),
+        };
+
+        self.history.push_front(current);
+        self.history.truncate(MAX_BUFFER_AGE);
+        result
+    }
+}
+
+/// Restrict painting to `region` (in points), dropping primitives outside it.
+pub fn clip_primitives(
+    primitives: Vec<ClippedPrimitive>,
+    region: egui::Rect,
+) -> Vec<ClippedPrimitive> {
+    primitives
+        .into_iter()
+        .filter_map(|mut primitive| {
+            primitive.clip_rect = primitive.clip_rect.intersect(region);
+            primitive.clip_rect.is_positive().then_some(primitive)
+        })
+        .collect()
+}
+
+fn count_hashes(footprints: &[Footprint]) -> HashMap<u64, usize> {
+    let mut counts = HashMap::new();
+    for footprint in footprints {
+        *counts.entry(footprint.hash).or_default() += 1;
+    }
+    counts
+}
+
+/// Clamp to the screen, snap to whole pixels and drop empty rects. Too many
+/// rects are collapsed into their bounding box.
+fn merge(rects: Vec<egui::Rect>, screen: egui::Rect) -> Vec<egui::Rect> {
+    let mut rects = rects
+        .into_iter()
+        .map(|r| {
+            let r = r.intersect(screen);
+            egui::Rect::from_min_max(r.min.floor(), r.max.ceil())
+        })
+        .filter(|r| r.is_positive())
+        .collect::<Vec<_>>();
+    rects.dedup();
+    if rects.len() > MAX_DAMAGE_RECTS {
+        let bounds = rects.iter().copied().reduce(|a, b| a.union(b));
+        rects = bounds.into_iter().collect();
+    }
+    rects
+}
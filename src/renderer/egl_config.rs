@@ -0,0 +1,313 @@
+use anyhow::Result;
+use khronos_egl as egl;
+use tracing::{info, warn};
+
+pub type Egl = egl::DynamicInstance<egl::EGL1_4>;
+
+const EXT_GL_COLORSPACE: &str = "EGL_KHR_gl_colorspace";
+
+/// OpenGL ES major version a config / context was created for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlesVersion {
+    Gles2,
+    Gles3,
+}
+
+impl GlesVersion {
+    pub fn client_version(self) -> egl::Int {
+        match self {
+            GlesVersion::Gles2 => 2,
+            GlesVersion::Gles3 => 3,
+        }
+    }
+}
+
+/// What the caller would like from the framebuffer.
+///
+/// These are preferences, not requirements: if nothing matches we still pick
+/// the closest RGBA8888 window config rather than failing.
+#[derive(Debug, Clone)]
+pub struct EglConfigPreferences {
+    /// Number of MSAA samples to ask for. `0` disables multisampling.
+    pub msaa_samples: u32,
+    /// Create the window surface with `EGL_GL_COLORSPACE_SRGB_KHR` when the
+    /// display advertises `EGL_KHR_gl_colorspace`.
+    ///
+    /// egui already outputs gamma-encoded colors, so this is off by default.
+    pub prefer_srgb: bool,
+    /// Allow falling back to an ES2 context when ES3 is unavailable.
+    pub allow_gles2: bool,
+}
+
+impl Default for EglConfigPreferences {
+    fn default() -> Self {
+        Self {
+            msaa_samples: 0,
+            prefer_srgb: false,
+            allow_gles2: true,
+        }
+    }
+}
+
+/// Attributes of one config returned by `eglChooseConfig`.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigCandidate {
+    pub config: egl::Config,
+    pub id: egl::Int,
+    pub red: egl::Int,
+    pub green: egl::Int,
+    pub blue: egl::Int,
+    pub alpha: egl::Int,
+    pub depth: egl::Int,
+    pub stencil: egl::Int,
+    pub samples: egl::Int,
+    pub renderable_type: egl::Int,
+    pub caveat: egl::Int,
+    pub native_visual_id: egl::Int,
+}
+
+impl ConfigCandidate {
+    fn query(egl: &Egl, display: egl::Display, config: egl::Config) -> Result<Self> {
+        let attr = |name| egl.get_config_attrib(display, config, name);
+        Ok(Self {
+            config,
+            id: attr(egl::CONFIG_ID)?,
+            red: attr(egl::RED_SIZE)?,
+            green: attr(egl::GREEN_SIZE)?,
+            blue: attr(egl::BLUE_SIZE)?,
+            alpha: attr(egl::ALPHA_SIZE)?,
+            depth: attr(egl::DEPTH_SIZE)?,
+            stencil: attr(egl::STENCIL_SIZE)?,
+            samples: attr(egl::SAMPLES)?,
+            renderable_type: attr(egl::RENDERABLE_TYPE)?,
+            caveat: attr(egl::CONFIG_CAVEAT)?,
+            native_visual_id: attr(egl::NATIVE_VISUAL_ID)?,
+        })
+    }
+
+    pub fn supports_gles3(&self) -> bool {
+        self.renderable_type & egl::OPENGL_ES3_BIT != 0
+    }
+
+    fn is_rgba8888(&self) -> bool {
+        self.red == 8 && self.green == 8 && self.blue == 8 && self.alpha == 8
+    }
+
+    /// Higher is better. Candidates that are not exactly RGBA8888 are never
+    /// preferred over one that is, since the overlay relies on a real alpha
+    /// channel and the native window format is derived from the config.
+    fn score(&self, prefs: &EglConfigPreferences) -> i64 {
+        let mut score = 0i64;
+
+        if self.is_rgba8888() {
+            score += 1_000_000;
+        }
+        if self.supports_gles3() {
+            score += 100_000;
+        }
+        if self.caveat == egl::NONE {
+            score += 10_000;
+        } else if self.caveat == egl::SLOW_CONFIG {
+            score -= 10_000;
+        }
+
+        // Exact sample count is best, fewer than requested is acceptable,
+        // more than requested only wastes bandwidth.
+        let wanted = prefs.msaa_samples as i64;
+        let samples = self.samples as i64;
+        score -= if samples <= wanted {
+            (wanted - samples) * 100
+        } else {
+            (samples - wanted) * 200
+        };
+
+        // egui needs neither depth nor stencil.
+        score -= (self.depth + self.stencil) as i64;
+
+        score
+    }
+
+    fn caveat_str(&self) -> &'static str {
+        match self.caveat {
+            egl::NONE => "none",
+            egl::SLOW_CONFIG => "slow",
+            egl::NON_CONFORMANT_CONFIG => "non-conformant",
+            _ => "?",
+        }
+    }
+}
+
+/// The config chosen by [`choose_config`] plus what the display supports.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectedConfig {
+    pub candidate: ConfigCandidate,
+    pub srgb: bool,
+}
+
+impl SelectedConfig {
+    /// Attribute list for `eglCreateWindowSurface`.
+    pub fn surface_attribs(&self) -> Vec<egl::Int> {
+        if self.srgb {
+            vec![egl::GL_COLORSPACE, egl::GL_COLORSPACE_SRGB, egl::NONE]
+        } else {
+            vec![egl::NONE]
+        }
+    }
+}
+
+pub fn has_extension(egl: &Egl, display: egl::Display, name: &str) -> bool {
+    egl.query_string(Some(display), egl::EXTENSIONS)
+        .map(|exts| exts.to_string_lossy().split_whitespace().any(|e| e == name))
+        .unwrap_or(false)
+}
+
+/// Enumerate every window-capable ES2/ES3 config, rank them against `prefs`
+/// and return the best one.
+pub fn choose_config(
+    egl: &Egl,
+    display: egl::Display,
+    prefs: &EglConfigPreferences,
+) -> Result<SelectedConfig> {
+    let renderable = if prefs.allow_gles2 {
+        egl::OPENGL_ES2_BIT
+    } else {
+        egl::OPENGL_ES3_BIT
+    };
+
+    #[rustfmt::skip]
+    let attribs = [
+        egl::RED_SIZE, 8,
+        egl::GREEN_SIZE, 8,
+        egl::BLUE_SIZE, 8,
+        egl::ALPHA_SIZE, 8,
+        egl::RENDERABLE_TYPE, renderable,
+        egl::SURFACE_TYPE, egl::WINDOW_BIT,
+        egl::NONE,
+    ];
+
+    let count = egl.matching_config_count(display, &attribs)?;
+    let mut configs = Vec::with_capacity(count);
+    egl.choose_config(display, &attribs, &mut configs)
+        .map_err(|_| anyhow::anyhow!("eglChooseConfig failed"))?;
+
+    let mut candidates = configs
+        .into_iter()
+        .filter_map(|config| {
+            ConfigCandidate::query(egl, display, config)
+                .map_err(|e| warn!("Skipping EGL config: {}", e))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+
+    if candidates.is_empty() {
+        anyhow::bail!("No matching EGL config found");
+    }
+
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.score(prefs)));
+
+    info!("EGL configs ({} candidates, best first):", candidates.len());
+    info!("   id  r g b a  depth stencil samples  es3  caveat          score");
+    for c in &candidates {
+        info!(
+            "{:>5}  {} {} {} {}  {:>5} {:>7} {:>7}  {:<4} {:<14} {:>7}",
+            c.id,
+            c.red,
+            c.green,
+            c.blue,
+            c.alpha,
+            c.depth,
+            c.stencil,
+            c.samples,
+            c.supports_gles3(),
+            c.caveat_str(),
+            c.score(prefs),
+        );
+    }
+
+    let candidate = candidates[0];
+    let srgb = prefs.prefer_srgb && has_extension(egl, display, EXT_GL_COLORSPACE);
+    if prefs.prefer_srgb && !srgb {
+        warn!(
+            "{} not supported, using a linear surface",
+            EXT_GL_COLORSPACE
+        );
+    }
+
+    info!(
+        "Chosen EGL config {} (samples={}, es3={}, srgb={})",
+        candidate.id,
+        candidate.samples,
+        candidate.supports_gles3(),
+        srgb
+    );
+
+    Ok(SelectedConfig { candidate, srgb })
+}
+
+/// Create a context for `selected`, trying ES3 first and falling back to ES2.
+pub fn create_context(
+    egl: &Egl,
+    display: egl::Display,
+    selected: &SelectedConfig,
+    prefs: &EglConfigPreferences,
+) -> Result<(egl::Context, GlesVersion)> {
+    let mut versions = Vec::with_capacity(2);
+    if selected.candidate.supports_gles3() {
+        versions.push(GlesVersion::Gles3);
+    }
+    if prefs.allow_gles2 {
+        versions.push(GlesVersion::Gles2);
+    }
+
+    let mut last_err = None;
+    for version in versions {
+        let context_attribs = [
+            egl::CONTEXT_CLIENT_VERSION,
+            version.client_version(),
+            egl::NONE,
+        ];
+        match egl.create_context(display, selected.candidate.config, None, &context_attribs) {
+            Ok(context) => {
+                info!("Created {:?} context", version);
+                return Ok((context, version));
+            }
+            Err(e) => {
+                warn!("Failed to create {:?} context: {}", version, e);
+                last_err = Some(e);
+            }
+        }
+    }
+
+    Err(match last_err {
+        Some(e) => anyhow::anyhow!("eglCreateContext failed: {}", e),
+        None => anyhow::anyhow!(
+            "Config {} supports no usable GLES version",
+            selected.candidate.id
+        ),
+    })
+}
+
+/// Create the window surface, dropping the sRGB colorspace request if the
+/// driver rejects it.
+///
+/// # Safety
+///
+/// `window` must be a valid `ANativeWindow*` that outlives the surface.
+pub unsafe fn create_window_surface(
+    egl: &Egl,
+    display: egl::Display,
+    selected: &mut SelectedConfig,
+    window: egl::NativeWindowType,
+) -> Result<egl::Surface> {
+    let config = selected.candidate.config;
+    let attribs = selected.surface_attribs();
+    match unsafe { egl.create_window_surface(display, config, window, Some(&attribs)) } {
+        Ok(surface) => Ok(surface),
+        Err(e) if selected.srgb => {
+            warn!("sRGB window surface rejected ({}), retrying without", e);
+            selected.srgb = false;
+            Ok(unsafe { egl.create_window_surface(display, config, window, None)? })
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,500 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::{ffi::c_void, time};
+
+use anyhow::Result;
+use glow::HasContext;
+use khronos_egl as egl;
+use ndk::native_window::NativeWindow;
+use tracing::{info, warn};
+
+mod damage;
+mod egl_config;
+mod overlay;
+mod pacing;
+mod texture_mirror;
+mod viewports;
+
+use damage::DamageTracker;
+use egl_config::{Egl, SelectedConfig};
+pub use egl_config::{EglConfigPreferences, GlesVersion};
+pub use overlay::InputRegion;
+use pacing::FramePacer;
+use texture_mirror::TextureMirror;
+use viewports::{ViewportRequest, ViewportSurface};
+
+pub struct Renderer {
+    egl: Arc<Egl>,
+    egl_display: egl::Display,
+    egl_surface: egl::Surface,
+    egl_context: egl::Context,
+    egl_config: SelectedConfig,
+    egl_prefs: EglConfigPreferences,
+    window: NativeWindow,
+    pub gles_version: GlesVersion,
+    pub egui_context: egui::Context,
+    pub egui_painter: egui_glow::Painter,
+    pub egui_raw_input: egui::RawInput,
+    pub width: i32,
+    pub height: i32,
+    native_pixels_per_point: f32,
+    texture_mirror: TextureMirror,
+    opacity: f32,
+    window_alpha: HashMap<egui::Id, f32>,
+    input_region: InputRegion,
+    viewports: HashMap<egui::ViewportId, ViewportSurface>,
+    viewport_requests: HashMap<egui::ViewportId, ViewportRequest>,
+    /// Closed by `ViewportCommand::Close`; not reopened until the UI stops
+    /// showing them.
+    closed_viewports: HashSet<egui::ViewportId>,
+    /// Viewports whose EGL surface is destroyed and couldn't be recreated.
+    lost_viewports: HashSet<egui::ViewportId>,
+    /// Screen rects (physical pixels) the layers of moved or resized
+    /// viewports should get.
+    viewport_moves: HashMap<egui::ViewportId, egui::Rect>,
+    /// Which viewport each active touch (by id) started on.
+    touch_owners: HashMap<u64, egui::ViewportId>,
+    damage: DamageTracker,
+    /// Whether the last `render()` painted anything into the root surface.
+    repainted: bool,
+    /// The root EGL surface is gone and `window` can't take a new one;
+    /// nothing is drawn until [`Self::replace_window`].
+    surface_lost: bool,
+    pacer: FramePacer,
+    start_time: time::Instant,
+}
+
+impl Renderer {
+    pub fn with_preferences(window: &NativeWindow, prefs: &EglConfigPreferences) -> Result<Self> {
+        let width = window.width();
+        let height = window.height();
+        info!("Creating Renderer with size: {}x{}", width, height);
+
+        let egl = unsafe {
+            egl::DynamicInstance::<egl::EGL1_4>::load_required_from_filename("libEGL.so")
+        }
+        .map_err(|e| anyhow::anyhow!("Unable to load libEGL.so: {}", e))?;
+        let egl = Arc::new(egl);
+
+        info!("EGL Version: {:?}", egl.version());
+
+        let egl_display = unsafe {
+            egl.get_display(egl::DEFAULT_DISPLAY)
+                .ok_or(anyhow::anyhow!("Failed to get display"))?
+        };
+
+        let (major, minor) = egl.initialize(egl_display)?;
+        info!("EGL Initialized: {}.{}", major, minor);
+
+        let mut selected = egl_config::choose_config(&egl, egl_display, prefs)?;
+        if selected.candidate.alpha == 0 {
+            warn!("EGL config has no alpha channel, overlay opacity will have no effect");
+        }
+
+        let format = selected.candidate.native_visual_id;
+        window.set_buffers_geometry(0, 0, Some(format.into()))?;
+
+        let (egl_context, gles_version) =
+            egl_config::create_context(&egl, egl_display, &selected, prefs)?;
+
+        let egl_surface = unsafe {
+            egl_config::create_window_surface(
+                &egl,
+                egl_display,
+                &mut selected,
+                window.ptr().as_ptr() as *mut _,
+            )?
+        };
+
+        egl.make_current(
+            egl_display,
+            Some(egl_surface),
+            Some(egl_surface),
+            Some(egl_context),
+        )?;
+
+        let egui_painter = create_painter(&egl)?;
+        info!("OpenGL Initialized ({:?})", gles_version);
+
+        let damage = DamageTracker::new(&egl, egl_display);
+
+        let egui_context = egui::Context::default();
+        // Deferred viewports get their own native surfaces; immediate ones
+        // are still embedded since there is no immediate viewport renderer.
+        egui_context.set_embed_viewports(false);
+
+        let egui_raw_input = egui::RawInput {
+            screen_rect: Some(screen_rect(width, height, 1.0)),
+            max_texture_side: Some(egui_painter.max_texture_side()),
+            ..Default::default()
+        };
+
+        info!("EGUI Context Initialized");
+
+        Ok(Self {
+            egl,
+            egl_display,
+            egl_surface,
+            egl_context,
+            egl_config: selected,
+            egl_prefs: prefs.clone(),
+            window: window.clone(),
+            gles_version,
+            egui_raw_input,
+            egui_context,
+            egui_painter,
+            width,
+            height,
+            native_pixels_per_point: 1.0,
+            texture_mirror: TextureMirror::default(),
+            opacity: 1.0,
+            window_alpha: HashMap::new(),
+            input_region: InputRegion::default(),
+            viewports: HashMap::new(),
+            viewport_requests: HashMap::new(),
+            closed_viewports: HashSet::new(),
+            lost_viewports: HashSet::new(),
+            viewport_moves: HashMap::new(),
+            touch_owners: HashMap::new(),
+            damage,
+            repainted: false,
+            surface_lost: false,
+            pacer: FramePacer::new(),
+            start_time: time::Instant::now(),
+        })
+    }
+
+    /// Push a batch of egui events into the next frame's raw input.
+    /// Call this before `render()` with events received from the input thread.
+    ///
+    /// Positions are expected in physical screen pixels (as produced by the
+    /// input thread); each event goes to the viewport under it and is
+    /// converted to that viewport's points.
+    pub fn push_events(&mut self, events: Vec<egui::Event>) {
+        self.route_events(events);
+    }
+
+    /// Set the display density (`DisplayMetrics.density`), i.e. how many
+    /// physical pixels make up one egui point at a UI scale of 1.0.
+    pub fn set_native_pixels_per_point(&mut self, pixels_per_point: f32) {
+        info!("Native pixels per point: {}", pixels_per_point);
+        self.native_pixels_per_point = pixels_per_point;
+    }
+
+    /// User-controlled multiplier on top of the display density.
+    /// Takes effect on the next frame.
+    pub fn set_ui_scale(&self, scale: f32) {
+        self.egui_context.set_zoom_factor(scale);
+    }
+
+    pub fn pixels_per_point(&self) -> f32 {
+        self.native_pixels_per_point * self.egui_context.zoom_factor()
+    }
+
+    /// Opacity of the whole overlay, `0.0` (invisible) to `1.0` (default).
+    /// Animate it for fade-in/out.
+    pub fn set_opacity(&mut self, opacity: f32) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        if opacity != self.opacity {
+            self.viewports
+                .values_mut()
+                .for_each(ViewportSurface::request_repaint);
+        }
+        self.opacity = opacity;
+    }
+
+    /// Extra alpha for one window (or any other area), keyed by its id, e.g.
+    /// `egui::Id::new("MainWindow")`. Combined with the global opacity.
+    pub fn set_window_alpha(&mut self, id: egui::Id, alpha: f32) {
+        let alpha = alpha.clamp(0.0, 1.0);
+        if alpha >= 1.0 {
+            self.window_alpha.remove(&id);
+        } else {
+            self.window_alpha.insert(id, alpha);
+        }
+    }
+
+    /// Refresh rate of the display in Hz. Frames are paced to it.
+    pub fn set_refresh_rate(&mut self, hz: f32) {
+        self.pacer.set_refresh_rate(hz);
+    }
+
+    /// Where egui had visible windows (and secondary viewports) in the last
+    /// rendered frame. Touches outside it should be passed through to the
+    /// apps underneath.
+    pub fn input_region(&self) -> &InputRegion {
+        &self.input_region
+    }
+
+    /// Update the drawable size. `render()` also calls this on its own when
+    /// the native window reports a different size.
+    pub fn resize(&mut self, width: i32, height: i32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+        info!(
+            "Resizing renderer: {}x{} -> {}x{}",
+            self.width, self.height, width, height
+        );
+        self.width = width;
+        self.height = height;
+        self.damage.reset();
+    }
+
+    /// Whether the root surface is lost and a new window has to be handed
+    /// over with [`Self::replace_window`] before anything is drawn again.
+    pub fn needs_window(&self) -> bool {
+        self.surface_lost
+    }
+
+    /// Give up the root EGL surface because its window is going away, e.g.
+    /// the Java `Surface` was released. Nothing is drawn until
+    /// [`Self::replace_window`].
+    pub fn release_surface(&mut self) {
+        if self.surface_lost {
+            return;
+        }
+        let _ = self.egl.make_current(self.egl_display, None, None, None);
+        if let Err(e) = self.egl.destroy_surface(self.egl_display, self.egl_surface) {
+            warn!("eglDestroySurface failed: {}", e);
+        }
+        self.surface_lost = true;
+    }
+
+    /// Render into a different native window, e.g. after the Java side
+    /// destroyed and recreated the Surface. The GL context and all egui
+    /// textures are kept.
+    pub fn replace_window(&mut self, window: &NativeWindow) -> Result<()> {
+        info!("Replacing native window");
+        self.window = window.clone();
+        self.recreate_surface()
+    }
+
+    /// Create the root EGL surface again on `window`. On failure the
+    /// surface stays lost (see [`Self::needs_window`]).
+    fn recreate_surface(&mut self) -> Result<()> {
+        self.release_surface();
+
+        let format = self.egl_config.candidate.native_visual_id;
+        self.window
+            .set_buffers_geometry(0, 0, Some(format.into()))?;
+
+        self.egl_surface = unsafe {
+            egl_config::create_window_surface(
+                &self.egl,
+                self.egl_display,
+                &mut self.egl_config,
+                self.window.ptr().as_ptr() as *mut _,
+            )?
+        };
+
+        self.surface_lost = false;
+        self.egl.make_current(
+            self.egl_display,
+            Some(self.egl_surface),
+            Some(self.egl_surface),
+            Some(self.egl_context),
+        )?;
+
+        self.resize(self.window.width(), self.window.height());
+        self.damage.reset();
+        Ok(())
+    }
+
+    /// Throw away the lost context, create a new one on the same config and
+    /// re-upload every egui texture from the CPU-side mirror.
+    fn recover_context(&mut self) -> Result<()> {
+        warn!("EGL context lost, recreating");
+
+        self.egui_painter.destroy();
+        let _ = self.egl.make_current(self.egl_display, None, None, None);
+        let _ = self.egl.destroy_context(self.egl_display, self.egl_context);
+
+        let (egl_context, gles_version) = egl_config::create_context(
+            &self.egl,
+            self.egl_display,
+            &self.egl_config,
+            &self.egl_prefs,
+        )?;
+        self.egl_context = egl_context;
+        self.gles_version = gles_version;
+
+        // The old surface may or may not have survived; a fresh one is cheap.
+        self.recreate_surface()?;
+
+        self.egui_painter = create_painter(&self.egl)?;
+        for (id, delta) in self.texture_mirror.full_deltas() {
+            self.egui_painter.set_texture(id, &delta);
+        }
+
+        info!(
+            "EGL context recovered ({:?}, {} textures re-uploaded)",
+            self.gles_version,
+            self.texture_mirror.len()
+        );
+        Ok(())
+    }
+
+    /// Run one egui pass and paint it. The returned platform output (copied
+    /// text, URLs to open, ...) is for the caller to act on.
+    pub fn render<F: FnOnce(&egui::Context)>(&mut self, run_ui: F) -> egui::PlatformOutput {
+        self.resize(self.window.width(), self.window.height());
+
+        let ctx = &mut self.egui_context;
+        let painter = &mut self.egui_painter;
+        self.egui_raw_input.time = Some(self.start_time.elapsed().as_secs_f64());
+        self.egui_raw_input.screen_rect = Some(screen_rect(
+            self.width,
+            self.height,
+            self.native_pixels_per_point * ctx.zoom_factor(),
+        ));
+        self.egui_raw_input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(self.native_pixels_per_point);
+
+        ctx.begin_pass(self.egui_raw_input.take());
+
+        run_ui(ctx);
+        overlay::apply_window_alpha(ctx, &self.window_alpha);
+
+        let mut full_output = ctx.end_pass();
+        self.input_region = InputRegion::from_context(ctx, full_output.pixels_per_point);
+        self.input_region
+            .rects
+            .extend(self.viewports.values().map(ViewportSurface::screen_rect));
+
+        // Paint egui primitives
+        let mut clipped_primitives =
+            ctx.tessellate(full_output.shapes, full_output.pixels_per_point);
+        overlay::apply_global_opacity(&mut clipped_primitives, self.opacity);
+
+        // Only repaint what changed since the back buffer was last drawn.
+        let screen = egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(self.width as f32, self.height as f32),
+        );
+        self.damage.update(
+            &clipped_primitives,
+            &full_output.textures_delta,
+            full_output.pixels_per_point,
+            screen,
+        );
+        let repair =
+            self.damage
+                .repair_region(&self.egl, self.egl_display, self.egl_surface, screen);
+        self.repainted = repair.is_some();
+        let clipped_primitives = match repair {
+            Some(region) => {
+                unsafe {
+                    let gl = painter.gl();
+                    gl.viewport(0, 0, self.width, self.height);
+                    gl.enable(glow::SCISSOR_TEST);
+                    gl.scissor(
+                        region.min.x as i32,
+                        self.height - region.max.y as i32,
+                        region.width() as i32,
+                        region.height() as i32,
+                    );
+                    gl.clear_color(0.0, 0.0, 0.0, 0.0);
+                    gl.clear(glow::COLOR_BUFFER_BIT);
+                    gl.disable(glow::SCISSOR_TEST);
+                }
+                damage::clip_primitives(clipped_primitives, region / full_output.pixels_per_point)
+            }
+            None => Vec::new(),
+        };
+
+        self.texture_mirror.apply_set(&full_output.textures_delta);
+        painter.paint_and_update_textures(
+            [self.width as u32, self.height as u32],
+            full_output.pixels_per_point,
+            &clipped_primitives,
+            &full_output.textures_delta,
+        );
+        self.texture_mirror.apply_free(&full_output.textures_delta);
+
+        self.update_viewport_requests(std::mem::take(&mut full_output.viewport_output));
+        let mut platform_output = full_output.platform_output;
+        if !self.viewports.is_empty() {
+            self.render_viewports(&mut platform_output);
+        }
+        platform_output
+    }
+
+    /// Read back the frame just rendered by [`Self::render`]. Call it before
+    /// [`Self::swap_buffers`]; afterwards the back buffer is undefined.
+    ///
+    /// Pixels are premultiplied, as composited. Secondary viewports are not
+    /// included.
+    pub fn capture_frame(&self) -> egui::ColorImage {
+        self.egui_painter
+            .read_screen_rgba([self.width as u32, self.height as u32])
+    }
+
+    /// Present the frame. A lost surface or context is recovered here so the
+    /// next frame can render normally; only unrecoverable errors are returned.
+    ///
+    /// If nothing changed, nothing is swapped and the previous frame stays
+    /// on screen. Either way this returns at the display's frame rate.
+    pub fn swap_buffers(&mut self) -> Result<()> {
+        if !self.repainted || self.surface_lost {
+            self.pacer.wait();
+            return Ok(());
+        }
+        let result =
+            self.damage
+                .swap_buffers(&self.egl, self.egl_display, self.egl_surface, self.height);
+        self.pacer.wait();
+        match result {
+            Ok(()) => Ok(()),
+            Err(egl::Error::BadSurface | egl::Error::BadNativeWindow) => {
+                warn!("EGL surface invalid, recreating");
+                if let Err(e) = self.recreate_surface() {
+                    warn!("The window can't take a new surface ({e:#}), waiting for another");
+                }
+                Ok(())
+            }
+            Err(egl::Error::ContextLost) => self.recover_context(),
+            Err(e) => Err(anyhow::anyhow!("Swap buffers failed: {}", e)),
+        }
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        self.egui_painter.destroy();
+        let _ = self.egl.make_current(self.egl_display, None, None, None);
+        for (id, viewport) in &self.viewports {
+            if !self.lost_viewports.contains(id) {
+                self.destroy_viewport_surface(viewport);
+            }
+        }
+        if !self.surface_lost {
+            let _ = self.egl.destroy_surface(self.egl_display, self.egl_surface);
+        }
+        let _ = self.egl.destroy_context(self.egl_display, self.egl_context);
+    }
+}
+
+/// The drawable area in egui points.
+fn screen_rect(width: i32, height: i32, pixels_per_point: f32) -> egui::Rect {
+    egui::Rect::from_min_size(
+        egui::Pos2::ZERO,
+        egui::vec2(width as f32, height as f32) / pixels_per_point,
+    )
+}
+
+fn create_painter(egl: &Egl) -> Result<egui_glow::Painter> {
+    let gl = unsafe {
+        glow::Context::from_loader_function(|name| {
+            egl.get_proc_address(name)
+                .map(|f| f as *const c_void)
+                .unwrap_or(std::ptr::null())
+        })
+    };
+
+    egui_glow::Painter::new(Arc::new(gl), "", None, false)
+        .map_err(|e| anyhow::anyhow!("Failed to create painter: {}", e))
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use egui::epaint::{self, ClippedPrimitive, Primitive};
+use egui::layers::ShapeIdx;
+
+/// Screen areas covered by visible egui windows, in physical pixels.
+///
+/// Touches inside belong to the overlay; everything else should reach the
+/// apps underneath.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputRegion {
+    pub rects: Vec<egui::Rect>,
+}
+
+impl InputRegion {
+    /// Collect the rects of every visible area (windows, popups, panels).
+    /// Plain layer painters have no area and never block input.
+    pub fn from_context(ctx: &egui::Context, pixels_per_point: f32) -> Self {
+        let rects = ctx.memory(|mem| {
+            mem.layer_ids()
+                .filter(|layer| mem.areas().is_visible(layer))
+                .filter_map(|layer| mem.area_rect(layer.id))
+                .map(|rect| rect * pixels_per_point)
+                .collect()
+        });
+        Self { rects }
+    }
+
+    pub fn contains(&self, pos: egui::Pos2) -> bool {
+        self.rects.iter().any(|rect| rect.contains(pos))
+    }
+}
+
+/// Multiply the colors of everything painted so far on the given areas' layers.
+///
+/// Must run between the UI closure and `end_pass`, while shapes are still
+/// grouped by layer.
+pub fn apply_window_alpha(ctx: &egui::Context, alphas: &HashMap<egui::Id, f32>) {
+    if alphas.is_empty() {
+        return;
+    }
+    let layers = ctx.memory(|mem| {
+        mem.layer_ids()
+            .filter_map(|layer| Some((layer, *alphas.get(&layer.id)?)))
+            .collect::<Vec<_>>()
+    });
+    ctx.graphics_mut(|graphics| {
+        for (layer, alpha) in layers {
+            let Some(list) = graphics.get_mut(layer) else {
+                continue;
+            };
+            for idx in 0..list.next_idx().0 {
+                list.mutate_shape(ShapeIdx(idx), |clipped| {
+                    epaint::shape_transform::adjust_colors(&mut clipped.shape, move |color| {
+                        *color = color.gamma_multiply(alpha);
+                    });
+                });
+            }
+        }
+    });
+}
+
+/// Fade every tessellated mesh by `opacity`.
+///
+/// egui colors are premultiplied, so scaling all four channels keeps them
+/// valid and the compositor blends the result correctly.
+pub fn apply_global_opacity(primitives: &mut [ClippedPrimitive], opacity: f32) {
+    if opacity >= 1.0 {
+        return;
+    }
+    for primitive in primitives {
+        if let Primitive::Mesh(mesh) = &mut primitive.primitive {
+            for vertex in &mut mesh.vertices {
+                vertex.color = vertex.color.gamma_multiply(opacity);
+            }
+        }
+    }
+}
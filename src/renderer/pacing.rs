@@ -0,0 +1,50 @@
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+/// Refresh rate assumed until the display reports one.
+const DEFAULT_REFRESH_RATE: f32 = 60.0;
+
+/// Shorter sleeps are skipped: when the swap already waited for vsync only
+/// jitter is left, and oversleeping it would miss the next vsync.
+const MIN_SLEEP: Duration = Duration::from_millis(2);
+
+/// Caps the render loop at the display refresh rate.
+///
+/// A swap normally blocks until vsync, which does the pacing on its own;
+/// this covers frames that don't swap (nothing changed) and swaps that
+/// return early.
+pub(super) struct FramePacer {
+    frame_time: Duration,
+    last_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        Self {
+            frame_time: Duration::from_secs_f32(1.0 / DEFAULT_REFRESH_RATE),
+            last_frame: Instant::now(),
+        }
+    }
+
+    pub fn set_refresh_rate(&mut self, hz: f32) {
+        if !(hz.is_finite() && hz > 0.0) {
+            return;
+        }
+        let frame_time = Duration::from_secs_f32(1.0 / hz);
+        if frame_time != self.frame_time {
+            info!("Pacing frames for {:.1} Hz", hz);
+            self.frame_time = frame_time;
+        }
+    }
+
+    /// Sleep out the rest of the current frame interval.
+    pub fn wait(&mut self) {
+        if let Some(remaining) = self.frame_time.checked_sub(self.last_frame.elapsed())
+            && remaining >= MIN_SLEEP
+        {
+            std::thread::sleep(remaining);
+        }
+        self.last_frame = Instant::now();
+    }
+}
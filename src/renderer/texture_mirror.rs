@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use egui::epaint::{ImageData, ImageDelta};
+use egui::{ColorImage, TextureId, TextureOptions, TexturesDelta};
+
+/// CPU-side copy of every egui-managed texture.
+///
+/// egui only sends a texture once (plus partial patches, e.g. when the font
+/// atlas grows), so after a GL context loss there is nothing to re-upload
+/// from unless we keep our own copy. User textures registered directly with
+/// the painter are not tracked.
+#[derive(Default)]
+pub struct TextureMirror {
+    textures: HashMap<TextureId, (Arc<ColorImage>, TextureOptions)>,
+}
+
+impl TextureMirror {
+    /// Apply the `set` half of a frame's delta. Call before painting.
+    pub fn apply_set(&mut self, delta: &TexturesDelta) {
+        for (id, image_delta) in &delta.set {
+            let ImageData::Color(patch) = &image_delta.image;
+            match image_delta.pos {
+                None => {
+                    self.textures
+                        .insert(*id, (patch.clone(), image_delta.options));
+                }
+                Some([x, y]) => {
+                    let Some((image, options)) = self.textures.get_mut(id) else {
+                        continue;
+                    };
+                    *options = image_delta.options;
+                    let image = Arc::make_mut(image);
+                    let [w, h] = patch.size;
+                    for row in 0..h {
+                        let dst_start = (y + row) * image.size[0] + x;
+                        let src_start = row * w;
+                        image.pixels[dst_start..dst_start + w]
+                            .copy_from_slice(&patch.pixels[src_start..src_start + w]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply the `free` half of a frame's delta. Call after painting.
+    pub fn apply_free(&mut self, delta: &TexturesDelta) {
+        for id in &delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    /// Full uploads for every live texture, for replaying into a new painter.
+    pub fn full_deltas(&self) -> impl Iterator<Item = (TextureId, ImageDelta)> + '_ {
+        self.textures.iter().map(|(id, (image, options))| {
+            (
+                *id,
+                ImageDelta::full(ImageData::Color(image.clone()), *options),
+            )
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use egui::{DeferredViewportUiCallback, ViewportBuilder, ViewportId};
+use glow::HasContext;
+use khronos_egl as egl;
+use ndk::native_window::NativeWindow;
+use tracing::{debug, info, warn};
+
+use super::{Renderer, egl_config, screen_rect};
+use crate::input;
+
+/// Size of a viewport whose builder doesn't set `inner_size`, in points.
+const DEFAULT_VIEWPORT_SIZE: egui::Vec2 = egui::vec2(240.0, 160.0);
+
+/// A deferred egui viewport requested by the UI, as seen by the last pass.
+pub(super) struct ViewportRequest {
+    pub builder: ViewportBuilder,
+    pub ui_cb: Option<Arc<DeferredViewportUiCallback>>,
+}
+
+/// A secondary native window driven as its own egui viewport.
+pub(super) struct ViewportSurface {
+    window: NativeWindow,
+    egl_surface: egl::Surface,
+    /// Top-left corner on screen, in physical pixels.
+    origin: egui::Pos2,
+    raw_input: egui::RawInput,
+    /// When egui wants the next pass; `None` until it asks again.
+    repaint_at: Option<Instant>,
+}
+
+impl ViewportSurface {
+    /// Repaint on the next frame, e.g. after a move or opacity change.
+    pub fn request_repaint(&mut self) {
+        self.repaint_at = Some(Instant::now());
+    }
+
+    /// Honour a `repaint_delay` from egui. `Duration::MAX` means never.
+    fn schedule_repaint(&mut self, delay: Duration) {
+        let Some(at) = Instant::now().checked_add(delay) else {
+            return;
+        };
+        self.repaint_at = Some(self.repaint_at.map_or(at, |current| current.min(at)));
+    }
+
+    /// Whether it should be run this frame: egui asked for it by now or it
+    /// received input.
+    fn take_repaint_due(&mut self) -> bool {
+        let due = self.repaint_at.is_some_and(|at| at <= Instant::now());
+        if due {
+            self.repaint_at = None;
+        }
+        due || !self.raw_input.events.is_empty()
+    }
+
+    /// The area covered on screen, in physical pixels.
+    pub fn screen_rect(&self) -> egui::Rect {
+        egui::Rect::from_min_size(
+            self.origin,
+            egui::vec2(self.window.width() as f32, self.window.height() as f32),
+        )
+    }
+}
+
+/// Screen position of a positional event.
+fn event_pos(event: &egui::Event) -> Option<egui::Pos2> {
+    match event {
+        egui::Event::Touch { pos, .. }
+        | egui::Event::PointerMoved(pos)
+        | egui::Event::PointerButton { pos, .. } => Some(*pos),
+        _ => None,
+    }
+}
+
+/// Move a positional event into a viewport's local coordinates.
+fn translate(event: egui::Event, offset: egui::Vec2) -> egui::Event {
+    match event {
+        egui::Event::Touch {
+            device_id,
+            id,
+            phase,
+            pos,
+            force,
+        } => egui::Event::Touch {
+            device_id,
+            id,
+            phase,
+            pos: pos - offset,
+            force,
+        },
+        egui::Event::PointerMoved(pos) => egui::Event::PointerMoved(pos - offset),
+        egui::Event::PointerButton {
+            pos,
+            button,
+            pressed,
+            modifiers,
+        } => egui::Event::PointerButton {
+            pos: pos - offset,
+            button,
+            pressed,
+            modifiers,
+        },
+        other => other,
+    }
+}
+
+impl Renderer {
+    /// Screen rect in physical pixels that a newly requested viewport should
+    /// get, from its builder's `position` and `inner_size` (both in points).
+    pub fn viewport_screen_rect(&self, builder: &ViewportBuilder) -> egui::Rect {
+        let pixels_per_point = self.pixels_per_point();
+        let position = builder.position.unwrap_or(egui::Pos2::ZERO);
+        let size = builder.inner_size.unwrap_or(DEFAULT_VIEWPORT_SIZE);
+        let min = (position.to_vec2() * pixels_per_point).to_pos2();
+        egui::Rect::from_min_max(min.round(), (min + size * pixels_per_point).round())
+    }
+
+    /// Deferred viewports the UI asked for that have no native window yet.
+    /// Create one for each (see [`Self::viewport_screen_rect`]) and hand it
+    /// to [`Self::attach_viewport`].
+    pub fn missing_viewports(&self) -> Vec<(ViewportId, ViewportBuilder)> {
+        self.viewport_requests
+            .iter()
+            .filter(|(id, request)| {
+                request.ui_cb.is_some()
+                    && !self.viewports.contains_key(id)
+                    && !self.closed_viewports.contains(id)
+            })
+            .map(|(id, request)| (*id, request.builder.clone()))
+            .collect()
+    }
+
+    /// Start rendering viewport `id` into `window`, placed at `origin`
+    /// (physical pixels) on screen.
+    pub fn attach_viewport(
+        &mut self,
+        id: ViewportId,
+        window: &NativeWindow,
+        origin: egui::Pos2,
+    ) -> Result<()> {
+        let format = self.egl_config.candidate.native_visual_id;
+        window.set_buffers_geometry(0, 0, Some(format.into()))?;
+        let egl_surface = unsafe {
+            egl_config::create_window_surface(
+                &self.egl,
+                self.egl_display,
+                &mut self.egl_config,
+                window.ptr().as_ptr() as *mut _,
+            )?
+        };
+
+        info!(
+            "Attached viewport {:?}: {}x{} at {:?}",
+            id,
+            window.width(),
+            window.height(),
+            origin
+        );
+        self.viewports.insert(
+            id,
+            ViewportSurface {
+                window: window.clone(),
+                egl_surface,
+                origin,
+                raw_input: egui::RawInput {
+                    viewport_id: id,
+                    max_texture_side: Some(self.egui_painter.max_texture_side()),
+                    ..Default::default()
+                },
+                repaint_at: Some(Instant::now()),
+            },
+        );
+        Ok(())
+    }
+
+    /// Viewports the UI stopped showing or closed with
+    /// `ViewportCommand::Close` since the last call, and ones whose window
+    /// can't take a new EGL surface (they get a new window through
+    /// [`Self::missing_viewports`]). Their EGL surfaces are already
+    /// destroyed; the caller should release the native windows.
+    pub fn take_closed_viewports(&mut self) -> Vec<ViewportId> {
+        let closed = self
+            .viewports
+            .keys()
+            .filter(|id| {
+                !self.viewport_requests.contains_key(id)
+                    || self.closed_viewports.contains(id)
+                    || self.lost_viewports.contains(id)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        for id in &closed {
+            self.viewport_moves.remove(id);
+            if let Some(viewport) = self.viewports.remove(id) {
+                if !self.lost_viewports.remove(id) {
+                    self.destroy_viewport_surface(&viewport);
+                }
+                info!("Closed viewport {:?}", id);
+            }
+        }
+        closed
+    }
+
+    /// Viewports moved or resized by a `ViewportCommand` since the last
+    /// call, with their new screen rect in physical pixels. The caller should
+    /// move the native windows' layers to match.
+    pub fn take_moved_viewports(&mut self) -> Vec<(ViewportId, egui::Rect)> {
+        self.viewport_moves.drain().collect()
+    }
+
+    pub(super) fn destroy_viewport_surface(&self, viewport: &ViewportSurface) {
+        if let Err(e) = self
+            .egl
+            .destroy_surface(self.egl_display, viewport.egl_surface)
+        {
+            warn!("eglDestroySurface failed for viewport: {}", e);
+        }
+    }
+
+    /// Send each event to the viewport under it. A touch stays with the
+    /// viewport that received its `Start`, even if it wanders off.
+    pub(super) fn route_events(&mut self, events: Vec<egui::Event>) {
+        let pixels_per_point = self.pixels_per_point();
+
+        for event in events {
+            let at = |pos: egui::Pos2| {
+                self.viewports
+                    .iter()
+                    .find(|(_, v)| v.screen_rect().contains(pos))
+                    .map_or(ViewportId::ROOT, |(id, _)| *id)
+            };
+            let target = match (&event, event_pos(&event)) {
+                (egui::Event::Touch { id, phase, .. }, Some(pos)) => match phase {
+                    egui::TouchPhase::Start => {
+                        let target = at(pos);
+                        self.touch_owners.insert(id.0, target);
+                        target
+                    }
+                    egui::TouchPhase::Move => *self.touch_owners.get(&id.0).unwrap_or(&at(pos)),
+                    egui::TouchPhase::End | egui::TouchPhase::Cancel => {
+                        self.touch_owners.remove(&id.0).unwrap_or_else(|| at(pos))
+                    }
+                },
+                (_, Some(pos)) => at(pos),
+                (_, None) => ViewportId::ROOT,
+            };
+
+            match self.viewports.get_mut(&target) {
+                Some(viewport) => {
+                    let local = translate(event, viewport.origin.to_vec2());
+                    viewport
+                        .raw_input
+                        .events
+                        .push(input::event_to_points(local, pixels_per_point));
+                }
+                None => self
+                    .egui_raw_input
+                    .events
+                    .push(input::event_to_points(event, pixels_per_point)),
+            }
+        }
+    }
+
+    /// Remember which viewports the pass that produced `outputs` wants.
+    pub(super) fn update_viewport_requests(
+        &mut self,
+        outputs: impl IntoIterator<Item = (ViewportId, egui::ViewportOutput)>,
+    ) {
+        self.viewport_requests.clear();
+        for (id, output) in outputs {
+            if id == ViewportId::ROOT {
+                continue;
+            }
+            self.apply_viewport_commands(id, &output.commands);
+            if let Some(viewport) = self.viewports.get_mut(&id) {
+                viewport.schedule_repaint(output.repaint_delay);
+            }
+            self.viewport_requests.insert(
+                id,
+                ViewportRequest {
+                    builder: output.builder,
+                    ui_cb: output.viewport_ui_cb,
+                },
+            );
+        }
+        self.closed_viewports
+            .retain(|id| self.viewport_requests.contains_key(id));
+    }
+
+    /// Carry out the commands the UI sent to viewport `id`. Only closing,
+    /// moving and resizing mean anything for a layer.
+    fn apply_viewport_commands(&mut self, id: ViewportId, commands: &[egui::ViewportCommand]) {
+        let pixels_per_point = self.pixels_per_point();
+        for command in commands {
+            let current = self.viewports.get(&id).map(ViewportSurface::screen_rect);
+            let rect = match (command, current) {
+                (egui::ViewportCommand::Close, _) => {
+                    self.closed_viewports.insert(id);
+                    continue;
+                }
+                (egui::ViewportCommand::OuterPosition(pos), Some(current)) => {
+                    let min = (pos.to_vec2() * pixels_per_point).round().to_pos2();
+                    egui::Rect::from_min_size(min, current.size())
+                }
+                (egui::ViewportCommand::InnerSize(size), Some(current)) => {
+                    egui::Rect::from_min_size(current.min, (*size * pixels_per_point).round())
+                }
+                _ => {
+                    debug!("Ignoring {:?} for viewport {:?}", command, id);
+                    continue;
+                }
+            };
+            if let Err(e) = self.move_viewport(id, rect) {
+                warn!("Cannot move viewport {:?}: {:#}", id, e);
+            }
+        }
+    }
+
+    /// Place viewport `id` at `rect` (physical pixels): resize its buffers
+    /// now and queue the layer move for [`Self::take_moved_viewports`].
+    fn move_viewport(&mut self, id: ViewportId, rect: egui::Rect) -> Result<()> {
+        let format = self.egl_config.candidate.native_visual_id;
+        let Some(viewport) = self.viewports.get_mut(&id) else {
+            return Ok(());
+        };
+        if rect.size() != viewport.screen_rect().size() {
+            viewport.window.set_buffers_geometry(
+                rect.width() as i32,
+                rect.height() as i32,
+                Some(format.into()),
+            )?;
+        }
+        viewport.origin = rect.min;
+        viewport.request_repaint();
+        self.viewport_moves.insert(id, rect);
+        Ok(())
+    }
+
+    /// Run and present the attached deferred viewports that egui asked to
+    /// repaint or that received input, then make the root surface current
+    /// again. Their platform output is merged into `output`.
+    pub(super) fn render_viewports(&mut self, output: &mut egui::PlatformOutput) {
+        let ids = self.viewports.keys().copied().collect::<Vec<_>>();
+        let mut nested = HashMap::new();
+
+        for id in ids {
+            let Some(ui_cb) = self
+                .viewport_requests
+                .get(&id)
+                .and_then(|r| r.ui_cb.clone())
+            else {
+                continue;
+            };
+            if !self
+                .viewports
+                .get_mut(&id)
+                .is_some_and(ViewportSurface::take_repaint_due)
+            {
+                continue;
+            }
+            match self.render_viewport(id, ui_cb) {
+                Ok(full_output) => {
+                    output.append(full_output.platform_output);
+                    for (nested_id, nested_output) in full_output.viewport_output {
+                        if nested_id == id {
+                            self.apply_viewport_commands(id, &nested_output.commands);
+                            if let Some(viewport) = self.viewports.get_mut(&id) {
+                                viewport.schedule_repaint(nested_output.repaint_delay);
+                            }
+                        } else {
+                            nested.insert(nested_id, nested_output);
+                        }
+                    }
+                }
+                Err(e) => warn!("Viewport {:?} failed to render: {:#}", id, e),
+            }
+        }
+
+        // Viewports opened from inside other viewports.
+        for (id, output) in nested {
+            if id != ViewportId::ROOT && !self.viewport_requests.contains_key(&id) {
+                self.viewport_requests.insert(
+                    id,
+                    ViewportRequest {
+                        builder: output.builder,
+                        ui_cb: output.viewport_ui_cb,
+                    },
+                );
+            }
+        }
+
+        if let Err(e) = self.egl.make_current(
+            self.egl_display,
+            Some(self.egl_surface),
+            Some(self.egl_surface),
+            Some(self.egl_context),
+        ) {
+            warn!("Failed to restore root surface: {}", e);
+        }
+    }
+
+    fn render_viewport(
+        &mut self,
+        id: ViewportId,
+        ui_cb: Arc<DeferredViewportUiCallback>,
+    ) -> Result<egui::FullOutput> {
+        let pixels_per_point = self.pixels_per_point();
+        let native_pixels_per_point = self.native_pixels_per_point;
+        let time = self.start_time.elapsed().as_secs_f64();
+        let viewport = self
+            .viewports
+            .get_mut(&id)
+            .ok_or_else(|| anyhow::anyhow!("viewport not attached"))?;
+        let (width, height) = (viewport.window.width(), viewport.window.height());
+
+        self.egl.make_current(
+            self.egl_display,
+            Some(viewport.egl_surface),
+            Some(viewport.egl_surface),
+            Some(self.egl_context),
+        )?;
+        unsafe {
+            let gl = self.egui_painter.gl();
+            gl.viewport(0, 0, width, height);
+            gl.clear_color(0.0, 0.0, 0.0, 0.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+        }
+
+        let mut raw_input = viewport.raw_input.take();
+        raw_input.viewport_id = id;
+        raw_input.time = Some(time);
+        raw_input.screen_rect = Some(screen_rect(width, height, pixels_per_point));
+        raw_input.viewports = std::iter::once((
+            id,
+            egui::ViewportInfo {
+                native_pixels_per_point: Some(native_pixels_per_point),
+                ..Default::default()
+            },
+        ))
+        .collect();
+        let egl_surface = viewport.egl_surface;
+
+        let mut full_output = self.egui_context.run(raw_input, |ctx| ui_cb(ctx));
+
+        let shapes = std::mem::take(&mut full_output.shapes);
+        let mut clipped_primitives = self
+            .egui_context
+            .tessellate(shapes, full_output.pixels_per_point);
+        super::overlay::apply_global_opacity(&mut clipped_primitives, self.opacity);
+
+        self.texture_mirror.apply_set(&full_output.textures_delta);
+        self.egui_painter.paint_and_update_textures(
+            [width as u32, height as u32],
+            full_output.pixels_per_point,
+            &clipped_primitives,
+            &full_output.textures_delta,
+        );
+        self.texture_mirror.apply_free(&full_output.textures_delta);
+
+        if let Err(e) = self.egl.swap_buffers(self.egl_display, egl_surface) {
+            self.recover_viewport(id, e)?;
+        }
+        Ok(full_output)
+    }
+
+    /// Handle a failed swap of viewport `id` the way [`Self::swap_buffers`]
+    /// does for the root surface. A window that can't take a new surface
+    /// is replaced through [`Self::take_closed_viewports`].
+    fn recover_viewport(&mut self, id: ViewportId, error: egl::Error) -> Result<()> {
+        match error {
+            egl::Error::BadSurface | egl::Error::BadNativeWindow => {
+                warn!("EGL surface of viewport {:?} invalid, recreating", id);
+                if let Err(e) = self.recreate_viewport_surface(id) {
+                    warn!("Viewport {:?} needs a new window: {:#}", id, e);
+                    self.lost_viewports.insert(id);
+                }
+                Ok(())
+            }
+            egl::Error::ContextLost => self.recover_context(),
+            e => Err(anyhow::anyhow!("Swap buffers failed: {}", e)),
+        }
+    }
+
+    fn recreate_viewport_surface(&mut self, id: ViewportId) -> Result<()> {
+        let _ = self.egl.make_current(self.egl_display, None, None, None);
+        let Some(viewport) = self.viewports.get_mut(&id) else {
+            return Ok(());
+        };
+        if let Err(e) = self
+            .egl
+            .destroy_surface(self.egl_display, viewport.egl_surface)
+        {
+            warn!("eglDestroySurface failed for viewport: {}", e);
+        }
+        viewport.egl_surface = unsafe {
+            egl_config::create_window_surface(
+                &self.egl,
+                self.egl_display,
+                &mut self.egl_config,
+                viewport.window.ptr().as_ptr() as *mut _,
+            )?
+        };
+        viewport.request_repaint();
+        Ok(())
+    }
+}
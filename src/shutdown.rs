@@ -0,0 +1,134 @@
+//! Orderly exit: signals ask the render loop to stop, and failures carry the
+//! [`Phase`] they happened in so the process exit code tells them apart.
+
+use std::fmt;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::warn;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Signals that stop the overlay: Ctrl+C, `kill` and the adb shell going away.
+const SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// How long `DestroyJavaVM` may wait for other threads before we give up
+/// and exit anyway.
+pub const TEARDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where startup or shutdown failed. Attached to errors as context, e.g.
+/// `runtime.create_java_vm(&options).context(Phase::Vm)?`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Command line or environment options.
+    Config,
+    /// Loading `libandroid_runtime.so` and `JniInvocation`, or starting
+    /// `app_process`.
+    Runtime,
+    /// `JNI_CreateJavaVM` and `startReg`.
+    Vm,
+    /// Loading the dex and running its `main`.
+    Bridge,
+    /// Display queries and the native window.
+    Window,
+    /// EGL, the UI and the render loop.
+    Render,
+    /// `DestroyJavaVM`.
+    Teardown,
+}
+
+impl Phase {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Config => 2,
+            Self::Runtime => 3,
+            Self::Vm => 4,
+            Self::Bridge => 5,
+            Self::Window => 6,
+            Self::Render => 7,
+            Self::Teardown => 8,
+        }
+    }
+
+    /// The phase a child such as `app_process` failed in, by its exit code.
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        [
+            Self::Config,
+            Self::Runtime,
+            Self::Vm,
+            Self::Bridge,
+            Self::Window,
+            Self::Render,
+            Self::Teardown,
+        ]
+        .into_iter()
+        .find(|phase| i32::from(phase.exit_code()) == code)
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Config => "reading options",
+            Self::Runtime => "loading the Android runtime",
+            Self::Vm => "starting the Java VM",
+            Self::Bridge => "loading the Java bridge",
+            Self::Window => "creating the window",
+            Self::Render => "rendering",
+            Self::Teardown => "shutting down the Java VM",
+        })
+    }
+}
+
+/// The exit code for a failed run: its [`Phase`]'s, or 1 if it has none.
+pub fn exit_code(error: &anyhow::Error) -> ExitCode {
+    ExitCode::from(exit_status(error))
+}
+
+/// [`exit_code`] as a number, for [`std::process::exit`].
+pub fn exit_status(error: &anyhow::Error) -> u8 {
+    error
+        .downcast_ref::<Phase>()
+        .map_or(1, |phase| phase.exit_code())
+}
+
+/// Turn [`SIGNALS`] into a shutdown request instead of an instant kill. A
+/// second signal exits right away, for when shutdown itself hangs.
+pub fn install_signal_handlers() -> Result<()> {
+    for signal in SIGNALS {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    Ok(())
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    // Only async-signal-safe work in here.
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(128 + signal) };
+    }
+}
+
+/// Whether a signal asked us to stop.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Exit with [`Phase::Teardown`]'s code if still running after
+/// [`TEARDOWN_TIMEOUT`].
+pub fn start_watchdog() {
+    let _ = std::thread::Builder::new()
+        .name("shutdown-watchdog".to_owned())
+        .spawn(|| {
+            let _alt_stack = crate::crash::AltStack::install();
+            std::thread::sleep(TEARDOWN_TIMEOUT);
+            warn!("Shutdown took longer than {TEARDOWN_TIMEOUT:?}, exiting");
+            std::process::exit(Phase::Teardown.exit_code().into());
+        });
+}
@@ -0,0 +1,345 @@
+//! System properties through bionic's property area: reading values of any
+//! length, listing all properties, waiting for changes and setting them, plus
+//! a browser panel for the UI.
+//!
+//! `__system_property_get` copies into a `PROP_VALUE_MAX` (92 byte) buffer
+//! and refuses longer `ro.*` values; everything here reads through
+//! `__system_property_read_callback` instead (Android 8+).
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use tracing::warn;
+
+/// How long the watcher blocks in [`wait`] before checking whether it
+/// should stop, and so how long it may outlive its [`Watcher`].
+const WATCH_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long a changed property stays highlighted in the browser.
+const CHANGE_HIGHLIGHT: Duration = Duration::from_secs(3);
+
+/// bionic's `prop_info`, an entry in the shared property area.
+#[repr(C)]
+struct RawPropInfo {
+    _private: [u8; 0],
+}
+
+type ReadCallback = unsafe extern "C" fn(
+    cookie: *mut c_void,
+    name: *const c_char,
+    value: *const c_char,
+    serial: u32,
+);
+type ForeachCallback = unsafe extern "C" fn(pi: *const RawPropInfo, cookie: *mut c_void);
+
+unsafe extern "C" {
+    fn __system_property_find(name: *const c_char) -> *const RawPropInfo;
+    fn __system_property_read_callback(
+        pi: *const RawPropInfo,
+        callback: ReadCallback,
+        cookie: *mut c_void,
+    );
+    fn __system_property_foreach(propfn: ForeachCallback, cookie: *mut c_void) -> c_int;
+    fn __system_property_wait(
+        pi: *const RawPropInfo,
+        old_serial: u32,
+        new_serial_ptr: *mut u32,
+        relative_timeout: *const libc::timespec,
+    ) -> bool;
+    fn __system_property_set(name: *const c_char, value: *const c_char) -> c_int;
+    fn __system_property_area_serial() -> u32;
+}
+
+/// A property's name, value and serial, read at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    /// Changes whenever the value does.
+    pub serial: u32,
+}
+
+/// Handle to a property that exists. Properties are never removed, so it
+/// stays valid for the life of the process.
+#[derive(Debug, Clone, Copy)]
+pub struct PropInfo(*const RawPropInfo);
+
+// Points into the read-only mapping of the property area.
+unsafe impl Send for PropInfo {}
+unsafe impl Sync for PropInfo {}
+
+impl PropInfo {
+    pub fn find(name: &str) -> Option<Self> {
+        let name = CString::new(name).ok()?;
+        let pi = unsafe { __system_property_find(name.as_ptr()) };
+        (!pi.is_null()).then_some(Self(pi))
+    }
+
+    pub fn read(&self) -> Property {
+        unsafe extern "C" fn on_read(
+            cookie: *mut c_void,
+            name: *const c_char,
+            value: *const c_char,
+            serial: u32,
+        ) {
+            let property = unsafe { &mut *(cookie as *mut Property) };
+            property.name = unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned();
+            property.value = unsafe { CStr::from_ptr(value) }
+                .to_string_lossy()
+                .into_owned();
+            property.serial = serial;
+        }
+
+        let mut property = Property {
+            name: String::new(),
+            value: String::new(),
+            serial: 0,
+        };
+        unsafe {
+            __system_property_read_callback(
+                self.0,
+                on_read,
+                &mut property as *mut Property as *mut c_void,
+            )
+        };
+        property
+    }
+}
+
+/// A property's value, or `None` if it is unset or empty.
+pub fn get(name: &str) -> Option<String> {
+    let value = PropInfo::find(name)?.read().value;
+    (!value.is_empty()).then_some(value)
+}
+
+/// Every property, in property area order.
+pub fn list() -> Vec<Property> {
+    unsafe extern "C" fn on_property(pi: *const RawPropInfo, cookie: *mut c_void) {
+        let properties = unsafe { &mut *(cookie as *mut Vec<Property>) };
+        properties.push(PropInfo(pi).read());
+    }
+
+    let mut properties = Vec::new();
+    unsafe {
+        __system_property_foreach(
+            on_property,
+            &mut properties as *mut Vec<Property> as *mut c_void,
+        )
+    };
+    properties
+}
+
+/// The serial of the whole property area, which changes whenever any
+/// property is added or set.
+pub fn area_serial() -> u32 {
+    unsafe { __system_property_area_serial() }
+}
+
+/// Block until any property changes after [`area_serial`] returned `serial`,
+/// or `timeout` passes. Returns the new area serial.
+pub fn wait(serial: u32, timeout: Duration) -> Option<u32> {
+    let timeout = libc::timespec {
+        tv_sec: timeout.as_secs() as _,
+        tv_nsec: timeout.subsec_nanos() as _,
+    };
+    let mut new_serial = 0;
+    let changed =
+        unsafe { __system_property_wait(std::ptr::null(), serial, &mut new_serial, &timeout) };
+    changed.then_some(new_serial)
+}
+
+/// Ask init to set a property. init checks the caller's SELinux context
+/// against the property's, so this mostly works as root or for `debug.*`
+/// as shell; `ro.*` properties can't be changed once set.
+pub fn set(name: &str, value: &str) -> Result<()> {
+    let c_name = CString::new(name)?;
+    let c_value = CString::new(value)?;
+    if unsafe { __system_property_set(c_name.as_ptr(), c_value.as_ptr()) } != 0 {
+        bail!("init refused to set {name}");
+    }
+    Ok(())
+}
+
+/// Properties as last listed, with when each one last changed.
+#[derive(Default)]
+struct Snapshot {
+    properties: Vec<Property>,
+    changed: HashMap<String, Instant>,
+}
+
+impl Snapshot {
+    fn refresh(&mut self) {
+        let mut properties = list();
+        properties.sort_by(|a, b| a.name.cmp(&b.name));
+        if !self.properties.is_empty() {
+            let old = self
+                .properties
+                .iter()
+                .map(|p| (p.name.as_str(), p.serial))
+                .collect::<HashMap<_, _>>();
+            let now = Instant::now();
+            for property in &properties {
+                if old.get(property.name.as_str()) != Some(&property.serial) {
+                    self.changed.insert(property.name.clone(), now);
+                }
+            }
+        }
+        self.changed.retain(|_, at| at.elapsed() < CHANGE_HIGHLIGHT);
+        self.properties = properties;
+    }
+}
+
+/// Re-lists properties whenever one changes, until dropped.
+struct Watcher {
+    stop: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    fn start(snapshot: Arc<Mutex<Snapshot>>, ctx: egui::Context) -> Option<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        thread::Builder::new()
+            .name("sysprop-watch".into())
+            .spawn(move || {
+                let _alt_stack = crate::crash::AltStack::install();
+                let mut serial = area_serial();
+                while !thread_stop.load(Ordering::Relaxed) {
+                    if let Some(new_serial) = wait(serial, WATCH_TIMEOUT) {
+                        serial = new_serial;
+                        if let Ok(mut snapshot) = snapshot.lock() {
+                            snapshot.refresh();
+                        }
+                        ctx.request_repaint();
+                    }
+                }
+            })
+            .map_err(|e| warn!("Cannot start the property watcher: {e}"))
+            .ok()?;
+        Some(Self { stop })
+    }
+}
+
+impl Drop for Watcher {
+    /// The thread notices within [`WATCH_TIMEOUT`] and exits by itself;
+    /// it is dropped on the UI thread, which shouldn't wait that long.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// UI to search the property list, watch it change live and set values.
+#[derive(Default)]
+pub struct PropertyBrowser {
+    snapshot: Arc<Mutex<Snapshot>>,
+    search: String,
+    watcher: Option<Watcher>,
+    /// The name and value being edited.
+    edit_name: String,
+    edit_value: String,
+    status: String,
+}
+
+impl PropertyBrowser {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let Ok(mut snapshot) = self.snapshot.lock() else {
+            return;
+        };
+        if snapshot.properties.is_empty() {
+            snapshot.refresh();
+        }
+        snapshot
+            .changed
+            .retain(|_, at| at.elapsed() < CHANGE_HIGHLIGHT);
+
+        ui.horizontal(|ui| {
+            ui.label("Search:");
+            ui.text_edit_singleline(&mut self.search);
+            if ui.button("Refresh").clicked() {
+                snapshot.refresh();
+            }
+        });
+
+        let search = self.search.to_lowercase();
+        let matches = snapshot
+            .properties
+            .iter()
+            .filter(|p| {
+                search.is_empty()
+                    || p.name.to_lowercase().contains(&search)
+                    || p.value.to_lowercase().contains(&search)
+            })
+            .collect::<Vec<_>>();
+        ui.label(
+            egui::RichText::new(format!(
+                "{} of {} properties",
+                matches.len(),
+                snapshot.properties.len()
+            ))
+            .weak(),
+        );
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::vertical()
+            .max_height(240.0)
+            .auto_shrink([false, true])
+            .show_rows(ui, row_height, matches.len(), |ui, rows| {
+                for property in &matches[rows] {
+                    let text =
+                        egui::RichText::new(format!("{} = {}", property.name, property.value))
+                            .monospace();
+                    let text = if snapshot.changed.contains_key(&property.name) {
+                        text.color(egui::Color32::YELLOW)
+                    } else {
+                        text
+                    };
+                    if ui
+                        .add(
+                            egui::Label::new(text)
+                                .truncate()
+                                .sense(egui::Sense::click()),
+                        )
+                        .clicked()
+                    {
+                        self.edit_name = property.name.clone();
+                        self.edit_value = property.value.clone();
+                    }
+                }
+            });
+        // Let highlights fade without waiting for other input.
+        if !snapshot.changed.is_empty() {
+            ui.ctx().request_repaint_after(CHANGE_HIGHLIGHT);
+        }
+        drop(snapshot);
+
+        let mut watch = self.watcher.is_some();
+        if ui.checkbox(&mut watch, "Watch for changes").changed() {
+            self.watcher = if watch {
+                Watcher::start(self.snapshot.clone(), ui.ctx().clone())
+            } else {
+                None
+            };
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Set:");
+            ui.add(egui::TextEdit::singleline(&mut self.edit_name).hint_text("name"));
+            ui.add(egui::TextEdit::singleline(&mut self.edit_value).hint_text("value"));
+            if ui.button("Set").clicked() {
+                self.status = match set(&self.edit_name, &self.edit_value) {
+                    Ok(()) => format!("Set {}", self.edit_name),
+                    Err(e) => format!("{e:#}"),
+                };
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(egui::RichText::new(&self.status).weak());
+        }
+    }
+}
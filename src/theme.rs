@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use jni::JNIEnv;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::bridge::JavaBridge;
+
+/// How often Android's night mode is re-read while following the system.
+const NIGHT_MODE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The theme picked by the user. Persisted as part of the app state.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThemeChoice {
+    /// Catppuccin Latte in light mode, Mocha in night mode.
+    #[default]
+    System,
+    Latte,
+    Frappe,
+    Macchiato,
+    Mocha,
+    EguiLight,
+    EguiDark,
+    /// An `egui::Style` serialized as RON.
+    Custom(PathBuf),
+}
+
+impl ThemeChoice {
+    /// Every built-in choice, in the order shown in a picker.
+    pub const BUILT_IN: [ThemeChoice; 7] = [
+        ThemeChoice::System,
+        ThemeChoice::Latte,
+        ThemeChoice::Frappe,
+        ThemeChoice::Macchiato,
+        ThemeChoice::Mocha,
+        ThemeChoice::EguiLight,
+        ThemeChoice::EguiDark,
+    ];
+
+    pub fn label(&self) -> String {
+        match self {
+            ThemeChoice::System => "System".to_owned(),
+            ThemeChoice::Latte => "Catppuccin Latte".to_owned(),
+            ThemeChoice::Frappe => "Catppuccin Frappé".to_owned(),
+            ThemeChoice::Macchiato => "Catppuccin Macchiato".to_owned(),
+            ThemeChoice::Mocha => "Catppuccin Mocha".to_owned(),
+            ThemeChoice::EguiLight => "egui light".to_owned(),
+            ThemeChoice::EguiDark => "egui dark".to_owned(),
+            ThemeChoice::Custom(path) => format!(
+                "Custom ({})",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        }
+    }
+
+    /// The concrete theme to show; never returns [`ThemeChoice::System`].
+    fn resolve(&self, night_mode: bool) -> ThemeChoice {
+        match self {
+            ThemeChoice::System if night_mode => ThemeChoice::Mocha,
+            ThemeChoice::System => ThemeChoice::Latte,
+            other => other.clone(),
+        }
+    }
+}
+
+/// Load an `egui::Style` from a RON file.
+pub fn load_style(path: &Path) -> Result<egui::Style> {
+    let data =
+        fs::read_to_string(path).with_context(|| format!("reading style {}", path.display()))?;
+    ron::from_str(&data).with_context(|| format!("parsing style {}", path.display()))
+}
+
+fn build_style(theme: &ThemeChoice) -> egui::Style {
+    let catppuccin = |flavour, visuals| {
+        let mut style = egui::Style {
+            visuals,
+            ..Default::default()
+        };
+        catppuccin_egui::set_style_theme(&mut style, flavour);
+        style
+    };
+
+    match theme {
+        ThemeChoice::Latte => catppuccin(catppuccin_egui::LATTE, egui::Visuals::light()),
+        ThemeChoice::Frappe => catppuccin(catppuccin_egui::FRAPPE, egui::Visuals::dark()),
+        ThemeChoice::Macchiato => catppuccin(catppuccin_egui::MACCHIATO, egui::Visuals::dark()),
+        ThemeChoice::Mocha | ThemeChoice::System => {
+            catppuccin(catppuccin_egui::MOCHA, egui::Visuals::dark())
+        }
+        ThemeChoice::EguiLight => egui::Style {
+            visuals: egui::Visuals::light(),
+            ..Default::default()
+        },
+        ThemeChoice::EguiDark => egui::Style {
+            visuals: egui::Visuals::dark(),
+            ..Default::default()
+        },
+        ThemeChoice::Custom(path) => load_style(path).unwrap_or_else(|e| {
+            warn!("{e:#}, using egui dark");
+            build_style(&ThemeChoice::EguiDark)
+        }),
+    }
+}
+
+/// JNI-thread side of "System": watches Android's night mode while the
+/// theme choice follows it.
+pub struct NightModeWatcher {
+    night_mode: Option<bool>,
+    last_poll: Option<Instant>,
+    enabled: bool,
+}
+
+impl NightModeWatcher {
+    /// Starts disabled; see [`Self::set_enabled`].
+    pub fn new() -> Self {
+        Self {
+            night_mode: None,
+            last_poll: None,
+            enabled: false,
+        }
+    }
+
+    /// Only poll while the theme choice is [`ThemeChoice::System`]. Turning
+    /// it on re-reads the night mode right away.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.last_poll = None;
+        }
+        self.enabled = enabled;
+    }
+
+    /// Re-read Android's night mode, at most every
+    /// [`NIGHT_MODE_POLL_INTERVAL`] and only while enabled. Returns the new
+    /// value when it changed.
+    pub fn poll<'a>(&mut self, bridge: &JavaBridge<'a>, env: &mut JNIEnv<'a>) -> Option<bool> {
+        if !self.enabled
+            || self
+                .last_poll
+                .is_some_and(|t| t.elapsed() < NIGHT_MODE_POLL_INTERVAL)
+        {
+            return None;
+        }
+        self.last_poll = Some(Instant::now());
+
+        match bridge.is_night_mode(env) {
+            Ok(night_mode) if Some(night_mode) != self.night_mode => {
+                info!(
+                    "System night mode is now {}",
+                    if night_mode { "on" } else { "off" }
+                );
+                self.night_mode = Some(night_mode);
+                Some(night_mode)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Failed to read night mode: {e:#}");
+                None
+            }
+        }
+    }
+}
+
+/// Applies a [`ThemeChoice`] to the egui context, only when the effective
+/// theme actually changes.
+pub struct ThemeManager {
+    applied: Option<ThemeChoice>,
+    night_mode: bool,
+}
+
+impl ThemeManager {
+    /// `night_mode` is what "System" resolves to until
+    /// [`set_night_mode`](Self::set_night_mode) says otherwise; read it before
+    /// the first frame so the wrong flavour never flashes up.
+    pub fn new(night_mode: bool) -> Self {
+        Self {
+            applied: None,
+            night_mode,
+        }
+    }
+
+    /// What "System" resolves to, as reported by [`NightModeWatcher`].
+    pub fn set_night_mode(&mut self, night_mode: bool) {
+        self.night_mode = night_mode;
+    }
+
+    /// Apply `choice` if it resolves to something other than what's shown.
+    /// Cheap to call every frame.
+    pub fn apply(&mut self, ctx: &egui::Context, choice: &ThemeChoice) {
+        let resolved = choice.resolve(self.night_mode);
+        if self.applied.as_ref() == Some(&resolved) {
+            return;
+        }
+
+        let style = build_style(&resolved);
+        ctx.set_theme(if style.visuals.dark_mode {
+            egui::Theme::Dark
+        } else {
+            egui::Theme::Light
+        });
+        ctx.set_style(style);
+        info!("Applied theme {}", resolved.label());
+        self.applied = Some(resolved);
+    }
+
+    /// Force the current theme to be rebuilt, e.g. after editing a custom
+    /// style file.
+    pub fn reload(&mut self) {
+        self.applied = None;
+    }
+
+    /// A combo box listing the built-in themes plus `custom` if given.
+    /// Returns `true` if the choice changed.
+    pub fn picker(ui: &mut egui::Ui, choice: &mut ThemeChoice, custom: Option<&Path>) -> bool {
+        let mut options = ThemeChoice::BUILT_IN.to_vec();
+        if let Some(path) = custom {
+            options.push(ThemeChoice::Custom(path.to_owned()));
+        }
+        if let ThemeChoice::Custom(_) = choice
+            && !options.contains(choice)
+        {
+            options.push(choice.clone());
+        }
+
+        let mut changed = false;
+        egui::ComboBox::from_id_salt("theme_picker")
+            .selected_text(choice.label())
+            .show_ui(ui, |ui| {
+                for option in options {
+                    let label = option.label();
+                    changed |= ui.selectable_value(choice, option, label).changed();
+                }
+            });
+        changed
+    }
+}